@@ -0,0 +1,378 @@
+use crate::{
+    economy::{Economy, NEWS_CYCLES_PER_YEAR},
+    entities::{
+        agents::Agents,
+        companies::{Companies, DELISTING_RECOVERY_RATE, FOLLOW_ON_RUNWAY_THRESHOLD},
+    },
+    instrument::InstrumentId,
+    log,
+    logger::Log,
+    max, min,
+    money::Money,
+    trade_house::{Trade, TradeAction},
+    transaction::TodoTransaction,
+    SimulationError, MIN_STRIKE_PRICE,
+};
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+/// Money paid back for every bond at maturity
+pub const BOND_FACE_VALUE: f64 = 1_000.0;
+/// Number of ticks between two coupons, bonds pay a coupon every news cycle
+pub const TICKS_PER_COUPON: u64 = 20;
+/// Number of coupons a bond pays until it matures
+pub const BOND_TERM: u64 = 20;
+/// Yearly yield asked on top of the risk-free rate for lending to a healthy company
+pub const BASE_CREDIT_SPREAD: f64 = 0.02;
+/// Yearly yield added for every news cycle the company has spent in distress
+pub const DISTRESS_CREDIT_SPREAD: f64 = 0.05;
+/// Chance of a company borrowing up to its debt capacity every news cycle,
+/// the companies short of cash always do
+pub const BOND_ISSUANCE_PROBABILITY: f64 = 0.05;
+/// Most a company owes its bondholders, in news cycles of its expected profit
+pub const MAX_DEBT_TO_PROFIT: f64 = 10.0;
+/// Portion of its balance a buyer puts into a placement
+pub const PLACEMENT_PORTION: f64 = 0.05;
+/// Chance of a value investor trading a bond instead of a company
+pub const BOND_TRADING_PROBABILITY: f64 = 0.05;
+/// Largest gap, as a portion of the fair value, between an offer for a bond and its fair value
+pub const BOND_PRICE_NOISE: f64 = 0.02;
+
+/// Debt of a company, paying a fixed coupon every news cycle and its face value at maturity
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Bond {
+    pub id: u64,
+    /// The issuer, paying the coupons from its balance
+    pub company_id: u64,
+    /// Yearly coupon as a portion of the face value, fixed at issuance
+    pub coupon_rate: f64,
+    /// Tick at which the face value is paid back
+    pub maturity: u64,
+    pub number_outstanding: u64,
+    /// Price of the last trades of the bond, or the price it was placed at
+    pub price: Money,
+    /// Set once the issuer went bankrupt, the bondholders got what could be recovered
+    pub defaulted: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Bonds {
+    pub bonds: Vec<Bond>,
+}
+
+/// Yearly yield lenders ask from the company on top of the risk-free rate
+pub fn credit_spread(companies: &Companies, company_id: u64) -> f64 {
+    let distress_cycles = companies
+        .distress_cycles
+        .get(company_id as usize)
+        .copied()
+        .unwrap_or(0);
+    BASE_CREDIT_SPREAD + DISTRESS_CREDIT_SPREAD * distress_cycles as f64
+}
+
+impl Bond {
+    /// Money paid for every bond each news cycle
+    pub fn coupon(&self) -> f64 {
+        BOND_FACE_VALUE * self.coupon_rate / NEWS_CYCLES_PER_YEAR
+    }
+    /// Still paying coupons, neither repaid nor defaulted
+    pub fn is_active(&self) -> bool {
+        !self.defaulted && self.number_outstanding != 0
+    }
+    /// Number of coupons left to be paid after `current_tick`, the last one paid at maturity
+    pub fn remaining_coupons(&self, current_tick: u64) -> u64 {
+        self.maturity
+            .saturating_sub(current_tick)
+            .div_ceil(TICKS_PER_COUPON)
+    }
+    /// Coupons and face value left, discounted at the risk-free rate and the credit spread of the issuer
+    pub fn fair_value(&self, companies: &Companies, economy: &Economy, current_tick: u64) -> f64 {
+        let number_of_coupons = self.remaining_coupons(current_tick) as i32;
+        let discount_rate = (economy.interest_rate + credit_spread(companies, self.company_id))
+            / NEWS_CYCLES_PER_YEAR;
+        if discount_rate <= 0.0 {
+            return self.coupon() * number_of_coupons as f64 + BOND_FACE_VALUE;
+        }
+        let discount = (1.0 + discount_rate).powi(-number_of_coupons);
+        self.coupon() * (1.0 - discount) / discount_rate + BOND_FACE_VALUE * discount
+    }
+}
+
+impl Bonds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Money the company owes its bondholders at face value
+    pub fn debt(&self, company_id: u64) -> f64 {
+        self.bonds
+            .iter()
+            .filter(|bond| bond.company_id == company_id && bond.is_active())
+            .map(|bond| BOND_FACE_VALUE * bond.number_outstanding as f64)
+            .sum()
+    }
+    /// Ids of the bonds of the company still paying coupons
+    pub fn company_bonds(&self, company_id: u64) -> Vec<u64> {
+        self.bonds
+            .iter()
+            .filter(|bond| bond.company_id == company_id && bond.is_active())
+            .map(|bond| bond.id)
+            .collect()
+    }
+    /// Ids of the bonds to be repaid at `current_tick`
+    pub fn maturing(&self, current_tick: u64) -> Vec<u64> {
+        self.bonds
+            .iter()
+            .filter(|bond| bond.is_active() && bond.maturity <= current_tick)
+            .map(|bond| bond.id)
+            .collect()
+    }
+    /// Places bonds worth up to `amount` directly with the buyers, at face value
+    ///
+    /// Every buyer takes up to `PLACEMENT_PORTION` of its balance,
+    /// the coupon is set to the risk-free rate plus the credit spread of the company.
+    /// Returns the id of the bond, `None` if nobody bought any
+    pub fn issue(
+        &mut self,
+        agents: &mut Agents,
+        companies: &mut Companies,
+        economy: &Economy,
+        company_id: u64,
+        amount: f64,
+        buyer_ids: &[u64],
+    ) -> Option<u64> {
+        let mut number_to_place = (amount / BOND_FACE_VALUE).floor() as u64;
+        let mut bond = Bond {
+            id: self.bonds.len() as u64,
+            company_id,
+            coupon_rate: economy.interest_rate + credit_spread(companies, company_id),
+            maturity: agents.current_tick + BOND_TERM * TICKS_PER_COUPON,
+            number_outstanding: 0,
+            price: Money::from(BOND_FACE_VALUE),
+            defaulted: false,
+        };
+        for agent_id in buyer_ids.iter().copied() {
+            if number_to_place == 0 {
+                break;
+            }
+            let Ok(balance) = agents.balances.get(agent_id) else {
+                continue;
+            };
+            let number_of_bonds = min(
+                number_to_place,
+                (balance.to_f64() * PLACEMENT_PORTION / BOND_FACE_VALUE).floor() as u64,
+            );
+            if number_of_bonds == 0 {
+                continue;
+            }
+            let cost = Money::from(BOND_FACE_VALUE).times(number_of_bonds);
+            if agents.balances.add(agent_id, -cost).is_err() {
+                continue;
+            }
+            agents.receive_shares(
+                agent_id,
+                InstrumentId::Bond(bond.id),
+                number_of_bonds,
+                BOND_FACE_VALUE,
+            );
+            companies.balances[company_id as usize] += cost;
+            bond.number_outstanding += number_of_bonds;
+            number_to_place -= number_of_bonds;
+        }
+        if bond.number_outstanding == 0 {
+            return None;
+        }
+        log!(info "New bond: bond_id: {}, company_id: {}, coupon_rate: {}, maturity: {}, number_outstanding: {}", bond.id, company_id, bond.coupon_rate, bond.maturity, bond.number_outstanding);
+        self.bonds.push(bond);
+        Some(self.bonds.len() as u64 - 1)
+    }
+    /// Companies borrow up to their debt capacity from the buyers now and then,
+    /// and whenever they run short of cash instead of waiting for a follow-on offering
+    ///
+    /// Returns the ids of the new bonds
+    pub fn rand_consider_issuing(
+        &mut self,
+        rng: &mut impl Rng,
+        agents: &mut Agents,
+        companies: &mut Companies,
+        economy: &Economy,
+        buyer_ids: &[u64],
+    ) -> Vec<u64> {
+        let mut bond_ids = Vec::new();
+        for company_id in companies.iter().collect::<Vec<_>>() {
+            let id = company_id as usize;
+            let expected_profit = companies.expected_profits[id];
+            if !companies.is_listed(company_id) || expected_profit <= 0.0 {
+                continue;
+            }
+            let short_of_cash =
+                companies.balances[id].to_f64() / expected_profit < FOLLOW_ON_RUNWAY_THRESHOLD;
+            if !short_of_cash && !rng.gen_bool(BOND_ISSUANCE_PROBABILITY) {
+                continue;
+            }
+            let amount = expected_profit * MAX_DEBT_TO_PROFIT - self.debt(company_id);
+            if amount < BOND_FACE_VALUE {
+                continue;
+            }
+            let mut buyer_ids = buyer_ids.to_vec();
+            buyer_ids.shuffle(rng);
+            bond_ids.extend(self.issue(agents, companies, economy, company_id, amount, &buyer_ids));
+        }
+        bond_ids
+    }
+    /// Pays the coupon of every bond from the balance of its issuer, and the face value of the bonds at maturity
+    ///
+    /// Bonds sitting in sell offers miss the coupon, and the `TradeHouse` needs to return
+    /// the maturing ones to their holders before this.
+    /// Returns the companies which couldn't pay, and are in default
+    pub fn pay_coupons(&mut self, agents: &mut Agents, companies: &mut Companies) -> Vec<u64> {
+        let mut holders = self
+            .bonds
+            .iter()
+            .map(|_| Vec::new())
+            .collect::<Vec<Vec<(u64, u64)>>>();
+        for (agent_id, instrument_id, number_of_bonds) in agents.holdings.iter() {
+            let InstrumentId::Bond(bond_id) = instrument_id else {
+                continue;
+            };
+            // bonds of another registry pay nothing here
+            let Some(bond_holders) = holders.get_mut(bond_id as usize) else {
+                continue;
+            };
+            if number_of_bonds != 0 {
+                bond_holders.push((agent_id, number_of_bonds));
+            }
+        }
+        let mut defaulted_companies = Vec::new();
+        for (bond, holders) in self.bonds.iter_mut().zip(holders) {
+            if !bond.is_active() || defaulted_companies.contains(&bond.company_id) {
+                continue;
+            }
+            let matures = bond.maturity <= agents.current_tick;
+            let payment = Money::from(bond.coupon() + if matures { BOND_FACE_VALUE } else { 0.0 });
+            let number_held = holders.iter().map(|(_, n)| n).sum::<u64>();
+            let due = payment.times(number_held);
+            let balance = &mut companies.balances[bond.company_id as usize];
+            if *balance < due {
+                log!(info "Bond default: bond_id: {}, company_id: {}, due: {}, balance: {}", bond.id, bond.company_id, due, balance);
+                defaulted_companies.push(bond.company_id);
+                continue;
+            }
+            *balance -= due;
+            for (agent_id, number_of_bonds) in holders {
+                let _ = agents
+                    .balances
+                    .add(agent_id, payment.times(number_of_bonds));
+            }
+            if !matures {
+                continue;
+            }
+            for (agent_id, number_of_bonds) in agents
+                .holdings
+                .remove_instrument(InstrumentId::Bond(bond.id))
+            {
+                agents.realize(
+                    agent_id,
+                    InstrumentId::Bond(bond.id),
+                    number_of_bonds,
+                    BOND_FACE_VALUE,
+                );
+                agents.close_position_if_sold(agent_id, InstrumentId::Bond(bond.id));
+            }
+            bond.number_outstanding = 0;
+            log!(info "Bond repaid: bond_id: {}, company_id: {}", bond.id, bond.company_id);
+        }
+        defaulted_companies
+    }
+    /// The bondholders of a bankrupt company take what can be recovered from its balance,
+    /// ahead of the shareholders
+    ///
+    /// The offers for its bonds in the `TradeHouse` need to be cancelled before this
+    pub fn default_company(
+        &mut self,
+        agents: &mut Agents,
+        companies: &mut Companies,
+        company_id: u64,
+    ) -> Result<(), SimulationError> {
+        let bond_ids = self.company_bonds(company_id);
+        let positions = bond_ids
+            .iter()
+            .map(|bond_id| {
+                agents
+                    .holdings
+                    .remove_instrument(InstrumentId::Bond(*bond_id))
+            })
+            .collect::<Vec<_>>();
+        let number_of_bonds = positions
+            .iter()
+            .flatten()
+            .map(|(_, number_of_bonds)| number_of_bonds)
+            .sum::<u64>();
+        let claim = Money::from(BOND_FACE_VALUE).times(number_of_bonds);
+        let balance = &mut companies.balances[company_id as usize];
+        let recovered = min(
+            claim,
+            max(*balance, Money::ZERO).scale(DELISTING_RECOVERY_RATE),
+        );
+        let recovery_per_bond = if number_of_bonds != 0 {
+            // rounded down, so the company never pays out more than it recovers
+            Money(recovered.0 / number_of_bonds as i64)
+        } else {
+            Money::ZERO
+        };
+        // the company pays out exactly what the bondholders get
+        *balance -= recovery_per_bond.times(number_of_bonds);
+        for (bond_id, positions) in bond_ids.into_iter().zip(positions) {
+            for (agent_id, number_of_bonds) in positions {
+                let instrument_id = InstrumentId::Bond(bond_id);
+                agents.realize(agent_id, instrument_id, number_of_bonds, recovery_per_bond);
+                agents.close_position_if_sold(agent_id, instrument_id);
+                agents
+                    .balances
+                    .add(agent_id, recovery_per_bond.times(number_of_bonds))?;
+            }
+            let bond = &mut self.bonds[bond_id as usize];
+            bond.defaulted = true;
+            bond.price = recovery_per_bond;
+        }
+        log!(info "Bonds defaulted: company_id: {}, claim: {}, recovered: {}", company_id, claim, recovered);
+        Ok(())
+    }
+    /// An order of a value investor for a random bond, worth up to `budget`
+    ///
+    /// Buys under the fair value and sells its bonds over it
+    pub fn rand_value_order(
+        &self,
+        rng: &mut impl Rng,
+        agents: &Agents,
+        companies: &Companies,
+        economy: &Economy,
+        agent_id: u64,
+        budget: f64,
+    ) -> Option<TodoTransaction> {
+        let bond = self
+            .bonds
+            .iter()
+            .filter(|bond| bond.is_active())
+            .collect::<Vec<_>>()
+            .choose(rng)
+            .copied()?;
+        let fair_value = bond.fair_value(companies, economy, agents.current_tick);
+        let strike_price = max(
+            MIN_STRIKE_PRICE,
+            fair_value * (1.0 + rng.gen_range(-BOND_PRICE_NOISE..BOND_PRICE_NOISE)),
+        );
+        let number_of_bonds_held = agents.holdings.get(agent_id, InstrumentId::Bond(bond.id));
+        let (action, number_of_bonds) = if bond.price.to_f64() > fair_value {
+            (TradeAction::Sell, number_of_bonds_held)
+        } else {
+            (TradeAction::Buy, (budget / strike_price).floor() as u64)
+        };
+        (number_of_bonds != 0).then_some(TodoTransaction {
+            agent_id,
+            instrument_id: InstrumentId::Bond(bond.id),
+            strike_price: Money::from(strike_price),
+            action,
+            trade: Trade::new(number_of_bonds),
+        })
+    }
+}
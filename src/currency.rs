@@ -0,0 +1,244 @@
+use crate::{
+    entities::{agents::Agents, companies::Companies},
+    instrument::InstrumentId,
+    log,
+    logger::Log,
+    market::Market,
+    money::Money,
+    strategy::AgentStrategy,
+    trade_house::{Trade, TradeAction},
+    transaction::TodoTransaction,
+    SimulationError,
+};
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+use std::cmp::min;
+
+/// Currency of the balances, the prices of the companies and the primary exchange
+pub const HOME_CURRENCY: u64 = 0;
+/// Standard deviation of the log change of the fundamental rates every news cycle
+pub const FX_VOLATILITY: f64 = 0.005;
+/// Gap between the quotes of a dealer and its mid, as a portion of it
+pub const FX_SPREAD: f64 = 0.002;
+/// Portion of the gap between the rate and the fundamental rate a dealer closes with its quotes
+pub const FX_REVERSION: f64 = 0.1;
+/// Portion of the mid a dealer moves its quotes by per `FX_DEALER_CAPITAL` it holds over or under
+pub const FX_INVENTORY_SKEW: f64 = 0.02;
+/// Portion of its cash and of its currency a dealer quotes at once
+pub const FX_QUOTE_PORTION: f64 = 0.1;
+/// Money a dealer starts with in every currency, valued in the home currency
+pub const FX_DEALER_CAPITAL: f64 = 10_000_000.0;
+
+/// The currencies and the rates between them and the home currency
+///
+/// A currency is traded as `InstrumentId::Fx`, its price being the amount of the home currency
+/// paid for a unit of it. The dealers quote every currency in the book of the primary exchange
+/// and the agents convert by taking their quotes, so the rates move with the flow
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Currencies {
+    pub names: Vec<String>,
+    /// Price of the last trades of every currency
+    pub rates: Vec<f64>,
+    /// Rate the dealers pull their quotes toward, wandering every news cycle
+    pub fundamentals: Vec<f64>,
+    /// Agent ids of the dealers, quoting every currency against the home currency
+    pub dealers: Vec<u64>,
+}
+
+impl Currencies {
+    /// A currency for every `(name, rate)`, the first one being the home currency
+    pub fn new(currencies: &[(&str, f64)]) -> Self {
+        Self {
+            names: currencies
+                .iter()
+                .map(|(name, _)| name.to_string())
+                .collect(),
+            rates: currencies.iter().map(|(_, rate)| *rate).collect(),
+            fundamentals: currencies.iter().map(|(_, rate)| *rate).collect(),
+            dealers: Vec::new(),
+        }
+    }
+    /// Home currency paid for a unit of the currency, 1 for the home currency itself
+    pub fn rate(&self, currency: u64) -> f64 {
+        if currency == HOME_CURRENCY {
+            return 1.0;
+        }
+        self.rates.get(currency as usize).copied().unwrap_or(1.0)
+    }
+    pub fn to_home(&self, amount: f64, currency: u64) -> f64 {
+        amount * self.rate(currency)
+    }
+    pub fn from_home(&self, amount: f64, currency: u64) -> f64 {
+        amount / self.rate(currency)
+    }
+    /// Creates a dealer as a new agent holding `FX_DEALER_CAPITAL` in every currency, returns its agent id
+    pub fn found_dealer(&mut self, agents: &mut Agents) -> u64 {
+        let agent_ids = agents.create_agents(1, &mut vec![FX_DEALER_CAPITAL]);
+        agents.assign_strategies(|_| AgentStrategy::FxDealer, &agent_ids);
+        for currency in 1..self.rates.len() as u64 {
+            let foreign_capital = self.from_home(FX_DEALER_CAPITAL, currency);
+            if let Err(e) =
+                agents
+                    .balances
+                    .add_in(agent_ids[0], currency, Money::from(foreign_capital))
+            {
+                log!(warn "Couldn't fund the dealer: {:?}", e);
+            }
+        }
+        self.dealers.push(agent_ids[0]);
+        agent_ids[0]
+    }
+    /// Moves the fundamental rates of the foreign currencies by a news cycle
+    pub fn rand_drift(&mut self, rng: &mut impl Rng) {
+        let Ok(noise) = Normal::new(0.0, FX_VOLATILITY) else {
+            return;
+        };
+        for fundamental in self.fundamentals.iter_mut().skip(1) {
+            *fundamental *= f64::exp(noise.sample(rng));
+        }
+    }
+    /// Replaces the quotes of every dealer in the book of the market
+    ///
+    /// A dealer quotes around the rate pulled toward the fundamental rate, lower when it holds more
+    /// of the currency than `FX_DEALER_CAPITAL` is worth and higher when it holds less
+    pub fn quote(&self, agents: &mut Agents, market: &mut Market, companies: &mut Companies) {
+        for dealer_id in self.dealers.iter().copied() {
+            for currency in 1..self.rates.len() as u64 {
+                if let Err(e) =
+                    market.cancel_agent_offers(InstrumentId::Fx(currency), dealer_id, agents)
+                {
+                    log!(warn "Couldn't cancel the quotes of dealer {}: {:?}", dealer_id, e);
+                }
+            }
+            for currency in 1..self.rates.len() as u64 {
+                let (Ok(cash), Ok(inventory)) = (
+                    agents.balances.get(dealer_id),
+                    agents.balances.get_in(dealer_id, currency),
+                ) else {
+                    continue;
+                };
+                let rate = self.rate(currency);
+                let fundamental = self
+                    .fundamentals
+                    .get(currency as usize)
+                    .copied()
+                    .unwrap_or(rate);
+                let imbalance = (self.to_home(inventory.to_f64(), currency) - FX_DEALER_CAPITAL)
+                    / FX_DEALER_CAPITAL;
+                let mid = (rate + FX_REVERSION * (fundamental - rate))
+                    * (1.0 - FX_INVENTORY_SKEW * imbalance);
+                let bid = Money::from(mid * (1.0 - FX_SPREAD));
+                let ask = Money::from(mid * (1.0 + FX_SPREAD));
+                if bid <= Money::ZERO {
+                    continue;
+                }
+                let quotes = [
+                    (
+                        TradeAction::Buy,
+                        bid,
+                        u64::try_from(cash.scale(FX_QUOTE_PORTION).0 / bid.0).unwrap_or(0),
+                    ),
+                    (
+                        TradeAction::Sell,
+                        ask,
+                        inventory.scale(FX_QUOTE_PORTION).whole_units(),
+                    ),
+                ];
+                for (action, strike_price, number) in quotes {
+                    if number == 0 {
+                        continue;
+                    }
+                    let quote = TodoTransaction {
+                        agent_id: dealer_id,
+                        instrument_id: InstrumentId::Fx(currency),
+                        strike_price,
+                        action,
+                        trade: Trade::new(number),
+                    };
+                    if let Err(e) = market.trade(false, &quote, agents, companies, 0.0) {
+                        log!(warn "Dealer {} couldn't quote: {:?}", dealer_id, e);
+                    }
+                }
+            }
+        }
+    }
+    /// Trades `amount` of the currency for the agent against the quotes in the book of the market,
+    /// the best ones first
+    pub fn convert(
+        agents: &mut Agents,
+        market: &mut Market,
+        companies: &mut Companies,
+        agent_id: u64,
+        currency: u64,
+        action: TradeAction,
+        amount: u64,
+    ) -> Result<(), SimulationError> {
+        let instrument_id = InstrumentId::Fx(currency);
+        let mut left = amount;
+        while left > 0 {
+            let best_price = match action {
+                TradeAction::Buy => market.house.best_ask(instrument_id),
+                TradeAction::Sell => market.house.best_bid(instrument_id),
+            };
+            let Some(strike_price) = best_price else {
+                return Err(SimulationError::UnDoable);
+            };
+            let offers = market.house.get_mut_trade_offers(instrument_id);
+            let quotes = match action {
+                TradeAction::Buy => &offers.seller_offers,
+                TradeAction::Sell => &offers.buyer_offers,
+            };
+            // the book fills an order from the first quote at its price, so no more is asked for
+            let Some(quote) = quotes
+                .iter()
+                .find(|quote| quote.strike_price == strike_price)
+            else {
+                return Err(SimulationError::UnDoable);
+            };
+            let number = min(left, quote.data.number_of_shares);
+            if number == 0 {
+                return Err(SimulationError::UnDoable);
+            }
+            let order = TodoTransaction {
+                agent_id,
+                instrument_id,
+                strike_price,
+                action,
+                trade: Trade::new(number),
+            };
+            market.trade(false, &order, agents, companies, 0.0)?;
+            left -= number;
+        }
+        Ok(())
+    }
+    /// Sells the foreign cash of the agents back for the home currency,
+    /// the dealers and the arbitrageurs keeping theirs to trade with
+    pub fn repatriate(&self, agents: &mut Agents, market: &mut Market, companies: &mut Companies) {
+        let mut foreign = agents
+            .balances
+            .foreign()
+            .filter(|(agent_id, _, balance)| {
+                *balance >= Money::UNIT
+                    && !matches!(
+                        agents.strategies[*agent_id as usize],
+                        AgentStrategy::FxDealer | AgentStrategy::Arbitrageur
+                    )
+            })
+            .collect::<Vec<_>>();
+        foreign.sort_by_key(|(agent_id, currency, _)| (*agent_id, *currency));
+        for (agent_id, currency, balance) in foreign {
+            if let Err(e) = Self::convert(
+                agents,
+                market,
+                companies,
+                agent_id,
+                currency,
+                TradeAction::Sell,
+                balance.whole_units(),
+            ) {
+                log!(warn "Couldn't bring the cash of agent {} home: {:?}", agent_id, e);
+            }
+        }
+    }
+}
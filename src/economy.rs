@@ -0,0 +1,124 @@
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+
+/// Number of news cycles which make up a year, interest and inflation are yearly rates
+pub const NEWS_CYCLES_PER_YEAR: f64 = 4.0;
+/// Chance of an expansion turning into a recession every news cycle
+pub const EXPANSION_TO_RECESSION_PROBABILITY: f64 = 0.05;
+/// Chance of a recession ending every news cycle
+pub const RECESSION_TO_EXPANSION_PROBABILITY: f64 = 0.25;
+/// Portion of the gap to their target the interest rate and inflation close every news cycle
+pub const MACRO_REVERSION: f64 = 0.2;
+/// How much every point of interest rate discourages agents from spending
+pub const SPENDING_INTEREST_RATE_SENSITIVITY: f64 = 5.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Regime {
+    Expansion,
+    Recession,
+}
+
+/// The state of the economy every company and agent lives in
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Economy {
+    pub regime: Regime,
+    /// Yearly risk-free rate earned by cash balances
+    pub interest_rate: f64,
+    /// Yearly inflation, grows the expected profits of the companies
+    pub inflation: f64,
+    /// Rate set by the central bank, when `None` the interest rate follows the regime
+    pub target_interest_rate: Option<f64>,
+}
+
+impl Default for Economy {
+    fn default() -> Self {
+        Self {
+            regime: Regime::Expansion,
+            interest_rate: Regime::Expansion.neutral_interest_rate(),
+            inflation: Regime::Expansion.neutral_inflation(),
+            target_interest_rate: None,
+        }
+    }
+}
+
+impl Regime {
+    pub fn neutral_interest_rate(&self) -> f64 {
+        match self {
+            Regime::Expansion => 0.04,
+            Regime::Recession => 0.01,
+        }
+    }
+    pub fn neutral_inflation(&self) -> f64 {
+        match self {
+            Regime::Expansion => 0.025,
+            Regime::Recession => 0.005,
+        }
+    }
+    /// Shift of the profits of every company, as a portion of their expected profit
+    pub fn profit_drift(&self) -> f64 {
+        match self {
+            Regime::Expansion => 0.02,
+            Regime::Recession => -0.05,
+        }
+    }
+    pub fn spending_multiplier(&self) -> f64 {
+        match self {
+            Regime::Expansion => 1.0,
+            Regime::Recession => 0.6,
+        }
+    }
+}
+
+impl Economy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Moves the economy forward by a news cycle
+    pub fn rand_tick(&mut self, rng: &mut impl Rng) {
+        let switch_probability = match self.regime {
+            Regime::Expansion => EXPANSION_TO_RECESSION_PROBABILITY,
+            Regime::Recession => RECESSION_TO_EXPANSION_PROBABILITY,
+        };
+        if rng.gen_bool(switch_probability) {
+            self.regime = match self.regime {
+                Regime::Expansion => Regime::Recession,
+                Regime::Recession => Regime::Expansion,
+            };
+        }
+
+        let Ok(noise) = Normal::new(0.0, 0.002) else {
+            return;
+        };
+        let target_interest_rate = self
+            .target_interest_rate
+            .unwrap_or(self.regime.neutral_interest_rate());
+        self.interest_rate +=
+            MACRO_REVERSION * (target_interest_rate - self.interest_rate) + noise.sample(rng);
+        self.interest_rate = self.interest_rate.max(0.0);
+        self.inflation += MACRO_REVERSION * (self.regime.neutral_inflation() - self.inflation)
+            + noise.sample(rng);
+    }
+    /// Sets the rate straight away, and keeps it there until `release_interest_rate`
+    pub fn set_interest_rate(&mut self, interest_rate: f64) {
+        self.interest_rate = interest_rate;
+        self.target_interest_rate = Some(interest_rate);
+    }
+    pub fn release_interest_rate(&mut self) {
+        self.target_interest_rate = None;
+    }
+    pub fn interest_per_cycle(&self) -> f64 {
+        self.interest_rate / NEWS_CYCLES_PER_YEAR
+    }
+    pub fn inflation_per_cycle(&self) -> f64 {
+        self.inflation / NEWS_CYCLES_PER_YEAR
+    }
+    pub fn profit_drift(&self) -> f64 {
+        self.regime.profit_drift()
+    }
+    /// Agents spend less in a recession and when cash pays well
+    pub fn spending_multiplier(&self) -> f64 {
+        self.regime.spending_multiplier()
+            / (1.0 + SPENDING_INTEREST_RATE_SENSITIVITY * self.interest_rate)
+    }
+}
@@ -1,8 +1,17 @@
 use crate::{
+    currency::{Currencies, HOME_CURRENCY},
     entities::{companies::Companies, Balances},
-    trade_house::{FailedOffer, StockOption, Trade, TradeAction},
+    instrument::InstrumentId,
+    log,
+    logger::Log,
+    max, min,
+    money::{Money, MINOR_UNITS},
+    strategy::{AgentStrategy, Learner},
+    trade_house::{FailedOffer, Trade, TradeAction},
     transaction::{TodoTransaction, Transaction},
-    SimulationError, NUM_OF_AGENTS, TIMELINE_SIZE_LIMIT,
+    wealth::WealthDistribution,
+    SimulationError, LEARNING_AGENT_RATIO, MIN_STRIKE_PRICE, NUM_OF_AGENTS, TIMELINE_SIZE_LIMIT,
+    VALUE_INVESTOR_RATIO,
 };
 use rand::Rng;
 use serde::{Deserialize, Serialize};
@@ -20,12 +29,47 @@ fn get_second(a: u128) -> u64 {
     (a & 0xFFFFFFFFFFFFFFFF) as u64
 }
 
+/// Key of what an agent holds of an instrument
+fn key(agent_id: u64, instrument_id: InstrumentId) -> u128 {
+    combine(agent_id, instrument_id.encode())
+}
+
+/// `None` for a key which wasn't made with `key`
+fn instrument(key: u128) -> Option<InstrumentId> {
+    InstrumentId::decode(get_second(key))
+}
+
+/// What an agent holds, keyed by the encoded `InstrumentId`
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct AgentHoldings(pub HashMap<u64, u64>);
 
 #[derive(Debug, Clone, Default)]
 pub struct Holdings(HashMap<u128, u64>);
 
+/// When and at what price an agent got into a company
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub entry_tick: u64,
+    /// Average price paid per share
+    pub cost_basis: Money,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Positions(HashMap<u128, Position>);
+
+/// Longest number of ticks an agent plans to hold a company for
+pub const MAX_INVESTMENT_HORIZON: u64 = 2_000;
+/// Loss, as a portion of the cost basis, at which an agent sells before its horizon
+pub const STOP_LOSS: f64 = 0.2;
+/// Money every agent spends to live every news cycle
+pub const SUBSISTENCE_COST: Money = Money(500 * MINOR_UNITS);
+/// Portion of its balance an agent spends on top of `SUBSISTENCE_COST` every news cycle
+pub const CONSUMPTION_RATE: f64 = 0.01;
+/// Balance under which an agent is broke and leaves the market
+pub const BROKE_BALANCE_THRESHOLD: Money = Money(100 * MINOR_UNITS);
+/// Discount on the current price at which the holdings of a leaving agent are offered
+pub const LIQUIDATION_DISCOUNT: f64 = 0.1;
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Timeline {
     pub data: Vec<(u64, TradeAction)>,
@@ -42,94 +86,120 @@ pub struct Preferences(pub Vec<Timeline>);
 pub struct Agents {
     pub num_of_agents: u64,
     pub holdings: Holdings,
+    /// Shares taken out of the holdings for the sell offers waiting in the book
+    pub escrow: Holdings,
     pub balances: Balances,
     pub preferences: Preferences,
-    pub try_offers: HashMap<u128, f64>,
+    /// Price every agent retries its failed offers at, negative for the sell offers
+    pub try_offers: HashMap<u128, Money>,
+    pub strategies: Vec<AgentStrategy>,
+    /// What the learning agents have learned, `None` for the other agents
+    pub learners: Vec<Option<Learner>>,
+    pub positions: Positions,
+    /// Number of ticks every agent plans to hold a company for
+    pub horizons: Vec<u64>,
+    /// Number of ticks each closed position was held for
+    pub closed_holding_periods: Vec<u64>,
+    pub current_tick: u64,
+    /// Profit (or loss) locked in by selling, only counts shares with a known cost basis
+    pub realized_pnl: Vec<Money>,
+    /// Money every agent has ever put into shares
+    pub invested: Vec<Money>,
+    /// Salary every agent earns every news cycle
+    pub incomes: Vec<Money>,
+    /// Agents which went broke have left the market and only sell what they have left
+    pub active: Vec<bool>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Agent {
     pub id: u64,
-    pub balance: f64,
+    pub balance: Money,
+    /// Cash held in the other currencies, keyed by currency
+    pub foreign_balances: HashMap<u64, Money>,
     pub holding: AgentHoldings,
     pub preferences: AgentPreferences,
+    pub strategy: AgentStrategy,
+    pub learner: Option<Learner>,
+    /// Keyed by the encoded `InstrumentId`
+    pub positions: HashMap<u64, Position>,
+    pub horizon: u64,
+    pub realized_pnl: Money,
+    pub invested: Money,
+    pub income: Money,
+    pub active: bool,
 }
 
 impl Agent {
     pub fn new(
         id: u64,
-        balance: f64,
+        balance: impl Into<Money>,
         holdings: &[(u64, u64)],
         preferences: &[(u64, (u64, TradeAction))],
     ) -> Self {
         Self {
             id,
-            balance,
+            balance: balance.into(),
+            foreign_balances: HashMap::new(),
             holding: AgentHoldings(holdings.iter().cloned().collect()),
             preferences: AgentPreferences(Timeline {
                 data: preferences.iter().map(|(_, a)| *a).collect(),
                 target_index: 0,
             }),
+            strategy: AgentStrategy::default(),
+            learner: None,
+            positions: HashMap::new(),
+            horizon: 0,
+            realized_pnl: Money::ZERO,
+            invested: Money::ZERO,
+            income: Money::ZERO,
+            active: true,
         }
     }
 }
 
 impl Holdings {
-    pub fn insert(&mut self, agent_id: u64, company_id: u64, number_of_shares: u64) {
+    pub fn insert(&mut self, agent_id: u64, instrument_id: InstrumentId, number_of_shares: u64) {
         self.0
-            .insert(combine(agent_id, company_id), number_of_shares);
+            .insert(key(agent_id, instrument_id), number_of_shares);
     }
-    pub fn get(&self, agent_id: u64, company_id: u64) -> u64 {
+    pub fn get(&self, agent_id: u64, instrument_id: InstrumentId) -> u64 {
         self.0
-            .get(&combine(agent_id, company_id)).copied()
+            .get(&key(agent_id, instrument_id))
+            .copied()
             .unwrap_or(0)
     }
     pub fn get_u128(&self, id: u128) -> u64 {
         self.0.get(&id).copied().unwrap_or(0)
     }
     pub fn push_from_txn(&mut self, target_agent_id: u64, transaction: &Transaction) {
-        self.0
-            .get_mut(&combine(target_agent_id, transaction.company_id))
-            .map(|share_count| *share_count += transaction.number_of_shares)
-            .unwrap_or_else(|| {
-                self.0.insert(
-                    combine(target_agent_id, transaction.company_id),
-                    transaction.number_of_shares,
-                );
-            });
+        self.push(
+            target_agent_id,
+            transaction.instrument_id,
+            transaction.number_of_shares,
+        );
     }
     pub fn pop_from_txn(
         &mut self,
         target_agent_id: u64,
         transaction: &Transaction,
     ) -> Result<(), SimulationError> {
-        let Some(share_count) = self
-            .0
-            .get_mut(&combine(target_agent_id, transaction.company_id))
-        else {
-            return Err(SimulationError::Unspendable);
-        };
-        if *share_count < transaction.number_of_shares {
-            return Err(SimulationError::Unspendable);
-        }
-        *share_count -= transaction.number_of_shares;
-        Ok(())
+        self.pop(
+            target_agent_id,
+            transaction.instrument_id,
+            transaction.number_of_shares,
+        )
     }
-    pub fn push(&mut self, agent_id: u64, company_id: u64, number_of_shares: u64) {
-        let Some(share_count) = self.0.get_mut(&combine(agent_id, company_id)) else {
-            self.0
-                .insert(combine(agent_id, company_id), number_of_shares);
-            return;
-        };
-        *share_count += number_of_shares;
+    pub fn push(&mut self, agent_id: u64, instrument_id: InstrumentId, number_of_shares: u64) {
+        *self.0.entry(key(agent_id, instrument_id)).or_default() += number_of_shares;
     }
     pub fn pop(
         &mut self,
         agent_id: u64,
-        company_id: u64,
+        instrument_id: InstrumentId,
         number_of_shares: u64,
     ) -> Result<(), SimulationError> {
-        let Some(share_count) = self.0.get_mut(&combine(agent_id, company_id)) else {
+        let Some(share_count) = self.0.get_mut(&key(agent_id, instrument_id)) else {
             return Err(SimulationError::Unspendable);
         };
         if *share_count < number_of_shares {
@@ -139,6 +209,86 @@ impl Holdings {
         *share_count -= number_of_shares;
         Ok(())
     }
+    /// Takes out as many of the shares as are there, dropping the entry once it is empty
+    pub fn pop_up_to(&mut self, agent_id: u64, instrument_id: InstrumentId, number_of_shares: u64) {
+        let id = key(agent_id, instrument_id);
+        let Some(share_count) = self.0.get_mut(&id) else {
+            return;
+        };
+        *share_count = share_count.saturating_sub(number_of_shares);
+        if *share_count == 0 {
+            self.0.remove(&id);
+        }
+    }
+    /// Removes every position in the company, returns the `(agent_id, number_of_shares)` removed
+    pub fn remove_company(&mut self, company_id: u64) -> Vec<(u64, u64)> {
+        self.remove_instrument(InstrumentId::Equity(company_id))
+    }
+    /// Removes every position in the instrument, returns the `(agent_id, number_of_shares)` removed
+    pub fn remove_instrument(&mut self, instrument_id: InstrumentId) -> Vec<(u64, u64)> {
+        let mut positions = self
+            .0
+            .iter()
+            .filter(|(id, _)| instrument(**id) == Some(instrument_id))
+            .map(|(id, number_of_shares)| (get_first(*id), *number_of_shares))
+            .collect::<Vec<_>>();
+        positions.sort();
+        for (agent_id, _) in positions.iter() {
+            self.0.remove(&key(*agent_id, instrument_id));
+        }
+        positions
+    }
+    /// Every `(agent_id, instrument_id, number_of_shares)` held
+    pub fn iter(&self) -> impl Iterator<Item = (u64, InstrumentId, u64)> + '_ {
+        self.0.iter().filter_map(|(id, number_of_shares)| {
+            Some((get_first(*id), instrument(*id)?, *number_of_shares))
+        })
+    }
+}
+
+impl Positions {
+    pub fn get(&self, agent_id: u64, instrument_id: InstrumentId) -> Option<Position> {
+        self.0.get(&key(agent_id, instrument_id)).copied()
+    }
+    pub fn insert(&mut self, agent_id: u64, instrument_id: InstrumentId, position: Position) {
+        self.0.insert(key(agent_id, instrument_id), position);
+    }
+    /// Averages the price of the new shares into the position, opening it if needed
+    pub fn add_shares(
+        &mut self,
+        agent_id: u64,
+        instrument_id: InstrumentId,
+        current_tick: u64,
+        (number_of_shares_held, number_of_shares): (u64, u64),
+        strike_price: Money,
+    ) {
+        let position = self
+            .0
+            .entry(key(agent_id, instrument_id))
+            .or_insert(Position {
+                entry_tick: current_tick,
+                cost_basis: strike_price,
+            });
+        let total_shares = number_of_shares_held + number_of_shares;
+        if total_shares == 0 {
+            return;
+        }
+        let cost =
+            position.cost_basis.times(number_of_shares_held) + strike_price.times(number_of_shares);
+        position.cost_basis = Money(cost.0 / total_shares as i64);
+    }
+    pub fn remove(&mut self, agent_id: u64, instrument_id: InstrumentId) -> Option<Position> {
+        self.0.remove(&key(agent_id, instrument_id))
+    }
+    pub fn remove_company(&mut self, company_id: u64) {
+        self.0
+            .retain(|id, _| instrument(*id) != Some(InstrumentId::Equity(company_id)));
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (u64, InstrumentId, &Position)> {
+        self.0
+            .iter()
+            .filter_map(|(id, position)| Some((get_first(*id), instrument(*id)?, position)))
+    }
 }
 
 impl Timeline {
@@ -190,6 +340,13 @@ impl Timeline {
         }
         Ok(self.data[self.data.len() - (index - self.target_index) - 1])
     }
+    pub fn remove_company(&mut self, company_id: u64) {
+        // put the timeline back in order, so that appending continues to work
+        let target_index = min(self.target_index, self.data.len());
+        self.data.rotate_left(target_index);
+        self.data.retain(|(id, _)| *id != company_id);
+        self.target_index = 0;
+    }
 }
 
 impl Preferences {
@@ -227,6 +384,11 @@ impl Preferences {
         };
         agent.get_rng(rng)
     }
+    pub fn remove_company(&mut self, company_id: u64) {
+        for timeline in self.0.iter_mut() {
+            timeline.remove_company(company_id);
+        }
+    }
 }
 
 impl Agents {
@@ -235,22 +397,69 @@ impl Agents {
     }
     pub fn load(agents: &[Agent]) -> Self {
         let num_of_agents = agents.len() as u64;
-        let mut balances = Vec::with_capacity(agents.len());
+        let mut home_balances = Vec::with_capacity(agents.len());
+        let mut foreign_balances = Vec::new();
         let mut holdings = Holdings::default();
         let mut preferences = Vec::with_capacity(agents.len());
+        let mut strategies = Vec::with_capacity(agents.len());
+        let mut learners = Vec::with_capacity(agents.len());
+        let mut positions = Positions::default();
+        let mut horizons = Vec::with_capacity(agents.len());
+        let mut realized_pnl = Vec::with_capacity(agents.len());
+        let mut invested = Vec::with_capacity(agents.len());
+        let mut incomes = Vec::with_capacity(agents.len());
+        let mut active = Vec::with_capacity(agents.len());
         for agent in agents.iter() {
-            balances.push(agent.balance);
-            for (company_id, holding) in agent.holding.0.iter() {
-                holdings.insert(agent.id, *company_id, *holding);
+            home_balances.push(agent.balance);
+            for (currency, balance) in agent.foreign_balances.iter() {
+                foreign_balances.push((agent.id, *currency, *balance));
+            }
+            strategies.push(agent.strategy);
+            learners.push(agent.learner.clone());
+            horizons.push(agent.horizon);
+            realized_pnl.push(agent.realized_pnl);
+            invested.push(agent.invested);
+            incomes.push(agent.income);
+            active.push(agent.active);
+            for (encoded, position) in agent.positions.iter() {
+                let Some(instrument_id) = InstrumentId::decode(*encoded) else {
+                    log!(warn "Unknown instrument {} in the positions of agent {}", encoded, agent.id);
+                    continue;
+                };
+                positions.insert(agent.id, instrument_id, *position);
+            }
+            for (encoded, holding) in agent.holding.0.iter() {
+                let Some(instrument_id) = InstrumentId::decode(*encoded) else {
+                    log!(warn "Unknown instrument {} in the holdings of agent {}", encoded, agent.id);
+                    continue;
+                };
+                holdings.insert(agent.id, instrument_id, *holding);
             }
             preferences.push(agent.preferences.0.clone());
         }
+        let mut balances = Balances::new(home_balances);
+        for (agent_id, currency, balance) in foreign_balances {
+            if let Err(e) = balances.add_in(agent_id, currency, balance) {
+                log!(warn "Failed to load the cash of agent {} in currency {}\n{:?}", agent_id, currency, e);
+            }
+        }
         Self {
             num_of_agents,
-            balances: Balances(balances),
+            balances,
             holdings,
+            escrow: Holdings::default(),
             preferences: Preferences(preferences),
             try_offers: HashMap::new(),
+            strategies,
+            learners,
+            positions,
+            horizons,
+            closed_holding_periods: Vec::new(),
+            current_tick: 0,
+            realized_pnl,
+            invested,
+            incomes,
+            active,
         }
     }
     pub fn save(&self) -> Result<Vec<Agent>, SimulationError> {
@@ -262,6 +471,12 @@ impl Agents {
             agents.push(Agent {
                 id: i,
                 balance: self.balances.get(i)?,
+                foreign_balances: self
+                    .balances
+                    .foreign()
+                    .filter(|(agent_id, _, _)| *agent_id == i)
+                    .map(|(_, currency, balance)| (currency, balance))
+                    .collect(),
                 preferences: AgentPreferences(preference_data.clone()),
                 holding: AgentHoldings(
                     self.holdings
@@ -271,6 +486,19 @@ impl Agents {
                         .map(|(key, value)| (get_second(*key), *value))
                         .collect(),
                 ),
+                strategy: self.strategies[i as usize],
+                learner: self.learners[i as usize].clone(),
+                positions: self
+                    .positions
+                    .iter()
+                    .filter(|(agent_id, _, _)| *agent_id == i)
+                    .map(|(_, instrument_id, position)| (instrument_id.encode(), *position))
+                    .collect(),
+                horizon: self.horizons[i as usize],
+                realized_pnl: self.realized_pnl[i as usize],
+                invested: self.invested[i as usize],
+                income: self.incomes[i as usize],
+                active: self.active[i as usize],
             });
         }
         Ok(agents)
@@ -327,11 +555,34 @@ impl Agents {
         }
         Ok(())
     }
+    pub fn rand_give_preferences_for_company(
+        &mut self,
+        rng: &mut impl Rng,
+        company_id: u64,
+        max_preference: usize,
+    ) {
+        let preference = move |_: u64| rng.gen_range(0..=max_preference);
+        self.give_preferences_for_company(preference, company_id);
+    }
+    pub fn give_preferences_for_company<F>(&mut self, mut preferences: F, company_id: u64)
+    where
+        F: FnMut(u64) -> usize,
+    {
+        for (agent_id, timeline) in self.preferences.0.iter_mut().enumerate() {
+            timeline.add(&vec![
+                (company_id, TradeAction::Buy);
+                preferences(agent_id as u64)
+            ]);
+        }
+    }
     pub fn rand_give_preferences_from_news(
         &mut self,
         rng: &mut impl Rng,
         news_dependent_company_id_probability_distribution: &[(u64, TradeAction)],
     ) {
+        if news_dependent_company_id_probability_distribution.is_empty() {
+            return;
+        }
         for agent_id in 0..self.num_of_agents {
             let (company_id, action) = news_dependent_company_id_probability_distribution
                 [rng.gen_range(0..news_dependent_company_id_probability_distribution.len())];
@@ -342,6 +593,7 @@ impl Agents {
         &mut self,
         mut rng: impl Rng + Clone,
         mut rng2: impl Rng,
+        balance_distribution: &WealthDistribution,
         num_of_agents_to_introduce: u64,
         num_of_companies: u64,
     ) -> Result<(), SimulationError> {
@@ -349,7 +601,7 @@ impl Agents {
         self.introduce_new_agents(
             preference,
             &mut (0..num_of_agents_to_introduce)
-                .map(|_| rng2.gen_range(1000.0..1_000_000.0))
+                .map(|_| balance_distribution.sample(&mut rng2))
                 .collect(),
             num_of_agents_to_introduce,
             num_of_companies,
@@ -368,29 +620,315 @@ impl Agents {
         if new_balances.len() != num_of_agents_to_introduce as usize {
             return Err(SimulationError::NoData);
         }
-        for i in self.num_of_agents..(self.num_of_agents + num_of_agents_to_introduce) {
+        // the agents need to exist before they are given preferences
+        for i in self.create_agents(num_of_agents_to_introduce, new_balances) {
             let mut pref_clone = preferences.clone();
             let agent_preferences = move |company_id: u64| pref_clone(i, company_id);
             self.set_preferences_for_all_companies(agent_preferences, i, num_of_companies)?;
         }
-        let _ = self.create_agents(num_of_agents_to_introduce, new_balances);
         Ok(())
     }
     pub fn create_agents(&mut self, num_of_agents: u64, new_balances: &mut Vec<f64>) -> Vec<u64> {
-        self.balances.0.append(new_balances);
+        self.balances
+            .home
+            .extend(new_balances.drain(..).map(Money::from));
         self.preferences
             .0
             .extend((0..num_of_agents).map(|_| Timeline::new()));
+        self.strategies
+            .extend((0..num_of_agents).map(|_| AgentStrategy::default()));
+        self.learners.extend((0..num_of_agents).map(|_| None));
+        self.horizons.extend((0..num_of_agents).map(|_| 0));
+        self.realized_pnl
+            .extend((0..num_of_agents).map(|_| Money::ZERO));
+        self.invested
+            .extend((0..num_of_agents).map(|_| Money::ZERO));
+        self.incomes.extend((0..num_of_agents).map(|_| Money::ZERO));
+        self.active.extend((0..num_of_agents).map(|_| true));
         self.num_of_agents += num_of_agents;
         ((self.num_of_agents - num_of_agents)..self.num_of_agents).collect()
     }
+    pub fn rand_assign_strategies(&mut self, rng: &mut impl Rng, agent_ids: &[u64]) {
+        let strategy = move |_: u64| {
+            let roll = rng.gen_range(0.0..1.0);
+            if roll < VALUE_INVESTOR_RATIO {
+                AgentStrategy::Value
+            } else if roll < VALUE_INVESTOR_RATIO + LEARNING_AGENT_RATIO {
+                AgentStrategy::Learning
+            } else {
+                AgentStrategy::Sentiment
+            }
+        };
+        self.assign_strategies(strategy, agent_ids);
+    }
+    pub fn assign_strategies<F>(&mut self, mut strategy: F, agent_ids: &[u64])
+    where
+        F: FnMut(u64) -> AgentStrategy,
+    {
+        for &agent_id in agent_ids {
+            let Some(agent_strategy) = self.strategies.get_mut(agent_id as usize) else {
+                continue;
+            };
+            *agent_strategy = strategy(agent_id);
+            self.learners[agent_id as usize] = match agent_strategy {
+                AgentStrategy::Learning => Some(Learner::new()),
+                _ => None,
+            };
+        }
+    }
+    pub fn rand_assign_horizons(&mut self, rng: &mut impl Rng, agent_ids: &[u64]) {
+        let horizon = move |_: u64| rng.gen_range(0..=MAX_INVESTMENT_HORIZON);
+        self.assign_horizons(horizon, agent_ids);
+    }
+    pub fn assign_horizons<F>(&mut self, mut horizon: F, agent_ids: &[u64])
+    where
+        F: FnMut(u64) -> u64,
+    {
+        for &agent_id in agent_ids {
+            if let Some(agent_horizon) = self.horizons.get_mut(agent_id as usize) {
+                *agent_horizon = horizon(agent_id);
+            }
+        }
+    }
+    /// An agent sells once its horizon has passed, or early when the stop loss is hit
+    pub fn is_willing_to_sell(&self, agent_id: u64, company_id: u64, current_price: f64) -> bool {
+        let Some(position) = self
+            .positions
+            .get(agent_id, InstrumentId::Equity(company_id))
+        else {
+            return true;
+        };
+        let horizon = self.horizons.get(agent_id as usize).copied().unwrap_or(0);
+        self.current_tick >= position.entry_tick + horizon
+            || Money::from(current_price) <= position.cost_basis.scale(1.0 - STOP_LOSS)
+    }
+    /// Gives the agent shares bought at `strike_price`, keeping track of its position
+    pub fn receive_shares(
+        &mut self,
+        agent_id: u64,
+        instrument_id: InstrumentId,
+        number_of_shares: u64,
+        strike_price: impl Into<Money>,
+    ) {
+        let strike_price = strike_price.into();
+        let number_of_shares_held = self.holdings.get(agent_id, instrument_id);
+        if let Some(invested) = self.invested.get_mut(agent_id as usize) {
+            *invested += strike_price.times(number_of_shares);
+        }
+        self.positions.add_shares(
+            agent_id,
+            instrument_id,
+            self.current_tick,
+            (number_of_shares_held, number_of_shares),
+            strike_price,
+        );
+        self.holdings
+            .push(agent_id, instrument_id, number_of_shares);
+    }
+    /// Locks in the profit of selling shares at `strike_price` against their cost basis
+    pub fn realize(
+        &mut self,
+        agent_id: u64,
+        instrument_id: InstrumentId,
+        number_of_shares: u64,
+        strike_price: impl Into<Money>,
+    ) {
+        let Some(position) = self.positions.get(agent_id, instrument_id) else {
+            return;
+        };
+        if let Some(realized_pnl) = self.realized_pnl.get_mut(agent_id as usize) {
+            *realized_pnl += (strike_price.into() - position.cost_basis).times(number_of_shares);
+        }
+    }
+    /// Profit (or loss) of the shares still held, marked to the current price
+    pub fn unrealized_pnl(&self, agent_id: u64, companies: &Companies) -> Money {
+        self.positions
+            .iter()
+            .filter(|(position_agent_id, _, _)| *position_agent_id == agent_id)
+            .filter_map(|(_, instrument_id, position)| {
+                let current_price = companies.get_current_price(instrument_id.equity()?)?;
+                let number_of_shares = self.holdings.get(agent_id, instrument_id);
+                Some((Money::from(current_price) - position.cost_basis).times(number_of_shares))
+            })
+            .sum()
+    }
+    /// Realized and unrealized profit as a portion of the money invested,
+    /// 0 for an agent which never invested
+    pub fn returns(&self, agent_id: u64, companies: &Companies) -> f64 {
+        let invested = self
+            .invested
+            .get(agent_id as usize)
+            .copied()
+            .unwrap_or_default();
+        if invested == Money::ZERO {
+            return 0.0;
+        }
+        let realized_pnl = self.realized_pnl[agent_id as usize];
+        (realized_pnl + self.unrealized_pnl(agent_id, companies)).to_f64() / invested.to_f64()
+    }
+    /// Returns `(agent_id, returns)` of every agent, best performing first
+    pub fn rank_by_returns(&self, companies: &Companies) -> Vec<(u64, f64)> {
+        let mut ranking = self
+            .iter()
+            .map(|agent_id| (agent_id, self.returns(agent_id, companies)))
+            .collect::<Vec<_>>();
+        ranking.sort_by(|(a_id, a), (b_id, b)| b.total_cmp(a).then(a_id.cmp(b_id)));
+        ranking
+    }
+    /// Closes the position of the agent once it doesn't hold the company anymore,
+    /// shares still offered in the book keep it open
+    pub fn close_position_if_sold(&mut self, agent_id: u64, instrument_id: InstrumentId) {
+        if self.holdings.get(agent_id, instrument_id) != 0
+            || self.escrow.get(agent_id, instrument_id) != 0
+        {
+            return;
+        }
+        if let Some(position) = self.positions.remove(agent_id, instrument_id) {
+            self.closed_holding_periods
+                .push(self.current_tick.saturating_sub(position.entry_tick));
+        }
+    }
+    /// Number of ticks every open position has been held for
+    pub fn open_holding_periods(&self) -> Vec<u64> {
+        self.positions
+            .iter()
+            .map(|(_, _, position)| self.current_tick.saturating_sub(position.entry_tick))
+            .collect()
+    }
+    pub fn rand_assign_incomes(&mut self, rng: &mut impl Rng, agent_ids: &[u64]) {
+        let income = move |_: u64| Money(rng.gen_range(0..SUBSISTENCE_COST.0 * 2));
+        self.assign_incomes(income, agent_ids);
+    }
+    pub fn assign_incomes<F>(&mut self, mut income: F, agent_ids: &[u64])
+    where
+        F: FnMut(u64) -> Money,
+    {
+        for &agent_id in agent_ids {
+            if let Some(agent_income) = self.incomes.get_mut(agent_id as usize) {
+                *agent_income = income(agent_id);
+            }
+        }
+    }
+    pub fn is_active(&self, agent_id: u64) -> bool {
+        self.active.get(agent_id as usize).copied().unwrap_or(false)
+    }
+    /// Shares of every agent marked to the current price, the ones offered in the book included
+    fn marked_shares(&self, companies: &Companies) -> Vec<Money> {
+        let mut marked_shares = vec![Money::ZERO; self.num_of_agents as usize];
+        for (agent_id, instrument_id, number_of_shares) in
+            self.holdings.iter().chain(self.escrow.iter())
+        {
+            let Some(current_price) = instrument_id
+                .equity()
+                .and_then(|company_id| companies.get_current_price(company_id))
+            else {
+                continue;
+            };
+            if let Some(agent_shares) = marked_shares.get_mut(agent_id as usize) {
+                *agent_shares += Money::from(current_price).times(number_of_shares);
+            }
+        }
+        marked_shares
+    }
+    /// Shares of the agent marked to the current price, the ones offered in the book included
+    pub fn marked_shares_of(&self, agent_id: u64, companies: &Companies) -> Money {
+        self.holdings
+            .iter()
+            .chain(self.escrow.iter())
+            .filter(|(holder_id, _, _)| *holder_id == agent_id)
+            .filter_map(|(_, instrument_id, number_of_shares)| {
+                let current_price = companies.get_current_price(instrument_id.equity()?)?;
+                Some(Money::from(current_price).times(number_of_shares))
+            })
+            .sum()
+    }
+    /// Pays the salaries and takes out what the agents spend to live,
+    /// returns the agents whose cash, shares and other assets are worth less than `BROKE_BALANCE_THRESHOLD`
+    ///
+    /// `other_assets` is the cash each agent has put aside for its offers and lots and its claims on the funds
+    pub fn earn_and_consume(
+        &mut self,
+        companies: &Companies,
+        other_assets: &HashMap<u64, Money>,
+    ) -> Vec<u64> {
+        let mut broke_agents = Vec::new();
+        let marked_shares = self.marked_shares(companies);
+        for agent_id in self.iter() {
+            let id = agent_id as usize;
+            // funds don't live off their money
+            if !self.active[id]
+                || matches!(
+                    self.strategies[id],
+                    AgentStrategy::Institutional
+                        | AgentStrategy::AuthorizedParticipant
+                        | AgentStrategy::Arbitrageur
+                        | AgentStrategy::FxDealer
+                )
+            {
+                continue;
+            }
+            let balance = &mut self.balances.home[id];
+            *balance += self.incomes[id];
+            let consumption = SUBSISTENCE_COST + max(*balance, Money::ZERO).scale(CONSUMPTION_RATE);
+            *balance = max(*balance - consumption, Money::ZERO);
+            // what it has put into offers, shares and funds still counts
+            let net_worth = *balance + other_assets.get(&agent_id).copied().unwrap_or_default();
+            if net_worth + marked_shares[id] < BROKE_BALANCE_THRESHOLD {
+                broke_agents.push(agent_id);
+            }
+        }
+        broke_agents
+    }
+    /// The agent stops trading on its own, its holdings are sold off through `liquidation_orders`
+    pub fn leave(&mut self, agent_id: u64) {
+        let Some(active) = self.active.get_mut(agent_id as usize) else {
+            return;
+        };
+        *active = false;
+        if let Some(timeline) = self.preferences.0.get_mut(agent_id as usize) {
+            *timeline = Timeline::new();
+        }
+        self.try_offers.retain(|id, _| get_first(*id) != agent_id);
+    }
+    /// Sell orders for everything the agents which left the market still hold
+    pub fn liquidation_orders(&self, companies: &Companies) -> Vec<TodoTransaction> {
+        let mut orders = self
+            .holdings
+            .iter()
+            .filter(|(agent_id, _, number_of_shares)| {
+                *number_of_shares != 0 && !self.is_active(*agent_id)
+            })
+            .filter_map(|(agent_id, instrument_id, number_of_shares)| {
+                let current_price = companies.get_current_price(instrument_id.equity()?)?;
+                Some(TodoTransaction {
+                    agent_id,
+                    instrument_id,
+                    strike_price: Money::from(max(
+                        MIN_STRIKE_PRICE,
+                        current_price * (1.0 - LIQUIDATION_DISCOUNT),
+                    )),
+                    action: TradeAction::Sell,
+                    trade: Trade::new(number_of_shares),
+                })
+            })
+            .collect::<Vec<_>>();
+        // the first orders get the best bids, so they're queued by agent instead of in hash order
+        orders.sort_by_key(|order| (order.agent_id, order.instrument_id));
+        orders
+    }
+    /// Pays the risk-free rate on the cash balances
+    pub fn accrue_interest(&mut self, interest_rate: f64) {
+        for balance in self.balances.home.iter_mut() {
+            *balance += max(*balance, Money::ZERO).scale(interest_rate);
+        }
+    }
     pub fn can_buy(
         &self,
         agent_id: u64,
-        price: f64,
+        price: impl Into<Money>,
         quantity: u64,
     ) -> Result<bool, SimulationError> {
-        Ok(self.balances.get(agent_id)? >= price * quantity as f64)
+        Ok(self.balances.get(agent_id)? >= price.into().times(quantity))
     }
     pub fn can_sell(&self, id: u128, quantity: u64) -> bool {
         self.holdings.get_u128(id) >= quantity
@@ -412,7 +950,10 @@ impl Agents {
             if rng.gen_ratio(6, 10) {
                 continue;
             }
-            let (action, price) = if *new_price > 0.0 {
+            let Some(instrument_id) = instrument(*id) else {
+                continue;
+            };
+            let (action, price) = if *new_price > Money::ZERO {
                 (TradeAction::Buy, *new_price)
             } else {
                 (TradeAction::Sell, -*new_price)
@@ -428,7 +969,7 @@ impl Agents {
             }
             transactions.push(TodoTransaction {
                 agent_id: get_first(*id),
-                company_id: get_second(*id),
+                instrument_id,
                 strike_price: price,
                 action,
                 trade: attempting_trade.clone(),
@@ -438,72 +979,98 @@ impl Agents {
     }
     pub fn alert_agents(
         &mut self,
-        expired_trades: &HashMap<u64, Vec<FailedOffer<Trade>>>,
-        expired_options: &HashMap<u64, Vec<FailedOffer<StockOption>>>,
+        expired_trades: &HashMap<InstrumentId, Vec<FailedOffer<Trade>>>,
     ) -> Result<(), SimulationError> {
-        for (company_id, offers) in expired_trades.iter() {
+        self.refund_failed_offers(expired_trades)?;
+        for (instrument_id, offers) in expired_trades.iter() {
             for offer in offers.iter() {
-                // refund
-                if offer.1 == TradeAction::Sell {
-                    self.holdings.push(
-                        offer.0.lifetime,
-                        *company_id,
-                        offer.0.data.number_of_shares,
-                    );
-                } else {
-                    self.balances.add(
-                        offer.0.offerer_id,
-                        offer.0.strike_price * (offer.0.data.number_of_shares as f64),
-                    )?;
-                }
-
                 self.add_failed_offer(
-                    *company_id,
+                    *instrument_id,
                     offer.0.offerer_id,
                     offer.0.strike_price,
                     &offer.1,
                 );
             }
         }
-        for (company_id, offers) in expired_options.iter() {
-            for offer in offers {
-                // refund
-                if offer.1 == TradeAction::Sell {
-                    self.holdings.push(
-                        offer.0.lifetime,
-                        *company_id,
-                        offer.0.data.number_of_shares,
-                    );
-                } else {
-                    self.balances.add(
-                        offer.0.offerer_id,
-                        offer.0.strike_price * (offer.0.data.number_of_shares as f64),
-                    )?;
-                }
-
-                self.add_failed_offer(
-                    *company_id,
-                    offer.0.offerer_id,
-                    offer.0.strike_price,
-                    &offer.1,
-                );
+        Ok(())
+    }
+    /// Gives back the holdings or the money which were locked in the offers
+    pub fn refund_failed_offers(
+        &mut self,
+        expired_trades: &HashMap<InstrumentId, Vec<FailedOffer<Trade>>>,
+    ) -> Result<(), SimulationError> {
+        self.refund_failed_offers_in(expired_trades, HOME_CURRENCY)
+    }
+    /// Same as `refund_failed_offers` for offers priced in the given currency
+    pub fn refund_failed_offers_in(
+        &mut self,
+        expired_trades: &HashMap<InstrumentId, Vec<FailedOffer<Trade>>>,
+        currency: u64,
+    ) -> Result<(), SimulationError> {
+        for (instrument_id, offers) in expired_trades.iter() {
+            for offer in offers.iter() {
+                self.refund_offer(*instrument_id, offer, currency)?;
             }
         }
         Ok(())
     }
+    fn refund_offer(
+        &mut self,
+        instrument_id: InstrumentId,
+        offer: &FailedOffer<Trade>,
+        currency: u64,
+    ) -> Result<(), SimulationError> {
+        let number_of_shares = offer.0.data.number_of_shares;
+        if offer.1 == TradeAction::Sell {
+            return self.receive_instrument(offer.0.offerer_id, instrument_id, number_of_shares);
+        }
+        self.balances.add_in(
+            offer.0.offerer_id,
+            currency,
+            offer.0.strike_price.times(number_of_shares),
+        )
+    }
+    /// Gives back instruments taken out of the holdings, the currencies going back to the balances
+    fn receive_instrument(
+        &mut self,
+        agent_id: u64,
+        instrument_id: InstrumentId,
+        number: u64,
+    ) -> Result<(), SimulationError> {
+        match instrument_id.currency() {
+            Some(currency) => self
+                .balances
+                .add_in(agent_id, currency, Money::from_units(number)),
+            None => {
+                self.escrow.pop_up_to(agent_id, instrument_id, number);
+                self.holdings.push(agent_id, instrument_id, number);
+                Ok(())
+            }
+        }
+    }
+    /// Drops everything the agents remember about the company
+    pub fn forget_company(&mut self, company_id: u64) {
+        self.preferences.remove_company(company_id);
+        self.positions.remove_company(company_id);
+        self.escrow.remove_company(company_id);
+        self.try_offers
+            .retain(|id, _| instrument(*id) != Some(InstrumentId::Equity(company_id)));
+    }
     pub fn add_failed_offer(
         &mut self,
-        company_id: u64,
+        instrument_id: InstrumentId,
         agent_id: u64,
-        failed_price: f64,
+        failed_price: Money,
         offer_type: &TradeAction,
     ) {
         let price = match offer_type {
             TradeAction::Buy => failed_price,
             TradeAction::Sell => -failed_price,
         };
-        self.try_offers
-            .insert(combine(agent_id, company_id), price + failed_price * 0.25);
+        self.try_offers.insert(
+            key(agent_id, instrument_id),
+            price + failed_price.scale(0.25),
+        );
     }
     pub fn rand_give_assets(
         &mut self,
@@ -521,6 +1088,81 @@ impl Agents {
         }
         Ok(())
     }
+    /// Gives every agent shares of a random listed company, worth a draw from the distribution
+    pub fn rand_give_initial_holdings(
+        &mut self,
+        rng: &mut impl Rng,
+        companies: &mut Companies,
+        holding_distribution: &WealthDistribution,
+        agent_ids: &[u64],
+    ) {
+        if companies.num_of_companies == 0 {
+            return;
+        }
+        for &agent_id in agent_ids {
+            let company_id = companies.rand_company_id(rng);
+            let value = holding_distribution.sample(rng);
+            let (true, Some(current_price)) = (
+                companies.is_listed(company_id),
+                companies.get_current_price(company_id),
+            ) else {
+                continue;
+            };
+            let price = max(current_price, MIN_STRIKE_PRICE);
+            let number_of_shares = (value / price).floor() as u64;
+            if number_of_shares == 0 {
+                continue;
+            }
+            self.receive_shares(
+                agent_id,
+                InstrumentId::Equity(company_id),
+                number_of_shares,
+                price,
+            );
+            companies.shares_outstanding[company_id as usize] += number_of_shares;
+        }
+    }
+    /// Returns the `(company_id, number_of_shares)` the agent holds, sorted by company
+    pub fn holdings_of(&self, agent_id: u64) -> Vec<(u64, u64)> {
+        let mut holdings = self
+            .holdings
+            .iter()
+            .filter(|(holder_id, _, number_of_shares)| {
+                *holder_id == agent_id && *number_of_shares != 0
+            })
+            .filter_map(|(_, instrument_id, number_of_shares)| {
+                Some((instrument_id.equity()?, number_of_shares))
+            })
+            .collect::<Vec<_>>();
+        holdings.sort();
+        holdings
+    }
+    /// Balances at the current rates and holdings marked to the current price of every agent
+    pub fn wealth(&self, companies: &Companies, currencies: &Currencies) -> Vec<f64> {
+        let mut wealth = self
+            .balances
+            .home
+            .iter()
+            .map(|balance| balance.to_f64())
+            .collect::<Vec<_>>();
+        for (agent_id, currency, balance) in self.balances.foreign() {
+            if let Some(agent_wealth) = wealth.get_mut(agent_id as usize) {
+                *agent_wealth += currencies.to_home(balance.to_f64(), currency);
+            }
+        }
+        for (agent_id, instrument_id, number_of_shares) in self.holdings.iter() {
+            let Some(current_price) = instrument_id
+                .equity()
+                .and_then(|company_id| companies.get_current_price(company_id))
+            else {
+                continue;
+            };
+            if let Some(agent_wealth) = wealth.get_mut(agent_id as usize) {
+                *agent_wealth += current_price * number_of_shares as f64;
+            }
+        }
+        wealth
+    }
     pub fn give_assets(
         &mut self,
         agent_id: u64,
@@ -528,39 +1170,116 @@ impl Agents {
         balance_to_add: f64,
         holding_to_add: u64,
     ) -> Result<(), SimulationError> {
-        self.balances.add(agent_id, balance_to_add)?;
-        self.holdings.push(agent_id, company_id, holding_to_add);
+        self.balances.add(agent_id, Money::from(balance_to_add))?;
+        self.holdings
+            .push(agent_id, InstrumentId::Equity(company_id), holding_to_add);
         Ok(())
     }
     pub fn deduct_assets_from_todotransaction(
         &mut self,
         todo_transaction: &TodoTransaction,
     ) -> Result<(), SimulationError> {
+        self.deduct_assets_from_todotransaction_in(todo_transaction, HOME_CURRENCY)
+    }
+    /// Same as `deduct_assets_from_todotransaction` for an order priced in the given currency
+    pub fn deduct_assets_from_todotransaction_in(
+        &mut self,
+        todo_transaction: &TodoTransaction,
+        currency: u64,
+    ) -> Result<(), SimulationError> {
+        let number = todo_transaction.trade.number_of_shares;
         if todo_transaction.action == TradeAction::Sell {
-            self.holdings.pop(
-                todo_transaction.agent_id,
-                todo_transaction.company_id,
-                todo_transaction.trade.number_of_shares,
-            )?;
+            match todo_transaction.instrument_id.currency() {
+                Some(sold_currency) => self.balances.add_in(
+                    todo_transaction.agent_id,
+                    sold_currency,
+                    -Money::from_units(number),
+                )?,
+                None => {
+                    self.holdings.pop(
+                        todo_transaction.agent_id,
+                        todo_transaction.instrument_id,
+                        number,
+                    )?;
+                    self.escrow.push(
+                        todo_transaction.agent_id,
+                        todo_transaction.instrument_id,
+                        number,
+                    );
+                }
+            }
             return Ok(());
         }
-        self.balances.add(
+        self.balances.add_in(
             todo_transaction.agent_id,
-            -(todo_transaction.strike_price * (todo_transaction.trade.number_of_shares as f64)),
+            currency,
+            -todo_transaction.strike_price.times(number),
         )?;
         Ok(())
     }
+    /// Gives back what `deduct_assets_from_todotransaction_in` took for an order which didn't go through
+    pub fn refund_assets_from_todotransaction_in(
+        &mut self,
+        todo_transaction: &TodoTransaction,
+        currency: u64,
+    ) -> Result<(), SimulationError> {
+        let number = todo_transaction.trade.number_of_shares;
+        if todo_transaction.action == TradeAction::Sell {
+            return self.receive_instrument(
+                todo_transaction.agent_id,
+                todo_transaction.instrument_id,
+                number,
+            );
+        }
+        self.balances.add_in(
+            todo_transaction.agent_id,
+            currency,
+            todo_transaction.strike_price.times(number),
+        )
+    }
     pub fn exchange_assets_from_transaction(
         &mut self,
         transaction: &Transaction,
     ) -> Result<(), SimulationError> {
+        self.exchange_assets_from_transaction_in(transaction, HOME_CURRENCY)
+    }
+    /// Same as `exchange_assets_from_transaction` for a trade priced in the given currency
+    pub fn exchange_assets_from_transaction_in(
+        &mut self,
+        transaction: &Transaction,
+        currency: u64,
+    ) -> Result<(), SimulationError> {
+        let value = transaction.strike_price.times(transaction.number_of_shares);
         // seller's holdings and buyer's money are resolved at the time of offering
-        self.holdings
-            .push_from_txn(transaction.buyer_id, transaction);
-        self.balances.add(
+        if let Some(bought_currency) = transaction.instrument_id.currency() {
+            // a currency changing hands is cash, not a position
+            self.balances.add_in(
+                transaction.buyer_id,
+                bought_currency,
+                Money::from_units(transaction.number_of_shares),
+            )?;
+            return self.balances.add_in(transaction.seller_id, currency, value);
+        }
+        self.receive_shares(
+            transaction.buyer_id,
+            transaction.instrument_id,
+            transaction.number_of_shares,
+            transaction.strike_price,
+        );
+        self.balances
+            .add_in(transaction.seller_id, currency, value)?;
+        self.escrow.pop_up_to(
             transaction.seller_id,
-            transaction.strike_price * (transaction.number_of_shares as f64),
-        )?;
+            transaction.instrument_id,
+            transaction.number_of_shares,
+        );
+        self.realize(
+            transaction.seller_id,
+            transaction.instrument_id,
+            transaction.number_of_shares,
+            transaction.strike_price,
+        );
+        self.close_position_if_sold(transaction.seller_id, transaction.instrument_id);
         Ok(())
     }
 }
@@ -1,9 +1,18 @@
 use crate::{
-    entities::agents::Agents, log, logger::Log, trade_house::TradeAction,
-    transaction::TodoTransaction, SimulationError,
+    economy::Economy,
+    entities::agents::Agents,
+    etf::Etfs,
+    instrument::InstrumentId,
+    log,
+    logger::Log,
+    max, min,
+    money::Money,
+    trade_house::TradeAction,
+    transaction::{CompanyTransaction, TodoTransaction},
+    SimulationError, IPO_ALLOCATION_METHOD, MIN_STRIKE_PRICE, NUM_OF_SECTORS, TICK_SIZE,
 };
-use rand::Rng;
-use rand_distr::{Distribution, Normal};
+use rand::{seq::SliceRandom, Rng};
+use rand_distr::{Distribution, Normal, StandardNormal};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -17,68 +26,281 @@ pub struct MarketValue {
     pub overall_movement_end: f64,
 }
 
+/// How the lots are split between the bettors when more lots are bet for than available
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum AllocationMethod {
+    /// Everyone gets lots in proportion to their bet
+    #[default]
+    ProRata,
+    /// Bettors are picked at random and given their entire bet
+    Lottery,
+    /// The biggest bettors are given their entire bet first
+    LargestFirst,
+    /// A portion of the lots is reserved for the bets of `max_small_bet` lots or less,
+    /// both pools are then split pro-rata
+    SmallInvestorReservation {
+        reserved_fraction: f64,
+        max_small_bet: u64,
+    },
+}
+
+/// A bid made during book-building, the money for it is held until the book is closed
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Bid {
+    pub agent_id: u64,
+    pub price: Money,
+    pub number_of_lots: u64,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Lots {
-    pub strike_price: f64,
+    pub strike_price: Money,
     pub number_of_lots: u64,
     pub lot_size: u64,
     pub bets: HashMap<u64, u64>,
     pub total_num_of_bets: u64,
+    pub allocation_method: AllocationMethod,
+    /// When set, the lots are book-built, agents bid within the band
+    /// and `strike_price` is only decided when the book is closed
+    pub price_band: Option<(Money, Money)>,
+    pub bids: Vec<Bid>,
+}
+
+/// What a company reports every news cycle, the market reacts to how far
+/// the profit is from what the analysts expected
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct EarningsReport {
+    pub revenue: f64,
+    pub profit: f64,
+    /// Profit the company expects for the next news cycle
+    pub guidance: f64,
+    /// Profit the analysts expected for this news cycle
+    pub consensus: f64,
+    /// Percent by which the profit beat (or missed) the consensus
+    pub surprise: f64,
+}
+
+/// A company the agents are excited (or scared) about
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Hype {
+    pub company_id: u64,
+    /// Between -100 and 100, same as the news that caused it
+    pub strength: f64,
+    /// Number of news cycles left before the hype dies out
+    pub lifetime: u64,
 }
 
 pub const SYMBOL_LENGTH: usize = 4;
 pub const MAX_NUM_OF_HYPE_COMPANIES: usize = 2;
 pub const MIN_PROFIT_PERCENT_FOR_POSITIVE_HYPE_CONSIDERATION: f64 = 70.0;
 pub const MAX_PROFIT_PERCENT_FOR_NEGATIVE_HYPE_CONSIDERATION: f64 = -30.0;
+/// Number of news cycles hype lasts
+pub const HYPE_LIFETIME: u64 = 3;
+/// Portion of the strength of hype left after every news cycle
+pub const HYPE_DECAY_RATE: f64 = 0.5;
+/// Number of news preferences handed out to the agents per unit of hype strength
+pub const HYPE_PREFERENCE_WEIGHT: f64 = 0.5;
+/// How much a fully hyped company (+100) increases the number of lots agents bet for
+pub const HYPE_IPO_DEMAND_BOOST: f64 = 1.0;
+/// Portion of the revenue a company keeps as profit
+pub const PROFIT_MARGIN: f64 = 0.2;
+/// Portion of the deviation from the expected profit a company expects to carry into its guidance
+pub const GUIDANCE_PERSISTENCE: f64 = 0.5;
+/// How much the analysts trust the guidance of a company over its long-run expected profit
+pub const ANALYST_GUIDANCE_WEIGHT: f64 = 0.5;
+/// Relative price move for every percent of earnings surprise
+pub const EARNINGS_PRICE_REACTION: f64 = 0.001;
+/// Correlation between the news of two companies in the same sector
+pub const SECTOR_NEWS_CORRELATION: f64 = 0.5;
+/// Balance under which a company is considered to be in distress
+pub const BANKRUPTCY_BALANCE_THRESHOLD: f64 = 1_000.0;
+/// Number of consecutive news cycles in distress after which a company goes bankrupt
+pub const BANKRUPTCY_NEWS_CYCLES: u64 = 3;
+/// Portion of the remaining balance that is paid back to the shareholders on delisting
+pub const DELISTING_RECOVERY_RATE: f64 = 0.5;
+/// IPO price per unit of expected profit
+pub const IPO_PRICE_TO_PROFIT_RATIO: f64 = 0.1;
+/// Money a new company tries to raise in its IPO per unit of expected profit
+pub const IPO_RAISE_TO_PROFIT_RATIO: f64 = 100.0;
+/// How much a fully hyped market (+100) raises the IPO price
+pub const IPO_HYPE_BOOST: f64 = 0.5;
+/// Upper bound of the preference an agent gets for a newly listed company in an unhyped market
+pub const IPO_MAX_AWARENESS_PREFERENCE: f64 = 10.0;
+/// Relative width of the price band of a book-built IPO on each side of the IPO price
+pub const IPO_PRICE_BAND_WIDTH: f64 = 0.2;
+/// How much the news and hype (in percent) of a company push the bids of a book-built IPO
+pub const BOOK_BUILDING_SENTIMENT_WEIGHT: f64 = 0.5;
+/// A company with less than this many news cycles of expected profit in its balance raises money
+pub const FOLLOW_ON_RUNWAY_THRESHOLD: f64 = 10.0;
+/// The runway, in news cycles of expected profit, a follow-on offering raises the company to
+pub const FOLLOW_ON_TARGET_RUNWAY: f64 = 20.0;
+/// Discount on the current price at which the shares of a follow-on offering are sold
+pub const FOLLOW_ON_DISCOUNT: f64 = 0.05;
 
 #[derive(Default)]
 pub struct Companies {
     pub num_of_companies: u64,
     pub market_values: Vec<MarketValue>,
-    pub balances: Vec<f64>,
+    pub balances: Vec<Money>,
     pub expected_profits: Vec<f64>,
     pub news: Vec<f64>,
-    pub hype: [Option<(u64, f64)>; MAX_NUM_OF_HYPE_COMPANIES],
+    /// Hyped companies of every sector
+    pub hype: Vec<[Option<Hype>; MAX_NUM_OF_HYPE_COMPANIES]>,
     pub lots: Vec<Lots>,
     pub lot_finalization_times: Vec<u64>,
+    /// Number of consecutive news cycles the company has spent under `BANKRUPTCY_BALANCE_THRESHOLD`
+    pub distress_cycles: Vec<u64>,
+    pub listed: Vec<bool>,
+    /// Shares in circulation: the founders' shares the company starts with,
+    /// the shares handed out to the agents at the start and every share sold through the lots
+    pub shares_outstanding: Vec<u64>,
+    /// Sector of the industry each company belongs to
+    pub sectors: Vec<u64>,
+    /// Latest earnings report of every company
+    pub earnings_reports: Vec<Option<EarningsReport>>,
+    /// Smallest step between the prices the stock of every company is traded at
+    pub tick_sizes: Vec<Money>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Company {
     pub id: u64,
     pub market_value: MarketValue,
-    pub balance: f64,
+    pub balance: Money,
     pub expected_profit: f64,
     pub news: f64,
     pub lots: Lots,
     pub lot_finalization_time: u64,
+    pub distress_cycles: u64,
+    pub listed: bool,
+    pub shares_outstanding: u64,
+    pub sector: u64,
+    pub earnings_report: Option<EarningsReport>,
+    pub tick_size: Money,
 }
 
 fn rand_hype(
     rng: &mut impl Rng,
-    number_of_companies: usize,
-) -> [Option<(u64, f64)>; MAX_NUM_OF_HYPE_COMPANIES] {
+    sector_companies: &[u64],
+) -> [Option<Hype>; MAX_NUM_OF_HYPE_COMPANIES] {
     let mut hype = [None; MAX_NUM_OF_HYPE_COMPANIES];
+    if sector_companies.is_empty() {
+        return hype;
+    }
     for hype_item in hype
         .iter_mut()
         .take(rng.gen_range(0..MAX_NUM_OF_HYPE_COMPANIES))
     {
-        *hype_item = Some((
-            rng.gen_range(0..number_of_companies as u64),
+        *hype_item = Some(Hype::new(
+            sector_companies[rng.gen_range(0..sector_companies.len())],
             rng.gen_range(-100.0..100.0),
         ));
     }
     hype
 }
 
+fn fill_hype(
+    hype: &mut [Option<Hype>; MAX_NUM_OF_HYPE_COMPANIES],
+    hypeable_companies: &mut Vec<(u64, f64)>,
+) {
+    let mut item = hypeable_companies.pop();
+    for hype in hype.iter_mut() {
+        let Some(clean_item) = item else {
+            return;
+        };
+        let Some(hype) = hype else {
+            *hype = Some(Hype::new(clean_item.0, clean_item.1));
+            item = hypeable_companies.pop();
+            continue;
+        };
+        if clean_item.1.abs() < hype.strength.abs() {
+            continue;
+        }
+        *hype = Hype::new(clean_item.0, clean_item.1);
+        item = hypeable_companies.pop();
+    }
+}
+
+fn average_hype<'a>(hype: impl Iterator<Item = &'a Hype> + Clone) -> f64 {
+    let count = hype.clone().count();
+    if count == 0 {
+        return 0.0;
+    }
+    hype.map(|hype| hype.strength).sum::<f64>() / count as f64
+}
+
 fn news_to_probability(news: f64) -> f64 {
     1.0 - (-news * news).exp()
 }
 
+/// The hype multiplier applied to a newly listed company
+fn ipo_hype_multiplier(market_hype: f64) -> f64 {
+    1.0 + IPO_HYPE_BOOST * max(market_hype, 0.0) / 100.0
+}
+
+/// Tick size of a stock listed at the price, coarser for the dearer stocks
+pub fn tick_size(price: f64) -> Money {
+    if price < 100.0 {
+        TICK_SIZE
+    } else if price < 1_000.0 {
+        TICK_SIZE.times(5)
+    } else {
+        TICK_SIZE.times(10)
+    }
+}
+
+/// Shares kept by the founders of a company, so that its shares are backed by its cash at the price
+pub fn founders_shares(balance: Money, price: f64) -> u64 {
+    (max(balance, Money::ZERO).to_f64() / max(price, MIN_STRIKE_PRICE)).round() as u64
+}
+
+pub fn ipo_price(expected_profit: f64, market_hype: f64) -> f64 {
+    max(
+        MIN_STRIKE_PRICE,
+        expected_profit * IPO_PRICE_TO_PROFIT_RATIO * ipo_hype_multiplier(market_hype),
+    )
+}
+
+impl EarningsReport {
+    pub fn new(profit: f64, guidance: f64, consensus: f64) -> Self {
+        // the costs are covered by the revenue expected at `PROFIT_MARGIN`
+        let costs = max(consensus, 0.0) * (1.0 / PROFIT_MARGIN - 1.0);
+        let surprise = if consensus == 0.0 {
+            0.0
+        } else {
+            (profit - consensus) / consensus.abs() * 100.0
+        };
+        Self {
+            revenue: max(profit + costs, 0.0),
+            profit,
+            guidance,
+            consensus,
+            surprise,
+        }
+    }
+}
+
+impl Hype {
+    pub fn new(company_id: u64, strength: f64) -> Self {
+        Self {
+            company_id,
+            strength,
+            lifetime: HYPE_LIFETIME,
+        }
+    }
+    /// Returns false once the hype has died out
+    pub fn tick(&mut self) -> bool {
+        self.strength *= HYPE_DECAY_RATE;
+        self.lifetime = self.lifetime.saturating_sub(1);
+        self.lifetime != 0
+    }
+}
+
 impl Company {
+    /// The company starts without shares in circulation, they come from its lots
     pub fn new(
         id: u64,
-        balance: f64,
+        balance: impl Into<Money>,
         expected_profit: f64,
         news: f64,
         lots: (f64, u64, u64),
@@ -86,55 +308,83 @@ impl Company {
         Self {
             id,
             market_value: MarketValue::default(),
-            balance,
+            balance: balance.into(),
             expected_profit,
             news,
             lots: Lots::new(lots.0, lots.1, lots.2),
             lot_finalization_time: 0,
+            distress_cycles: 0,
+            listed: true,
+            shares_outstanding: 0,
+            sector: 0,
+            earnings_report: None,
+            tick_size: TICK_SIZE,
         }
     }
 }
 
 impl Lots {
-    pub fn new(strike_price: f64, number_of_lots: u64, lot_size: u64) -> Self {
+    pub fn new(strike_price: impl Into<Money>, number_of_lots: u64, lot_size: u64) -> Self {
         Self {
-            strike_price,
+            strike_price: strike_price.into(),
             number_of_lots,
             lot_size,
             bets: HashMap::new(),
             total_num_of_bets: 0,
+            allocation_method: IPO_ALLOCATION_METHOD,
+            price_band: None,
+            bids: Vec::new(),
         }
     }
+    pub fn new_book_building(price_band: (f64, f64), number_of_lots: u64, lot_size: u64) -> Self {
+        let price_band = (Money::from(price_band.0), Money::from(price_band.1));
+        Self {
+            price_band: Some(price_band),
+            ..Self::new(price_band.1, number_of_lots, lot_size)
+        }
+    }
+    pub fn is_book_building(&self) -> bool {
+        self.price_band.is_some()
+    }
     pub fn is_blank(&self) -> bool {
-        self.strike_price == 0.0 || self.lot_size == 0 || self.number_of_lots == 0
+        self.strike_price == Money::ZERO || self.lot_size == 0 || self.number_of_lots == 0
     }
     pub fn close(&mut self) {
-        self.strike_price = 0.0;
+        self.strike_price = Money::ZERO;
         self.number_of_lots = 0;
         self.lot_size = 0;
         self.total_num_of_bets = 0;
         self.bets.clear();
+        self.price_band = None;
+        self.bids.clear();
     }
     pub fn rand(rng: &mut impl Rng) -> Self {
         Self {
-            strike_price: rng.gen_range(10.0..1_000.0),
+            strike_price: Money::from(rng.gen_range(10.0..1_000.0)),
             number_of_lots: rng.gen_range(1..1_000) * 100, // keep it a multiple of 100,
             lot_size: rng.gen_range(1..10) * 10,           // keep it a multiple of 10
             total_num_of_bets: 0,
             bets: HashMap::new(), // no random bets because agents might not have the money for the bet
-                                  // or be uninterested
+            // or be uninterested
+            allocation_method: IPO_ALLOCATION_METHOD,
+            price_band: None,
+            bids: Vec::new(),
         }
     }
     pub fn rng_reset(&mut self, rng: &mut impl Rng, appox_price: f64) {
         self.bets.clear();
-        self.strike_price = appox_price + rng.gen_range(-1_000.0..1_000.0);
+        self.bids.clear();
+        self.price_band = None;
+        self.strike_price = Money::from(appox_price + rng.gen_range(-1_000.0..1_000.0));
         self.number_of_lots = rng.gen_range(1..1_000) * 100;
         self.lot_size = rng.gen_range(1..10) * 10;
         self.total_num_of_bets = 0;
     }
     pub fn rng_reset_exact_price(&mut self, rng: &mut impl Rng, exact_price: f64) {
         self.bets.clear();
-        self.strike_price = exact_price;
+        self.bids.clear();
+        self.price_band = None;
+        self.strike_price = Money::from(exact_price);
         self.number_of_lots = rng.gen_range(1..1_000) * 100;
         self.lot_size = rng.gen_range(1..10) * 10;
         self.total_num_of_bets = 0;
@@ -150,7 +400,7 @@ impl Lots {
         }
         agents.balances.add(
             agent_id,
-            -(self.strike_price * (self.lot_size * number_of_lots) as f64),
+            -self.strike_price.times(self.lot_size * number_of_lots),
         )?;
         self.bets
             .entry(agent_id)
@@ -159,6 +409,104 @@ impl Lots {
         self.total_num_of_bets += number_of_lots;
         Ok(())
     }
+    /// The price of the bid is brought within the price band
+    pub fn add_bid_and_update_agent(
+        &mut self,
+        agents: &mut Agents,
+        agent_id: u64,
+        price: Money,
+        number_of_lots: u64,
+    ) -> Result<(), SimulationError> {
+        let Some((lowest_price, highest_price)) = self.price_band else {
+            return Err(SimulationError::UnDoable);
+        };
+        if self.is_blank() || number_of_lots == 0 {
+            return Ok(());
+        }
+        let price = price.clamp(lowest_price, highest_price);
+        agents
+            .balances
+            .add(agent_id, -price.times(self.lot_size * number_of_lots))?;
+        self.bids.push(Bid {
+            agent_id,
+            price,
+            number_of_lots,
+        });
+        Ok(())
+    }
+    /// Cash every bettor and bidder has put aside for the lots
+    pub fn escrowed_cash(&self) -> impl Iterator<Item = (u64, Money)> + '_ {
+        let bets = self.bets.iter().map(|(&agent_id, &number_of_lots)| {
+            (
+                agent_id,
+                self.strike_price.times(self.lot_size * number_of_lots),
+            )
+        });
+        let bids = self.bids.iter().map(|bid| {
+            (
+                bid.agent_id,
+                bid.price.times(self.lot_size * bid.number_of_lots),
+            )
+        });
+        bets.chain(bids)
+    }
+    /// The highest price at which the bids cover all the lots,
+    /// or the bottom of the price band if they never do
+    pub fn discover_price(&self) -> Option<Money> {
+        let (lowest_price, _) = self.price_band?;
+        let mut bids = self.bids.clone();
+        bids.sort_by_key(|bid| std::cmp::Reverse(bid.price));
+        let mut demand = 0;
+        for bid in bids.iter() {
+            demand += bid.number_of_lots;
+            if demand >= self.number_of_lots {
+                return Some(bid.price);
+            }
+        }
+        Some(lowest_price)
+    }
+    /// Sets the issue price from the bids, the bids at or above it become bets at the issue price
+    /// and everyone else is refunded
+    pub fn close_book(&mut self, agents: &mut Agents) -> Result<(), SimulationError> {
+        let Some(issue_price) = self.discover_price() else {
+            return Ok(());
+        };
+        for bid in std::mem::take(&mut self.bids) {
+            let shares = bid.number_of_lots * self.lot_size;
+            if bid.price < issue_price {
+                agents.balances.add(bid.agent_id, bid.price.times(shares))?;
+                continue;
+            }
+            agents
+                .balances
+                .add(bid.agent_id, (bid.price - issue_price).times(shares))?;
+            self.add_bet(bid.agent_id, bid.number_of_lots);
+        }
+        log!(info "Book closed: issue_price: {}, price_band: {:?}", issue_price, self.price_band);
+        self.strike_price = issue_price;
+        self.price_band = None;
+        Ok(())
+    }
+    /// Refunds every bet and bid, then closes the lots
+    pub fn cancel(&mut self, agents: &mut Agents) -> Result<(), SimulationError> {
+        let mut bets = self
+            .bets
+            .iter()
+            .map(|(&agent_id, &number_of_lots)| (agent_id, number_of_lots))
+            .collect::<Vec<_>>();
+        bets.sort();
+        for (agent_id, number_of_lots) in bets {
+            self.remove_bet_and_update_agent(agents, agent_id, number_of_lots)?;
+        }
+        for bid in std::mem::take(&mut self.bids) {
+            agents.balances.add(
+                bid.agent_id,
+                bid.price.times(bid.number_of_lots * self.lot_size),
+            )?;
+        }
+        self.close();
+        Ok(())
+    }
     pub fn add_bet(&mut self, agent_id: u64, number_of_lots: u64) {
         if self.is_blank() || number_of_lots == 0 {
             return;
@@ -170,7 +518,7 @@ impl Lots {
         self.total_num_of_bets += number_of_lots;
     }
     pub fn fits_agent_price(&self, strike_price: f64, acceptable_deviation: f64) -> bool {
-        (self.strike_price - strike_price).abs() < acceptable_deviation
+        (self.strike_price.to_f64() - strike_price).abs() < acceptable_deviation
     }
     pub fn remove_bet_and_update_agent(
         &mut self,
@@ -189,7 +537,7 @@ impl Lots {
         }
         agents.balances.add(
             agent_id,
-            self.strike_price * (self.lot_size * number_of_lots) as f64,
+            self.strike_price.times(self.lot_size * number_of_lots),
         )?;
 
         *bet -= number_of_lots;
@@ -212,57 +560,189 @@ impl Lots {
     pub fn get_bet(&self, agent_id: u64) -> u64 {
         *self.bets.get(&agent_id).unwrap_or(&0)
     }
-    pub fn distribute_shares(&mut self, company_id: u64, agents: &mut Agents) {
+    /// Allocates the lots between the bettors, refunds whatever couldn't be allocated and closes the lots
+    pub fn finalize(
+        &mut self,
+        company_id: u64,
+        agents: &mut Agents,
+        rng: &mut impl Rng,
+    ) -> Result<Vec<CompanyTransaction>, SimulationError> {
         if self.is_blank() {
-            return;
+            return Ok(Vec::new());
         }
-        let mut bets = self.bets.iter().collect::<Vec<_>>();
-        bets.sort_by(|a, b| b.1.cmp(a.1));
-        log!(info "Lot distribution: company_id: {} strike_price: {}", company_id,  self.strike_price);
-        for (&agent_id, &number_of_lots) in bets.iter() {
-            let number_of_shares = number_of_lots * self.lot_size;
-            print!("({}[{}]), ", agent_id, number_of_shares);
-            if self.number_of_lots < number_of_lots {
+        if self.is_book_building() {
+            self.close_book(agents)?;
+        }
+        let mut bets = self
+            .bets
+            .iter()
+            .map(|(&agent_id, &number_of_lots)| (agent_id, number_of_lots))
+            .collect::<Vec<_>>();
+        bets.sort();
+        let allocations = self
+            .allocation_method
+            .allocate(&bets, self.number_of_lots, rng);
+        log!(info "Lot distribution: company_id: {} strike_price: {} method: {:?}", company_id, self.strike_price, self.allocation_method);
+
+        let mut transactions = Vec::with_capacity(bets.len());
+        for (&(agent_id, number_of_lots), allocated_lots) in bets.iter().zip(allocations) {
+            let refunded_lots = number_of_lots - allocated_lots;
+            if refunded_lots != 0 {
+                agents.balances.add(
+                    agent_id,
+                    self.strike_price.times(refunded_lots * self.lot_size),
+                )?;
+            }
+            if allocated_lots == 0 {
                 continue;
             }
-            agents
-                .holdings
-                .insert(agent_id, company_id, number_of_shares);
-            self.number_of_lots -= number_of_lots;
+            let number_of_shares = allocated_lots * self.lot_size;
+            agents.receive_shares(
+                agent_id,
+                InstrumentId::Equity(company_id),
+                number_of_shares,
+                self.strike_price,
+            );
+            transactions.push(CompanyTransaction::new(
+                agent_id,
+                company_id,
+                number_of_shares,
+                self.strike_price,
+            ));
         }
-        println!();
-        self.bets.clear();
+        self.close();
+        Ok(transactions)
     }
-    pub fn compress_lot_size(&mut self, compress_ratio: f64) -> u64 {
-        let new_lot_size = (self.lot_size as f64 * compress_ratio).round() as u64;
-        println!("{:?}", compress_ratio);
+}
 
-        let refund_difference = self.lot_size - new_lot_size;
-        self.lot_size = new_lot_size;
-        refund_difference
-    }
-    pub fn compress_shares(&mut self, agents: &mut Agents) -> Result<(), SimulationError> {
-        if self.is_blank() {
-            return Ok(());
+impl AllocationMethod {
+    /// Splits the available lots between the bets, returns the lots given to each bet in the same order
+    ///
+    /// Nobody is given more lots than they bet for
+    pub fn allocate(
+        &self,
+        bets: &[(u64, u64)],
+        available_lots: u64,
+        rng: &mut impl Rng,
+    ) -> Vec<u64> {
+        let total_num_of_bets: u64 = bets.iter().map(|(_, number_of_lots)| number_of_lots).sum();
+        if total_num_of_bets <= available_lots {
+            return bets
+                .iter()
+                .map(|(_, number_of_lots)| *number_of_lots)
+                .collect();
         }
-        let compress_ratio = self.total_num_of_bets as f64 / self.number_of_lots as f64;
-        if compress_ratio.fract() != 0.0 {
-            return Err(SimulationError::UnDoable);
+        match self {
+            AllocationMethod::ProRata => allocate_pro_rata(bets, available_lots),
+            AllocationMethod::LargestFirst => {
+                let mut order = (0..bets.len()).collect::<Vec<_>>();
+                order.sort_by(|a, b| bets[*b].1.cmp(&bets[*a].1));
+                allocate_in_order(bets, &order, available_lots)
+            }
+            AllocationMethod::Lottery => {
+                let mut order = (0..bets.len()).collect::<Vec<_>>();
+                order.shuffle(rng);
+                allocate_in_order(bets, &order, available_lots)
+            }
+            AllocationMethod::SmallInvestorReservation {
+                reserved_fraction,
+                max_small_bet,
+            } => {
+                let reserved_lots = (available_lots as f64 * reserved_fraction).floor() as u64;
+                let is_small = |number_of_lots: u64| number_of_lots <= *max_small_bet;
+                let small_bets = bets
+                    .iter()
+                    .map(|&(id, number_of_lots)| {
+                        (
+                            id,
+                            if is_small(number_of_lots) {
+                                number_of_lots
+                            } else {
+                                0
+                            },
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                let mut allocations = allocate_pro_rata(&small_bets, reserved_lots);
+
+                // whatever is left of the reserve goes to everyone else
+                let mut remaining_lots = available_lots - allocations.iter().sum::<u64>();
+                let large_bets = bets
+                    .iter()
+                    .map(|&(id, number_of_lots)| {
+                        (
+                            id,
+                            if is_small(number_of_lots) {
+                                0
+                            } else {
+                                number_of_lots
+                            },
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                let large_allocations = allocate_pro_rata(&large_bets, remaining_lots);
+                remaining_lots -= large_allocations.iter().sum::<u64>();
+                for (allocation, large_allocation) in allocations.iter_mut().zip(large_allocations)
+                {
+                    *allocation += large_allocation;
+                }
+
+                // and if the large bets were satisfied, the small bets get the rest
+                let unfilled_bets = bets
+                    .iter()
+                    .zip(allocations.iter())
+                    .map(|(&(id, number_of_lots), allocation)| (id, number_of_lots - allocation))
+                    .collect::<Vec<_>>();
+                for (allocation, extra) in allocations
+                    .iter_mut()
+                    .zip(allocate_pro_rata(&unfilled_bets, remaining_lots))
+                {
+                    *allocation += extra;
+                }
+                allocations
+            }
         }
-        let refund_difference = self.compress_lot_size(compress_ratio);
-        for (bettor, &number_of_lots) in self.bets.iter() {
-            agents.balances.add(
-                *bettor,
-                (refund_difference * number_of_lots) as f64 * self.strike_price,
-            )?;
+    }
+}
+
+fn allocate_pro_rata(bets: &[(u64, u64)], available_lots: u64) -> Vec<u64> {
+    let total_num_of_bets: u64 = bets.iter().map(|(_, number_of_lots)| number_of_lots).sum();
+    if total_num_of_bets <= available_lots {
+        return bets
+            .iter()
+            .map(|(_, number_of_lots)| *number_of_lots)
+            .collect();
+    }
+    let share = |number_of_lots: u64| number_of_lots as u128 * available_lots as u128;
+    let mut allocations = bets
+        .iter()
+        .map(|(_, number_of_lots)| (share(*number_of_lots) / total_num_of_bets as u128) as u64)
+        .collect::<Vec<_>>();
+
+    // hand out the lots lost to rounding down, largest remainders first
+    let mut remaining_lots = available_lots - allocations.iter().sum::<u64>();
+    let mut order = (0..bets.len()).collect::<Vec<_>>();
+    order.sort_by_key(|i| std::cmp::Reverse(share(bets[*i].1) % total_num_of_bets as u128));
+    for i in order {
+        if remaining_lots == 0 {
+            break;
+        }
+        if allocations[i] < bets[i].1 {
+            allocations[i] += 1;
+            remaining_lots -= 1;
         }
-        self.strike_price *= compress_ratio;
-        Ok(())
     }
-    pub fn finalize(&mut self, company_id: u64, agents: &mut Agents) {
-        _ = self.compress_shares(agents); // compress if you can
-        self.distribute_shares(company_id, agents);
+    allocations
+}
+
+fn allocate_in_order(bets: &[(u64, u64)], order: &[usize], mut available_lots: u64) -> Vec<u64> {
+    let mut allocations = vec![0; bets.len()];
+    for &i in order {
+        let allocation = min(bets[i].1, available_lots);
+        allocations[i] = allocation;
+        available_lots -= allocation;
     }
+    allocations
 }
 
 impl Companies {
@@ -276,9 +756,18 @@ impl Companies {
         let mut news = Vec::with_capacity(number_of_companies);
         let mut lots = Vec::with_capacity(number_of_companies);
         let mut lot_finalization_times = Vec::with_capacity(number_of_companies);
+        let mut tick_sizes = Vec::with_capacity(number_of_companies);
+        let mut shares_outstanding = Vec::with_capacity(number_of_companies);
+        let sectors = (0..number_of_companies)
+            .map(|_| rng.gen_range(0..NUM_OF_SECTORS))
+            .collect::<Vec<_>>();
         for _ in 0..number_of_companies {
-            balances.push(rng.gen_range(10_000.0..1_000_000.0));
-            market_values.push(MarketValue::rand(rng));
+            let balance = Money::from(rng.gen_range(10_000.0..1_000_000.0));
+            balances.push(balance);
+            let market_value = MarketValue::rand(rng);
+            tick_sizes.push(tick_size(market_value.current_price));
+            shares_outstanding.push(founders_shares(balance, market_value.current_price));
+            market_values.push(market_value);
             lots.push(Lots::rand(rng));
             lot_finalization_times.push(current_time + rng.gen_range(5..10));
 
@@ -295,11 +784,24 @@ impl Companies {
             num_of_companies: number_of_companies as u64,
             market_values,
             balances,
-            hype: rand_hype(rng, number_of_companies),
             expected_profits,
             news,
             lots,
             lot_finalization_times,
+            distress_cycles: vec![0; number_of_companies],
+            listed: vec![true; number_of_companies],
+            shares_outstanding,
+            hype: (0..NUM_OF_SECTORS)
+                .map(|sector| {
+                    let sector_companies = (0..number_of_companies as u64)
+                        .filter(|id| sectors[*id as usize] == sector)
+                        .collect::<Vec<_>>();
+                    rand_hype(rng, &sector_companies)
+                })
+                .collect(),
+            sectors,
+            earnings_reports: vec![None; number_of_companies],
+            tick_sizes,
         }
     }
     pub fn load(companies: &[Company]) -> Self {
@@ -310,6 +812,12 @@ impl Companies {
         let mut news = Vec::with_capacity(num_of_companies);
         let mut lots = Vec::with_capacity(num_of_companies);
         let mut lot_finalization_times = Vec::with_capacity(num_of_companies);
+        let mut distress_cycles = Vec::with_capacity(num_of_companies);
+        let mut listed = Vec::with_capacity(num_of_companies);
+        let mut shares_outstanding = Vec::with_capacity(num_of_companies);
+        let mut sectors = Vec::with_capacity(num_of_companies);
+        let mut earnings_reports = Vec::with_capacity(num_of_companies);
+        let mut tick_sizes = Vec::with_capacity(num_of_companies);
         for company in companies.iter() {
             market_values.push(company.market_value.clone());
             balances.push(company.balance);
@@ -317,16 +825,28 @@ impl Companies {
             news.push(company.news);
             lots.push(company.lots.clone());
             lot_finalization_times.push(company.lot_finalization_time);
+            distress_cycles.push(company.distress_cycles);
+            listed.push(company.listed);
+            shares_outstanding.push(company.shares_outstanding);
+            sectors.push(company.sector);
+            earnings_reports.push(company.earnings_report);
+            tick_sizes.push(company.tick_size);
         }
         Self {
             num_of_companies: num_of_companies as u64,
             market_values,
             balances,
-            hype: vec![None; MAX_NUM_OF_HYPE_COMPANIES].try_into().unwrap(),
+            hype: vec![[None; MAX_NUM_OF_HYPE_COMPANIES]; NUM_OF_SECTORS as usize],
             expected_profits,
             news,
             lots,
             lot_finalization_times,
+            distress_cycles,
+            listed,
+            shares_outstanding,
+            sectors,
+            earnings_reports,
+            tick_sizes,
         }
     }
     pub fn load_mut(&mut self, companies: &[Company]) {
@@ -339,6 +859,12 @@ impl Companies {
             self.lots.push(company.lots.clone());
             self.lot_finalization_times
                 .push(company.lot_finalization_time);
+            self.distress_cycles.push(company.distress_cycles);
+            self.listed.push(company.listed);
+            self.shares_outstanding.push(company.shares_outstanding);
+            self.sectors.push(company.sector);
+            self.earnings_reports.push(company.earnings_report);
+            self.tick_sizes.push(company.tick_size);
         }
     }
     pub fn save(&self) -> Vec<Company> {
@@ -352,10 +878,104 @@ impl Companies {
                 news: self.news[id],
                 lots: self.lots[id].clone(),
                 lot_finalization_time: self.lot_finalization_times[id],
+                distress_cycles: self.distress_cycles[id],
+                listed: self.listed[id],
+                shares_outstanding: self.shares_outstanding[id],
+                sector: self.sectors[id],
+                earnings_report: self.earnings_reports[id],
+                tick_size: self.tick_sizes[id],
             });
         }
         companies
     }
+    /// Average of the current hype, positive when the market is excited
+    pub fn market_hype(&self) -> f64 {
+        average_hype(self.hype.iter().flatten().flatten())
+    }
+    /// Average of the current hype of the sector
+    pub fn sector_hype(&self, sector: u64) -> f64 {
+        let Some(hype) = self.hype.get(sector as usize) else {
+            return 0.0;
+        };
+        average_hype(hype.iter().flatten())
+    }
+    /// The hype of the company itself, 0 if it isn't hyped
+    pub fn company_hype(&self, company_id: u64) -> f64 {
+        let Some(hype) = self.hype.get(self.sectors[company_id as usize] as usize) else {
+            return 0.0;
+        };
+        hype.iter()
+            .flatten()
+            .filter(|hype| hype.company_id == company_id)
+            .map(|hype| hype.strength)
+            .sum()
+    }
+    /// Lists a new company with its IPO open, returns the id of the new company.
+    /// With `book_building` the IPO price is discovered from bids within a band around the listing price
+    pub fn rand_list_new_company(
+        &mut self,
+        current_time: u64,
+        book_building: bool,
+        rng: &mut impl Rng,
+    ) -> u64 {
+        let id = self.num_of_companies;
+        let expected_profit = rng.gen_range(100.0..10_000.0);
+        let sector = rng.gen_range(0..NUM_OF_SECTORS);
+        let strike_price = ipo_price(expected_profit, self.sector_hype(sector));
+        let tick_size = tick_size(strike_price);
+        let strike_price = Money::from(strike_price).ceil_to(tick_size).to_f64();
+        let lot_size = rng.gen_range(1..10) * 10;
+        let number_of_lots = ((expected_profit * IPO_RAISE_TO_PROFIT_RATIO)
+            / (strike_price * lot_size as f64))
+            .ceil() as u64;
+
+        let balance = Money::from(rng.gen_range(10_000.0..100_000.0));
+        let mut company = Company::new(
+            id,
+            balance,
+            expected_profit,
+            0.0,
+            (strike_price, number_of_lots, lot_size),
+        );
+        if book_building {
+            company.lots = Lots::new_book_building(
+                (
+                    max(
+                        MIN_STRIKE_PRICE,
+                        strike_price * (1.0 - IPO_PRICE_BAND_WIDTH),
+                    ),
+                    strike_price * (1.0 + IPO_PRICE_BAND_WIDTH),
+                ),
+                number_of_lots,
+                lot_size,
+            );
+        }
+        company.market_value = MarketValue::at_price(strike_price);
+        company.shares_outstanding = founders_shares(balance, strike_price);
+        company.sector = sector;
+        company.tick_size = tick_size;
+        company.lot_finalization_time = current_time + rng.gen_range(5..10);
+        self.load_mut(&[company]);
+        log!(info "New listing: company_id: {}, sector: {}, ipo_price: {}, number_of_lots: {}, lot_size: {}", id, sector, strike_price, number_of_lots, lot_size);
+        id
+    }
+    /// Upper bound of the preference agents get for a newly listed company
+    pub fn new_listing_preference(&self, company_id: u64) -> usize {
+        let sector_hype = self.sector_hype(self.sectors[company_id as usize]);
+        (IPO_MAX_AWARENESS_PREFERENCE * ipo_hype_multiplier(sector_hype)).round() as usize
+    }
+    /// Smallest step between the prices of the instrument, the currencies trading to the minor unit
+    pub fn tick_size(&self, instrument_id: InstrumentId) -> Money {
+        match instrument_id {
+            InstrumentId::Equity(company_id) => self
+                .tick_sizes
+                .get(company_id as usize)
+                .copied()
+                .unwrap_or(TICK_SIZE),
+            InstrumentId::Fx(_) => Money::MINOR_UNIT,
+            _ => TICK_SIZE,
+        }
+    }
     pub fn get_current_price(&self, company_id: u64) -> Option<f64> {
         self.market_values
             .get(company_id as usize)
@@ -364,41 +984,254 @@ impl Companies {
     pub fn iter(&self) -> std::ops::Range<u64> {
         0..self.num_of_companies
     }
+    pub fn is_listed(&self, company_id: u64) -> bool {
+        self.listed
+            .get(company_id as usize)
+            .copied()
+            .unwrap_or(false)
+    }
     pub fn rand_company_id(&self, rng: &mut impl Rng) -> u64 {
         rng.gen_range(0..self.num_of_companies)
     }
-    pub fn rand_release_news(&mut self, agents: &mut Agents, rng: &mut impl Rng) {
+    /// Cash every agent has put aside for the lots of all the companies
+    pub fn escrowed_cash(&self) -> HashMap<u64, Money> {
+        let mut escrowed_cash = HashMap::new();
+        for (agent_id, cash) in self.lots.iter().flat_map(|lots| lots.escrowed_cash()) {
+            *escrowed_cash.entry(agent_id).or_default() += cash;
+        }
+        escrowed_cash
+    }
+    /// Returns the ids of the companies which went bankrupt during this news cycle
+    pub fn rand_release_news(
+        &mut self,
+        agents: &mut Agents,
+        economy: &Economy,
+        rng: &mut impl Rng,
+    ) -> Vec<u64> {
+        self.tick_hype();
         let mut hypeable_companies = Vec::new();
+        let mut bankrupt_companies = Vec::new();
+        let sector_shocks = (0..NUM_OF_SECTORS)
+            .map(|_| StandardNormal.sample(rng))
+            .collect::<Vec<f64>>();
         for id in 0..self.num_of_companies {
+            if !self.is_listed(id) {
+                continue;
+            }
             // for now, we distribute shares after news update
-            self.lots[id as usize].finalize(id, agents);
-            if rng.gen_ratio(1, 10) {
-                // 10% chance of re-releasing shares
-                let failable_value = rng.gen_range(10.0..2_000.0);
-                let current_price = self.get_current_price(id).unwrap_or(failable_value);
-                self.lots[id as usize].rng_reset_exact_price(rng, current_price);
+            if let Err(e) = self.finalize_lots(id, agents, rng) {
+                log!(warn "Failed to finalize the lots of company {}\n{:?}", id, e);
             }
+            // cash earns interest and profits grow with inflation
+            let balance = &mut self.balances[id as usize];
+            *balance += max(*balance, Money::ZERO).scale(economy.interest_per_cycle());
+            self.expected_profits[id as usize] *= 1.0 + economy.inflation_per_cycle();
 
             let expected_profit = self.expected_profits[id as usize];
             let Ok(normal) = Normal::new(0.0, 100.0 / expected_profit) else {
                 // If the normal distribution fails, we just add the expected profit
-                self.balances[id as usize] += expected_profit;
+                self.balances[id as usize] += Money::from(expected_profit);
                 continue;
             };
-            let deviation: f64 = normal.sample(rng);
-            let Some(hypeable_news) = self.release_news(id, deviation) else {
+            // part of the news is shared by the whole sector
+            let sector_shock = sector_shocks
+                .get(self.sectors[id as usize] as usize)
+                .copied()
+                .unwrap_or_default();
+            let deviation: f64 = economy.profit_drift()
+                + SECTOR_NEWS_CORRELATION.sqrt() * sector_shock * normal.std_dev()
+                + (1.0 - SECTOR_NEWS_CORRELATION).sqrt() * normal.sample(rng);
+            let hypeable_news = self.release_earnings(id, deviation);
+            if self.update_distress(id) {
+                bankrupt_companies.push(id);
+                continue;
+            }
+            self.rand_consider_follow_on_offering(id, rng);
+            let Some(hypeable_news) = hypeable_news else {
                 continue;
             };
             hypeable_companies.push((id, hypeable_news));
         }
         self.send_hype(&mut hypeable_companies);
+        bankrupt_companies
+    }
+    /// Distributes the shares of the lots and collects the money raised
+    pub fn finalize_lots(
+        &mut self,
+        company_id: u64,
+        agents: &mut Agents,
+        rng: &mut impl Rng,
+    ) -> Result<Vec<CompanyTransaction>, SimulationError> {
+        let id = company_id as usize;
+        let transactions = self.lots[id].finalize(company_id, agents, rng)?;
+        let raised = transactions
+            .iter()
+            .map(|transaction| transaction.strike_price.times(transaction.number_of_shares))
+            .sum::<Money>();
+        let new_shares = transactions
+            .iter()
+            .map(|transaction| transaction.number_of_shares)
+            .sum::<u64>();
+        self.balances[id] += raised;
+        if new_shares == 0 {
+            return Ok(transactions);
+        }
+
+        // the value of the company is now split between more shares
+        let old_shares = self.shares_outstanding[id];
+        let market_value = &mut self.market_values[id];
+        market_value.current_price = (market_value.current_price * old_shares as f64
+            + raised.to_f64())
+            / (old_shares + new_shares) as f64;
+        self.shares_outstanding[id] += new_shares;
+        Ok(transactions)
+    }
+    /// Opens lots sized to the cash the company needs if its balance is running low
+    ///
+    /// Returns true if an offering was opened
+    pub fn rand_consider_follow_on_offering(
+        &mut self,
+        company_id: u64,
+        rng: &mut impl Rng,
+    ) -> bool {
+        let id = company_id as usize;
+        let expected_profit = self.expected_profits[id];
+        if !self.lots[id].is_blank() || expected_profit <= 0.0 {
+            return false;
+        }
+        let balance = self.balances[id].to_f64();
+        if balance / expected_profit >= FOLLOW_ON_RUNWAY_THRESHOLD {
+            return false;
+        }
+        let cash_need = expected_profit * FOLLOW_ON_TARGET_RUNWAY - balance;
+        let current_price = self.market_values[id].current_price;
+        let strike_price = max(MIN_STRIKE_PRICE, current_price * (1.0 - FOLLOW_ON_DISCOUNT));
+        let lot_size = rng.gen_range(1..10) * 10;
+        let number_of_lots = (cash_need / (strike_price * lot_size as f64)).ceil() as u64;
+        self.lots[id] = Lots::new(strike_price, number_of_lots, lot_size);
+        log!(info "Follow-on offering: company_id: {}, cash_need: {}, strike_price: {}, number_of_lots: {}, lot_size: {}", company_id, cash_need, strike_price, number_of_lots, lot_size);
+        true
+    }
+    /// Returns true once the company has spent `BANKRUPTCY_NEWS_CYCLES` in distress
+    pub fn update_distress(&mut self, company_id: u64) -> bool {
+        let id = company_id as usize;
+        if self.balances[id] >= Money::from(BANKRUPTCY_BALANCE_THRESHOLD) {
+            self.distress_cycles[id] = 0;
+            return false;
+        }
+        self.distress_cycles[id] += 1;
+        self.distress_cycles[id] >= BANKRUPTCY_NEWS_CYCLES
+    }
+    /// Removes the company from the simulation
+    ///
+    /// The offers in the `TradeHouse` need to be cancelled before this, so that the shares
+    /// sitting in the sell offers are returned to the holders. The ETFs holding the company
+    /// get their part of the recovery like any other holder
+    pub fn delist(
+        &mut self,
+        company_id: u64,
+        agents: &mut Agents,
+        etfs: &mut Etfs,
+    ) -> Result<(), SimulationError> {
+        let id = company_id as usize;
+        self.lots[id].cancel(agents)?;
+
+        let positions = agents.holdings.remove_company(company_id);
+        let fund_positions = etfs.remove_company(company_id);
+        let total_shares: u64 = positions
+            .iter()
+            .chain(fund_positions.iter())
+            .map(|(_, shares)| shares)
+            .sum();
+        let recoverable = max(self.balances[id], Money::ZERO).scale(DELISTING_RECOVERY_RATE);
+        let recovery_per_share = if total_shares != 0 {
+            // rounded down, so the company never pays out more than it recovers
+            Money(recoverable.0 / total_shares as i64)
+        } else {
+            Money::ZERO
+        };
+        for (agent_id, number_of_shares) in positions {
+            // the shareholders lose whatever isn't recovered
+            agents.realize(
+                agent_id,
+                InstrumentId::Equity(company_id),
+                number_of_shares,
+                recovery_per_share,
+            );
+            if recovery_per_share == Money::ZERO {
+                continue;
+            }
+            agents
+                .balances
+                .add(agent_id, recovery_per_share.times(number_of_shares))?;
+        }
+        // the funds keep their part of the recovery as cash for their units
+        for (etf_id, number_of_shares) in fund_positions {
+            if let Some(etf) = etfs.etfs.get_mut(etf_id as usize) {
+                etf.cash += recovery_per_share.times(number_of_shares);
+            }
+        }
+        // the company pays out exactly what the shareholders got
+        self.balances[id] -= recovery_per_share.times(total_shares);
+        agents.forget_company(company_id);
+
+        self.listed[id] = false;
+        self.news[id] = 0.0;
+        if let Some(sector_hype) = self.hype.get_mut(self.sectors[id] as usize) {
+            for hype in sector_hype.iter_mut() {
+                if matches!(hype, Some(hype) if hype.company_id == company_id) {
+                    *hype = None;
+                }
+            }
+        }
+        log!(info "Delisted: company_id: {}, recovered: {}, shares wiped: {}", company_id, recoverable, total_shares);
+        Ok(())
     }
     pub fn release_news(&mut self, company_id: u64, deviation: f64) -> Option<f64> {
         let id = company_id as usize;
-        let balance = &mut self.balances[id];
-        let news = deviation * 100.0;
-        *balance += self.expected_profits[id] * deviation;
-        self.news[id] = news;
+        self.balances[id] += Money::from(self.expected_profits[id] * deviation);
+        self.publish_news(company_id, deviation * 100.0)
+    }
+    /// Profit the analysts expect from the company this news cycle
+    pub fn consensus_estimate(&self, company_id: u64) -> f64 {
+        let id = company_id as usize;
+        let expected_profit = self.expected_profits[id];
+        let Some(last_report) = self.earnings_reports[id] else {
+            return expected_profit;
+        };
+        ANALYST_GUIDANCE_WEIGHT * last_report.guidance
+            + (1.0 - ANALYST_GUIDANCE_WEIGHT) * expected_profit
+    }
+    /// Reports the profit of a company deviating from its expected profit
+    ///
+    /// Unlike `release_news`, the news is the surprise versus the consensus
+    /// and the price jumps with it
+    pub fn release_earnings(&mut self, company_id: u64, deviation: f64) -> Option<f64> {
+        let id = company_id as usize;
+        let expected_profit = self.expected_profits[id];
+        let profit = expected_profit * (1.0 + deviation);
+        // a company in distress doesn't promise to carry its good quarter forward
+        let outlook = if self.balances[id] < Money::from(BANKRUPTCY_BALANCE_THRESHOLD) {
+            min(deviation, 0.0)
+        } else {
+            deviation
+        };
+        let guidance = expected_profit * (1.0 + outlook * GUIDANCE_PERSISTENCE);
+        let report = EarningsReport::new(profit, guidance, self.consensus_estimate(company_id));
+        self.balances[id] += Money::from(expected_profit * deviation);
+        self.earnings_reports[id] = Some(report);
+
+        let market_value = &mut self.market_values[id];
+        market_value.current_price = max(
+            MIN_STRIKE_PRICE,
+            market_value.current_price * (1.0 + report.surprise * EARNINGS_PRICE_REACTION),
+        );
+        log!(info "Earnings: company_id: {}, profit: {}, consensus: {}, surprise: {}", company_id, report.profit, report.consensus, report.surprise);
+        self.publish_news(company_id, report.surprise)
+    }
+    /// Sets the news of a company, returns the news if it is strong enough to hype
+    fn publish_news(&mut self, company_id: u64, news: f64) -> Option<f64> {
+        self.news[company_id as usize] = news;
         if (MAX_PROFIT_PERCENT_FOR_NEGATIVE_HYPE_CONSIDERATION
             ..=MIN_PROFIT_PERCENT_FOR_POSITIVE_HYPE_CONSIDERATION)
             .contains(&news)
@@ -407,31 +1240,74 @@ impl Companies {
         }
         Some(news)
     }
-    pub fn send_hype(&mut self, hypeable_companies: &mut Vec<(u64, f64)>) {
-        let mut item = hypeable_companies.pop();
-        for hype in self.hype.iter_mut() {
-            let Some(clean_item) = item else {
-                return;
-            };
-            let Some(hype) = hype else {
-                *hype = Some(clean_item);
-                item = hypeable_companies.pop();
-                continue;
-            };
-            if clean_item.1.abs() < hype.1.abs() {
-                continue;
+    /// Decays the hype, removing the hype which has died out
+    pub fn tick_hype(&mut self) {
+        for hype in self.hype.iter_mut().flatten() {
+            if hype.as_mut().is_some_and(|hype| !hype.tick()) {
+                *hype = None;
             }
-            *hype = clean_item;
-            item = hypeable_companies.pop();
         }
     }
+    /// Forces hype onto a company, replacing the weakest hype of its sector
+    pub fn inject_hype(&mut self, company_id: u64, strength: f64) {
+        let Some(sector_hype) = self
+            .hype
+            .get_mut(self.sectors[company_id as usize] as usize)
+        else {
+            return;
+        };
+        let Some(weakest) = sector_hype.iter_mut().min_by(|a, b| {
+            let strength = |hype: &Option<Hype>| hype.map_or(0.0, |hype| hype.strength.abs());
+            strength(a).total_cmp(&strength(b))
+        }) else {
+            return;
+        };
+        *weakest = Some(Hype::new(company_id, strength));
+    }
+    /// Every sector hypes its own companies
+    pub fn send_hype(&mut self, hypeable_companies: &mut Vec<(u64, f64)>) {
+        for (sector, hype) in self.hype.iter_mut().enumerate() {
+            let mut sector_hypeable_companies = hypeable_companies
+                .iter()
+                .filter(|(id, _)| self.sectors[*id as usize] == sector as u64)
+                .copied()
+                .collect();
+            fill_hype(hype, &mut sector_hypeable_companies);
+        }
+        hypeable_companies.clear();
+    }
     pub fn generate_preferences_from_news(&self, rng: &mut impl Rng) -> Vec<(u64, TradeAction)> {
         let mut output = Vec::with_capacity(1000);
+        // hyped companies get picked by more agents
+        for hype in self.hype.iter().flatten().flatten() {
+            if !self.is_listed(hype.company_id) {
+                continue;
+            }
+            let action = if hype.strength > 0.0 {
+                TradeAction::Buy
+            } else {
+                TradeAction::Sell
+            };
+            let count = (hype.strength.abs() * HYPE_PREFERENCE_WEIGHT).round() as usize;
+            output.extend(std::iter::repeat_n((hype.company_id, action), count));
+        }
+        if !self
+            .news
+            .iter()
+            .zip(self.listed.iter())
+            .any(|(&news, &listed)| listed && news != 0.0)
+        {
+            return output;
+        }
+        let news_output_size = output.len() + 1000;
         let mut news_iter = self.news.iter().enumerate().cycle();
-        while output.len() != 1000 {
+        while output.len() != news_output_size {
             let Some((company_id, &news)) = news_iter.next() else {
                 break;
             };
+            if !self.listed[company_id] {
+                continue;
+            }
             let probability = news_to_probability(news);
             if !rng.gen_bool(probability) {
                 continue;
@@ -448,23 +1324,55 @@ impl Companies {
     pub fn release_shares(&mut self, company_id: u64, number_of_lots: u64, strike_price: f64) {
         let lots = &mut self.lots[company_id as usize];
         lots.number_of_lots = number_of_lots;
-        lots.strike_price = strike_price;
+        lots.strike_price = Money::from(strike_price);
     }
     pub fn check_lot(&self, company_id: u64) -> bool {
         // Ya, this is the way it happens in real life, idk why
         self.lots[company_id as usize].number_of_lots != 0
     }
     pub fn check_lots_from_todotransaction(&self, todo_transaction: &TodoTransaction) -> bool {
-        self.check_lot(todo_transaction.company_id)
+        todo_transaction
+            .instrument_id
+            .equity()
+            .is_some_and(|company_id| self.check_lot(company_id))
     }
-    pub fn add_bet_from_todotransaction(&mut self, todo_transaction: &TodoTransaction) {
-        let lot = &mut self.lots[todo_transaction.company_id as usize];
+    /// The bet is paid for at the price of the lots, not the strike price of the agent
+    pub fn add_bet_from_todotransaction(
+        &mut self,
+        agents: &mut Agents,
+        todo_transaction: &TodoTransaction,
+    ) -> Result<(), SimulationError> {
+        let Some(company_id) = todo_transaction.instrument_id.equity() else {
+            return Err(SimulationError::UnDoable);
+        };
+        // hyped companies get bigger bets
+        let demand_multiplier =
+            1.0 + HYPE_IPO_DEMAND_BOOST * max(self.company_hype(company_id), 0.0) / 100.0;
+        let lot = &mut self.lots[company_id as usize];
         if lot.lot_size == 0 {
-            return;
+            return Err(SimulationError::UnDoable);
         }
-        lot.add_bet(
+        let number_of_lots = (todo_transaction.trade.number_of_shares as f64 * demand_multiplier
+            / lot.lot_size as f64)
+            .round() as u64;
+        if number_of_lots == 0 {
+            return Err(SimulationError::UnDoable);
+        }
+        if !lot.is_book_building() {
+            return lot.add_bet_and_update_agent(agents, todo_transaction.agent_id, number_of_lots);
+        }
+        let price =
+            self.book_building_bid_price(company_id, todo_transaction.strike_price.to_f64());
+        self.lots[company_id as usize].add_bid_and_update_agent(
+            agents,
             todo_transaction.agent_id,
-            (todo_transaction.trade.number_of_shares as f64 / lot.lot_size as f64).round() as u64,
-        );
+            Money::from(price).floor_to(self.tick_sizes[company_id as usize]),
+            number_of_lots,
+        )
+    }
+    /// Good news and hype make agents bid higher in a book-built IPO
+    pub fn book_building_bid_price(&self, company_id: u64, strike_price: f64) -> f64 {
+        let sentiment = self.news[company_id as usize] + self.company_hype(company_id);
+        strike_price * (1.0 + BOOK_BUILDING_SENTIMENT_WEIGHT * sentiment / 100.0)
     }
 }
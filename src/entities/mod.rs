@@ -1,27 +1,75 @@
-use crate::SimulationError;
+use crate::{currency::HOME_CURRENCY, money::Money, SimulationError};
+use std::collections::HashMap;
 
 pub mod agents;
 pub mod companies;
 
 #[derive(Debug, Clone, Default)]
-pub struct Balances(pub Vec<f64>);
+pub struct Balances {
+    /// Cash of every agent in the home currency
+    pub home: Vec<Money>,
+    /// `(agent_id, currency)` to the cash held in the other currencies
+    foreign: HashMap<(u64, u64), Money>,
+}
 
 impl Balances {
-    pub fn get(&self, agent_id: u64) -> Result<f64, SimulationError> {
-        let Some(balance) = self.0.get(agent_id as usize) else {
+    pub fn new(home: Vec<Money>) -> Self {
+        Self {
+            home,
+            foreign: HashMap::new(),
+        }
+    }
+    pub fn get(&self, agent_id: u64) -> Result<Money, SimulationError> {
+        let Some(balance) = self.home.get(agent_id as usize) else {
             return Err(SimulationError::AgentNotFound(agent_id));
         };
         Ok(*balance)
     }
-    pub fn add(&mut self, agent_id: u64, amount: f64) -> Result<(), SimulationError> {
-        let Some(balance) = self.0.get_mut(agent_id as usize) else {
+    pub fn add(&mut self, agent_id: u64, amount: Money) -> Result<(), SimulationError> {
+        let Some(balance) = self.home.get_mut(agent_id as usize) else {
             return Err(SimulationError::AgentNotFound(agent_id));
         };
         let result = *balance + amount;
-        if result < 0.0 {
+        if result < Money::ZERO {
             return Err(SimulationError::Unspendable);
         }
         *balance = result;
         Ok(())
     }
+    /// Cash of the agent in the given currency
+    pub fn get_in(&self, agent_id: u64, currency: u64) -> Result<Money, SimulationError> {
+        if currency == HOME_CURRENCY {
+            return self.get(agent_id);
+        }
+        if agent_id as usize >= self.home.len() {
+            return Err(SimulationError::AgentNotFound(agent_id));
+        }
+        Ok(self
+            .foreign
+            .get(&(agent_id, currency))
+            .copied()
+            .unwrap_or_default())
+    }
+    pub fn add_in(
+        &mut self,
+        agent_id: u64,
+        currency: u64,
+        amount: Money,
+    ) -> Result<(), SimulationError> {
+        if currency == HOME_CURRENCY {
+            return self.add(agent_id, amount);
+        }
+        let result = self.get_in(agent_id, currency)? + amount;
+        if result < Money::ZERO {
+            return Err(SimulationError::Unspendable);
+        }
+        self.foreign.insert((agent_id, currency), result);
+        Ok(())
+    }
+    /// `(agent_id, currency, balance)` of the cash held in the other currencies
+    pub fn foreign(&self) -> impl Iterator<Item = (u64, u64, Money)> + '_ {
+        self.foreign
+            .iter()
+            .map(|(&(agent_id, currency), &balance)| (agent_id, currency, balance))
+    }
 }
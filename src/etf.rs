@@ -0,0 +1,439 @@
+use crate::{
+    entities::{agents::Agents, companies::Companies},
+    index::{IndexWeighting, MarketIndex},
+    instrument::InstrumentId,
+    log,
+    logger::Log,
+    max, min,
+    money::Money,
+    strategy::AgentStrategy,
+    trade_house::{Trade, TradeAction},
+    transaction::TodoTransaction,
+    SimulationError, MIN_STRIKE_PRICE,
+};
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+/// Number of units of an ETF created or redeemed at once
+pub const CREATION_UNIT_SIZE: u64 = 1_000;
+/// Value of a unit of an ETF when it is launched
+pub const ETF_LAUNCH_PRICE: f64 = 100.0;
+/// Premium (or discount) to the NAV past which the authorized participants step in
+pub const ARBITRAGE_THRESHOLD: f64 = 0.01;
+/// Largest portion of the value of a creation unit which can be paid in cash instead of shares
+pub const MAX_CASH_IN_LIEU: f64 = 0.2;
+/// Fee, as a portion of the value of the missing shares, charged on the cash paid in lieu of them
+pub const CASH_IN_LIEU_FEE: f64 = 0.005;
+/// Money an authorized participant starts with
+pub const AUTHORIZED_PARTICIPANT_CAPITAL: f64 = 10_000_000.0;
+/// Chance of a retail agent trading an ETF instead of a company
+pub const ETF_TRADING_PROBABILITY: f64 = 0.02;
+/// Largest gap, as a portion of the NAV, between a retail offer for ETF units and the NAV
+pub const ETF_PRICE_NOISE: f64 = 0.02;
+
+/// A fund holding the basket of an index, traded in units on its own book
+///
+/// The shares delivered for the units are held by the fund and don't belong to any agent
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Etf {
+    pub id: u64,
+    /// Weighting of the index the ETF tracks
+    pub weighting: IndexWeighting,
+    /// `(company_id, number_of_shares)` delivered for every creation unit, sorted by company
+    pub basket: Vec<(u64, u64)>,
+    /// `(company_id, number_of_shares)` the fund holds for the units outstanding, sorted by company
+    ///
+    /// Apart from the basket, it keeps the constituents the index dropped since the creations
+    pub holdings: Vec<(u64, u64)>,
+    /// Cash paid in lieu of missing shares, its fee included, held for the units outstanding
+    pub cash: Money,
+    pub units_outstanding: u64,
+    /// Price of the last trades of the units, free to drift away from the NAV
+    pub price: Money,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Etfs {
+    pub etfs: Vec<Etf>,
+    /// Agent ids of the authorized participants, the only agents allowed to create and redeem units
+    pub authorized_participants: Vec<u64>,
+}
+
+/// Shares of the index constituents worth `value`, in proportion to their weight
+fn basket(index: &MarketIndex, companies: &Companies, value: f64) -> Vec<(u64, u64)> {
+    index
+        .weights(companies)
+        .into_iter()
+        .filter_map(|(company_id, weight)| {
+            let price = companies.get_current_price(company_id)?;
+            if price <= 0.0 {
+                return None;
+            }
+            let number_of_shares = (weight * value / price).round() as u64;
+            (number_of_shares != 0).then_some((company_id, number_of_shares))
+        })
+        .collect()
+}
+
+/// Value of the `(company_id, number_of_shares)` at the current prices
+fn value(shares: &[(u64, u64)], companies: &Companies) -> f64 {
+    shares
+        .iter()
+        .map(|(company_id, number_of_shares)| {
+            companies.get_current_price(*company_id).unwrap_or(0.0) * *number_of_shares as f64
+        })
+        .sum()
+}
+
+impl Etf {
+    pub fn new(id: u64, index: &MarketIndex, companies: &Companies) -> Self {
+        let mut etf = Self {
+            id,
+            weighting: index.weighting,
+            basket: basket(
+                index,
+                companies,
+                ETF_LAUNCH_PRICE * CREATION_UNIT_SIZE as f64,
+            ),
+            holdings: Vec::new(),
+            cash: Money::ZERO,
+            units_outstanding: 0,
+            price: Money::from(ETF_LAUNCH_PRICE),
+        };
+        let nav = etf.nav(companies);
+        if nav > 0.0 {
+            etf.price = Money::from(nav);
+        }
+        etf
+    }
+    /// Value of the holdings and cash behind a single unit, at the current prices,
+    /// or of the basket of a creation unit while there are no units
+    pub fn nav(&self, companies: &Companies) -> f64 {
+        if self.units_outstanding == 0 {
+            return value(&self.basket, companies) / CREATION_UNIT_SIZE as f64;
+        }
+        (value(&self.holdings, companies) + self.cash.to_f64()) / self.units_outstanding as f64
+    }
+    fn deposit(&mut self, company_id: u64, number_of_shares: u64) {
+        match self
+            .holdings
+            .binary_search_by_key(&company_id, |(id, _)| *id)
+        {
+            Ok(i) => self.holdings[i].1 += number_of_shares,
+            Err(i) => self.holdings.insert(i, (company_id, number_of_shares)),
+        }
+    }
+    /// Portion by which the price is over the NAV, negative for a discount
+    pub fn premium(&self, companies: &Companies) -> Option<f64> {
+        let nav = self.nav(companies);
+        (nav > 0.0).then(|| self.price.to_f64() / nav - 1.0)
+    }
+}
+
+impl Etfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Lists a new ETF tracking the index, returns its id
+    pub fn launch(&mut self, index: &MarketIndex, companies: &Companies) -> u64 {
+        let etf_id = self.etfs.len() as u64;
+        let etf = Etf::new(etf_id, index, companies);
+        log!(info "New ETF: etf_id: {}, weighting: {:?}, basket size: {}, price: {}", etf_id, etf.weighting, etf.basket.len(), etf.price);
+        self.etfs.push(etf);
+        etf_id
+    }
+    /// Creates a market maker as a new agent, returns its agent id
+    pub fn found_authorized_participant(&mut self, agents: &mut Agents) -> u64 {
+        let agent_ids = agents.create_agents(1, &mut vec![AUTHORIZED_PARTICIPANT_CAPITAL]);
+        agents.assign_strategies(|_| AgentStrategy::AuthorizedParticipant, &agent_ids);
+        self.authorized_participants.push(agent_ids[0]);
+        agent_ids[0]
+    }
+    /// The ETFs tracking the index follow its new constituents with the next creations,
+    /// keeping the value of a creation unit
+    ///
+    /// The holdings are left alone, the redemptions hand them out until they're gone
+    pub fn rebalance(&mut self, index: &MarketIndex, companies: &Companies) {
+        for etf in self
+            .etfs
+            .iter_mut()
+            .filter(|etf| etf.weighting == index.weighting)
+        {
+            let basket_value = value(&etf.basket, companies);
+            if basket_value > 0.0 {
+                etf.basket = basket(index, companies, basket_value);
+            }
+        }
+    }
+    /// Drops a delisted company from the baskets and the holdings,
+    /// returning the `(etf_id, number_of_shares)` the funds held
+    pub fn remove_company(&mut self, company_id: u64) -> Vec<(u64, u64)> {
+        let mut positions = Vec::new();
+        for etf in self.etfs.iter_mut() {
+            etf.basket.retain(|(id, _)| *id != company_id);
+            if let Some(&(_, number_of_shares)) =
+                etf.holdings.iter().find(|(id, _)| *id == company_id)
+            {
+                positions.push((etf.id, number_of_shares));
+            }
+            etf.holdings.retain(|(id, _)| *id != company_id);
+        }
+        positions
+    }
+    /// Takes the basket of a creation unit from the agent in exchange for `CREATION_UNIT_SIZE` units
+    ///
+    /// The shares the agent is missing are paid for in cash, up to `MAX_CASH_IN_LIEU` of the basket,
+    /// which the fund keeps along with the shares
+    pub fn create(
+        &mut self,
+        agents: &mut Agents,
+        companies: &Companies,
+        etf_id: u64,
+        agent_id: u64,
+    ) -> Result<(), SimulationError> {
+        let Some(etf) = self.etfs.get_mut(etf_id as usize) else {
+            return Err(SimulationError::NoData);
+        };
+        if etf.basket.is_empty() {
+            return Err(SimulationError::Unspendable);
+        }
+        let price = |company_id: u64| companies.get_current_price(company_id).unwrap_or(0.0);
+        let missing_value = etf
+            .basket
+            .iter()
+            .map(|(company_id, number_of_shares)| {
+                let held = agents
+                    .holdings
+                    .get(agent_id, InstrumentId::Equity(*company_id));
+                price(*company_id) * number_of_shares.saturating_sub(held) as f64
+            })
+            .sum::<f64>();
+        if missing_value > MAX_CASH_IN_LIEU * value(&etf.basket, companies) {
+            return Err(SimulationError::Unspendable);
+        }
+        if missing_value > 0.0 {
+            let cash_in_lieu = Money::from(missing_value * (1.0 + CASH_IN_LIEU_FEE));
+            agents.balances.add(agent_id, -cash_in_lieu)?;
+            etf.cash += cash_in_lieu;
+        }
+        for (company_id, number_of_shares) in etf.basket.clone() {
+            let delivered = min(
+                number_of_shares,
+                agents
+                    .holdings
+                    .get(agent_id, InstrumentId::Equity(company_id)),
+            );
+            if delivered == 0 {
+                continue;
+            }
+            agents
+                .holdings
+                .pop(agent_id, InstrumentId::Equity(company_id), delivered)?;
+            etf.deposit(company_id, delivered);
+            // delivering the shares is selling them to the fund at their current price
+            agents.realize(
+                agent_id,
+                InstrumentId::Equity(company_id),
+                delivered,
+                price(company_id),
+            );
+            agents.close_position_if_sold(agent_id, InstrumentId::Equity(company_id));
+        }
+        agents
+            .holdings
+            .push(agent_id, InstrumentId::Etf(etf_id), CREATION_UNIT_SIZE);
+        etf.units_outstanding += CREATION_UNIT_SIZE;
+        Ok(())
+    }
+    /// Gives the agent its part of the holdings and cash of the fund in exchange for
+    /// `CREATION_UNIT_SIZE` units
+    ///
+    /// Returns the `(company_id, number_of_shares)` handed out
+    pub fn redeem(
+        &mut self,
+        agents: &mut Agents,
+        companies: &Companies,
+        etf_id: u64,
+        agent_id: u64,
+    ) -> Result<Vec<(u64, u64)>, SimulationError> {
+        let Some(etf) = self.etfs.get_mut(etf_id as usize) else {
+            return Err(SimulationError::NoData);
+        };
+        if etf.units_outstanding < CREATION_UNIT_SIZE {
+            return Err(SimulationError::UnDoable);
+        }
+        agents
+            .holdings
+            .pop(agent_id, InstrumentId::Etf(etf_id), CREATION_UNIT_SIZE)?;
+        // the last units take whatever the rounding left behind
+        let part = |amount: u64| {
+            (amount as u128 * CREATION_UNIT_SIZE as u128 / etf.units_outstanding as u128) as u64
+        };
+        let shares = etf
+            .holdings
+            .iter()
+            .map(|(company_id, number_of_shares)| (*company_id, part(*number_of_shares)))
+            .filter(|(_, number_of_shares)| *number_of_shares != 0)
+            .collect::<Vec<_>>();
+        let cash = Money(part(max(etf.cash.0, 0) as u64) as i64);
+        etf.units_outstanding -= CREATION_UNIT_SIZE;
+        etf.cash -= cash;
+        agents.balances.add(agent_id, cash)?;
+        for (company_id, number_of_shares) in shares.iter() {
+            if let Some(held) = etf.holdings.iter_mut().find(|(id, _)| id == company_id) {
+                held.1 -= number_of_shares;
+            }
+            let current_price = companies
+                .get_current_price(*company_id)
+                .unwrap_or(MIN_STRIKE_PRICE);
+            agents.receive_shares(
+                agent_id,
+                InstrumentId::Equity(*company_id),
+                *number_of_shares,
+                current_price,
+            );
+        }
+        etf.holdings
+            .retain(|(_, number_of_shares)| *number_of_shares != 0);
+        Ok(shares)
+    }
+    /// An order of a retail agent for the units of a random ETF, worth up to `budget`
+    ///
+    /// Retail agents go by the published NAV, their noise is what drifts the price away from it
+    pub fn rand_retail_order(
+        &self,
+        rng: &mut impl Rng,
+        agents: &Agents,
+        companies: &Companies,
+        agent_id: u64,
+        budget: f64,
+    ) -> Option<TodoTransaction> {
+        let etf = self.etfs.choose(rng)?;
+        let strike_price = max(
+            MIN_STRIKE_PRICE,
+            etf.nav(companies) * (1.0 + rng.gen_range(-ETF_PRICE_NOISE..ETF_PRICE_NOISE)),
+        );
+        let number_of_units_held = agents.holdings.get(agent_id, InstrumentId::Etf(etf.id));
+        let (action, number_of_units) = if number_of_units_held != 0 && rng.gen_bool(0.5) {
+            (TradeAction::Sell, number_of_units_held)
+        } else {
+            (TradeAction::Buy, (budget / strike_price).floor() as u64)
+        };
+        (number_of_units != 0).then_some(TodoTransaction {
+            agent_id,
+            instrument_id: InstrumentId::Etf(etf.id),
+            strike_price: Money::from(strike_price),
+            action,
+            trade: Trade::new(number_of_units),
+        })
+    }
+    /// The authorized participants close the gaps between the prices of the ETFs and their NAV
+    ///
+    /// Over the NAV they gather the basket, create units and sell them,
+    /// under it they buy units, redeem them and sell the basket.
+    /// Returns the orders for company shares and the orders for ETF units
+    pub fn arbitrage(
+        &mut self,
+        agents: &mut Agents,
+        companies: &Companies,
+    ) -> (Vec<TodoTransaction>, Vec<TodoTransaction>) {
+        let mut share_orders = Vec::new();
+        let mut unit_orders = Vec::new();
+        let price = |company_id: u64| {
+            companies
+                .get_current_price(company_id)
+                .map(|price| max(price, MIN_STRIKE_PRICE))
+        };
+        for agent_id in self.authorized_participants.clone() {
+            for etf_id in 0..self.etfs.len() as u64 {
+                let etf = &self.etfs[etf_id as usize];
+                let Some(premium) = etf.premium(companies) else {
+                    continue;
+                };
+                let etf_price = max(etf.price, Money::from(MIN_STRIKE_PRICE));
+                if premium > ARBITRAGE_THRESHOLD {
+                    if self.create(agents, companies, etf_id, agent_id).is_ok() {
+                        log!(info "ETF {} units created by {} at a premium of {}", etf_id, agent_id, premium);
+                    }
+                    let number_of_units = agents.holdings.get(agent_id, InstrumentId::Etf(etf_id));
+                    if number_of_units != 0 {
+                        unit_orders.push(TodoTransaction {
+                            agent_id,
+                            instrument_id: InstrumentId::Etf(etf_id),
+                            strike_price: etf_price,
+                            action: TradeAction::Sell,
+                            trade: Trade::new(number_of_units),
+                        });
+                        continue;
+                    }
+                    let missing = self.etfs[etf_id as usize]
+                        .basket
+                        .iter()
+                        .filter_map(|(company_id, number_of_shares)| {
+                            let held = agents
+                                .holdings
+                                .get(agent_id, InstrumentId::Equity(*company_id));
+                            (held < *number_of_shares)
+                                .then(|| (*company_id, *number_of_shares - held))
+                        })
+                        .collect::<Vec<_>>();
+                    let cost = missing
+                        .iter()
+                        .map(|(company_id, number_of_shares)| {
+                            price(*company_id).unwrap_or(0.0) * *number_of_shares as f64
+                        })
+                        .sum::<f64>();
+                    if cost > agents.balances.get(agent_id).map_or(0.0, Money::to_f64) {
+                        continue;
+                    }
+                    // paying up to half the premium still leaves a profit
+                    share_orders.extend(missing.into_iter().filter_map(
+                        |(company_id, number_of_shares)| {
+                            Some(TodoTransaction {
+                                agent_id,
+                                instrument_id: InstrumentId::Equity(company_id),
+                                strike_price: Money::from(
+                                    price(company_id)? * (1.0 + premium / 2.0),
+                                ),
+                                action: TradeAction::Buy,
+                                trade: Trade::new(number_of_shares),
+                            })
+                        },
+                    ));
+                } else if premium < -ARBITRAGE_THRESHOLD {
+                    if let Ok(shares) = self.redeem(agents, companies, etf_id, agent_id) {
+                        log!(info "ETF {} units redeemed by {} at a discount of {}", etf_id, agent_id, -premium);
+                        share_orders.extend(shares.into_iter().filter_map(
+                            |(company_id, number_of_shares)| {
+                                Some(TodoTransaction {
+                                    agent_id,
+                                    instrument_id: InstrumentId::Equity(company_id),
+                                    strike_price: Money::from(price(company_id)?),
+                                    action: TradeAction::Sell,
+                                    trade: Trade::new(number_of_shares),
+                                })
+                            },
+                        ));
+                        continue;
+                    }
+                    let number_of_units = CREATION_UNIT_SIZE
+                        .saturating_sub(agents.holdings.get(agent_id, InstrumentId::Etf(etf_id)));
+                    if !agents
+                        .can_buy(agent_id, etf_price, number_of_units)
+                        .unwrap_or(false)
+                    {
+                        continue;
+                    }
+                    unit_orders.push(TodoTransaction {
+                        agent_id,
+                        instrument_id: InstrumentId::Etf(etf_id),
+                        strike_price: etf_price,
+                        action: TradeAction::Buy,
+                        trade: Trade::new(number_of_units),
+                    });
+                }
+            }
+        }
+        (share_orders, unit_orders)
+    }
+}
@@ -0,0 +1,451 @@
+use crate::{
+    currency::{Currencies, HOME_CURRENCY},
+    entities::{agents::Agents, companies::Companies},
+    etf::Etfs,
+    instrument::InstrumentId,
+    log,
+    logger::Log,
+    market::{FeeSchedule, Market},
+    money::Money,
+    strategy::AgentStrategy,
+    trade_house::{FailedOffer, Trade, TradeAction},
+    transaction::TodoTransaction,
+    SimulationError,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Chance of a company being listed on each of the other exchanges besides the primary one
+pub const CROSS_LISTING_PROBABILITY: f64 = 0.5;
+/// Money an arbitrageur starts with
+pub const ARBITRAGEUR_CAPITAL: f64 = 1_000_000.0;
+/// Portion of its balance an arbitrageur puts into a single opportunity
+pub const ARBITRAGE_ORDER_SIZE: f64 = 0.1;
+
+/// A venue with its own book, fees, currency and distance from the agents
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Exchange {
+    pub id: u64,
+    pub market: Market,
+    /// Number of ticks an order takes to reach the exchange
+    pub latency: u64,
+    /// Companies traded on the exchange besides the primary one, which lists them all
+    pub listings: HashSet<u64>,
+}
+
+/// Every exchange, the first one being the primary exchange
+///
+/// Only the primary exchange lists the ETFs, the bonds and the currencies, its halts stop the company
+/// everywhere, and the trades on the other exchanges are folded into its prices and volumes.
+/// The orders are priced in the home currency until they are sent to an exchange
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Exchanges {
+    pub exchanges: Vec<Exchange>,
+    /// `(arrival_tick, exchange_id, order)` of the orders on their way to an exchange
+    in_flight: Vec<(u64, u64, TodoTransaction)>,
+    /// Agent ids of the arbitrageurs, trading the gaps between the exchanges
+    pub arbitrageurs: Vec<u64>,
+}
+
+impl Exchange {
+    /// Whether the instrument can be traded on the exchange
+    pub fn lists(&self, instrument_id: InstrumentId) -> bool {
+        if self.id == 0 {
+            return true;
+        }
+        instrument_id
+            .equity()
+            .is_some_and(|company_id| self.listings.contains(&company_id))
+    }
+}
+
+impl Exchanges {
+    /// An exchange for every `(fees, latency, currency)`, the first one being the primary exchange
+    pub fn new(venues: &[(FeeSchedule, u64, u64)]) -> Self {
+        Self {
+            exchanges: venues
+                .iter()
+                .enumerate()
+                .map(|(id, (fees, latency, currency))| {
+                    let mut market = Market::with_fees(*fees);
+                    market.currency = *currency;
+                    Exchange {
+                        id: id as u64,
+                        market,
+                        latency: *latency,
+                        listings: HashSet::new(),
+                    }
+                })
+                .collect(),
+            ..Self::default()
+        }
+    }
+    pub fn primary(&self) -> &Market {
+        &self.exchanges[0].market
+    }
+    pub fn primary_mut(&mut self) -> &mut Market {
+        &mut self.exchanges[0].market
+    }
+    /// Lists the company on the exchange, on top of the primary one
+    pub fn list(&mut self, exchange_id: u64, company_id: u64) {
+        if let Some(exchange) = self.exchanges.get_mut(exchange_id as usize) {
+            exchange.listings.insert(company_id);
+        }
+    }
+    /// Lists the company on each of the other exchanges with `CROSS_LISTING_PROBABILITY`
+    pub fn rand_cross_list(&mut self, rng: &mut impl Rng, company_id: u64) {
+        for exchange in self.exchanges.iter_mut().skip(1) {
+            if rng.gen_bool(CROSS_LISTING_PROBABILITY) {
+                exchange.listings.insert(company_id);
+            }
+        }
+    }
+    /// Creates an arbitrageur as a new agent holding `ARBITRAGEUR_CAPITAL` in the currency of every exchange,
+    /// returns its agent id
+    pub fn found_arbitrageur(&mut self, agents: &mut Agents, currencies: &Currencies) -> u64 {
+        let agent_ids = agents.create_agents(1, &mut vec![ARBITRAGEUR_CAPITAL]);
+        agents.assign_strategies(|_| AgentStrategy::Arbitrageur, &agent_ids);
+        let mut foreign_currencies = self
+            .exchanges
+            .iter()
+            .map(|exchange| exchange.market.currency)
+            .filter(|currency| *currency != HOME_CURRENCY)
+            .collect::<Vec<_>>();
+        foreign_currencies.sort();
+        foreign_currencies.dedup();
+        for currency in foreign_currencies {
+            let foreign_capital = currencies.from_home(ARBITRAGEUR_CAPITAL, currency);
+            if let Err(e) =
+                agents
+                    .balances
+                    .add_in(agent_ids[0], currency, Money::from(foreign_capital))
+            {
+                log!(warn "Couldn't fund the arbitrageur: {:?}", e);
+            }
+        }
+        self.arbitrageurs.push(agent_ids[0]);
+        agent_ids[0]
+    }
+    /// Picks the exchange giving the order the best price after the taker fee in the home currency,
+    /// or the cheapest one to wait in the book if none can fill it right away
+    pub fn route(&self, order: &TodoTransaction, currencies: &Currencies) -> u64 {
+        let halted = order
+            .instrument_id
+            .equity()
+            .is_some_and(|company_id| self.primary().is_halted(company_id));
+        if halted {
+            // the primary exchange turns it away
+            return 0;
+        }
+        let candidates = self
+            .exchanges
+            .iter()
+            .filter(|exchange| exchange.lists(order.instrument_id));
+        let fillable = candidates
+            .clone()
+            .filter_map(|exchange| {
+                let house = &exchange.market.house;
+                let fees = exchange.market.fees;
+                let to_home =
+                    |price: Money| currencies.to_home(price.to_f64(), exchange.market.currency);
+                let price = match order.action {
+                    TradeAction::Buy => {
+                        house
+                            .best_ask(order.instrument_id)
+                            .map(to_home)
+                            .filter(|ask| *ask <= order.strike_price.to_f64())?
+                            * (1.0 + fees.taker)
+                    }
+                    // the seller wants the most, compared as the least negative
+                    TradeAction::Sell => {
+                        -house
+                            .best_bid(order.instrument_id)
+                            .map(to_home)
+                            .filter(|bid| *bid >= order.strike_price.to_f64())?
+                            * (1.0 - fees.taker)
+                    }
+                };
+                Some((exchange.id, price))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+        if let Some((exchange_id, _)) = fillable {
+            return exchange_id;
+        }
+        candidates
+            .min_by(|a, b| a.market.fees.maker.total_cmp(&b.market.fees.maker))
+            .map_or(0, |exchange| exchange.id)
+    }
+    /// Sends the order to the exchange, where it arrives after the latency of the exchange
+    pub fn send(&mut self, exchange_id: u64, order: TodoTransaction, current_tick: u64) {
+        let latency = self
+            .exchanges
+            .get(exchange_id as usize)
+            .map_or(0, |exchange| exchange.latency);
+        self.in_flight
+            .push((current_tick + latency, exchange_id, order));
+    }
+    /// Routes every order and sends it on its way, priced in the currency of its exchange
+    ///
+    /// A buyer short of the currency of its exchange buys the rest from the quotes of the dealers first,
+    /// and goes to the primary exchange if it can't
+    pub fn submit(
+        &mut self,
+        orders: impl IntoIterator<Item = TodoTransaction>,
+        agents: &mut Agents,
+        companies: &mut Companies,
+        currencies: &Currencies,
+        current_tick: u64,
+    ) {
+        for mut order in orders {
+            let mut exchange_id = self.route(&order, currencies);
+            let currency = self.exchanges[exchange_id as usize].market.currency;
+            let strike_price =
+                Money::from(currencies.from_home(order.strike_price.to_f64(), currency));
+            if order.action == TradeAction::Buy && currency != HOME_CURRENCY {
+                let cost = strike_price.times(order.trade.number_of_shares);
+                let balance = agents
+                    .balances
+                    .get_in(order.agent_id, currency)
+                    .unwrap_or_default();
+                if cost > balance
+                    && Currencies::convert(
+                        agents,
+                        self.primary_mut(),
+                        companies,
+                        order.agent_id,
+                        currency,
+                        TradeAction::Buy,
+                        (cost - balance).ceil_to(Money::UNIT).whole_units(),
+                    )
+                    .is_err()
+                {
+                    exchange_id = 0;
+                }
+            }
+            if exchange_id != 0 {
+                order.strike_price = strike_price;
+            }
+            self.send(exchange_id, order, current_tick);
+        }
+    }
+    /// Trades the orders which reached their exchange, then folds the trades into the primary exchange
+    ///
+    /// Returns the first error hit, the other exchanges still trade
+    pub fn rand_do_trade(
+        &mut self,
+        rng: &mut impl Rng,
+        agents: &mut Agents,
+        companies: &mut Companies,
+        currencies: &Currencies,
+        current_tick: u64,
+    ) -> Result<(), SimulationError> {
+        let mut arrived = self
+            .exchanges
+            .iter()
+            .map(|_| Vec::new())
+            .collect::<Vec<_>>();
+        let mut in_flight = Vec::with_capacity(self.in_flight.len());
+        let primary = &self.exchanges[0].market;
+        for (arrival_tick, exchange_id, order) in self.in_flight.drain(..) {
+            if arrival_tick > current_tick {
+                in_flight.push((arrival_tick, exchange_id, order));
+                continue;
+            }
+            // sent before the halt, the other exchanges turn it away like the primary one would
+            let halted = order
+                .instrument_id
+                .equity()
+                .is_some_and(|company_id| primary.is_halted(company_id));
+            if halted && exchange_id != 0 {
+                continue;
+            }
+            if let Some(orders) = arrived.get_mut(exchange_id as usize) {
+                orders.push(order);
+            }
+        }
+        self.in_flight = in_flight;
+        let mut result = Ok(());
+        for (exchange, mut orders) in self.exchanges.iter_mut().zip(arrived) {
+            if let Err(e) = exchange
+                .market
+                .rand_do_trade(rng, agents, companies, &mut orders)
+            {
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        let (primary, others) = self.exchanges.split_at_mut(1);
+        for exchange in others.iter_mut() {
+            let rate = currencies.rate(exchange.market.currency);
+            primary[0].market.consolidate(&mut exchange.market, rate);
+        }
+        result
+    }
+    /// Orders buying where a company is cheapest and selling what is held where it is dearest,
+    /// whenever the gap in the home currency pays for both taker fees
+    ///
+    /// Returns `(exchange_id, order)` of the orders to send, priced in the currency of their exchange
+    pub fn arbitrage(
+        &self,
+        agents: &Agents,
+        companies: &Companies,
+        currencies: &Currencies,
+    ) -> Vec<(u64, TodoTransaction)> {
+        let mut orders = Vec::new();
+        for company_id in companies.iter() {
+            let instrument_id = InstrumentId::Equity(company_id);
+            if !companies.is_listed(company_id) || self.primary().is_halted(company_id) {
+                continue;
+            }
+            let venues = self
+                .exchanges
+                .iter()
+                .filter(|exchange| exchange.lists(instrument_id));
+            let cheapest = venues
+                .clone()
+                .filter_map(|exchange| {
+                    let market = &exchange.market;
+                    let ask = market.house.best_ask(instrument_id)?;
+                    let cost = currencies.to_home(ask.to_f64(), market.currency)
+                        * (1.0 + market.fees.taker);
+                    Some((exchange.id, ask, cost))
+                })
+                .min_by(|a, b| a.2.total_cmp(&b.2));
+            let dearest = venues
+                .filter_map(|exchange| {
+                    let market = &exchange.market;
+                    let bid = market.house.best_bid(instrument_id)?;
+                    let proceeds = currencies.to_home(bid.to_f64(), market.currency)
+                        * (1.0 - market.fees.taker);
+                    Some((exchange.id, bid, proceeds))
+                })
+                .max_by(|a, b| a.2.total_cmp(&b.2));
+            let (Some((buy_venue, ask, cost)), Some((sell_venue, bid, proceeds))) =
+                (cheapest, dearest)
+            else {
+                continue;
+            };
+            if buy_venue == sell_venue || proceeds <= cost {
+                continue;
+            }
+            log!(info "Arbitrage: company_id: {}, ask: {} on {}, bid: {} on {}", company_id, ask, buy_venue, bid, sell_venue);
+            let buy_currency = self.exchanges[buy_venue as usize].market.currency;
+            for agent_id in self.arbitrageurs.iter().copied() {
+                let held = agents.holdings.get(agent_id, instrument_id);
+                if held != 0 {
+                    orders.push((
+                        sell_venue,
+                        TodoTransaction {
+                            agent_id,
+                            instrument_id,
+                            strike_price: bid,
+                            action: TradeAction::Sell,
+                            trade: Trade::new(held),
+                        },
+                    ));
+                }
+                let budget = agents
+                    .balances
+                    .get_in(agent_id, buy_currency)
+                    .map_or(0.0, Money::to_f64)
+                    * ARBITRAGE_ORDER_SIZE;
+                let number_of_shares =
+                    (currencies.to_home(budget, buy_currency) / cost).floor() as u64;
+                if number_of_shares != 0 {
+                    orders.push((
+                        buy_venue,
+                        TodoTransaction {
+                            agent_id,
+                            instrument_id,
+                            strike_price: ask,
+                            action: TradeAction::Buy,
+                            trade: Trade::new(number_of_shares),
+                        },
+                    ));
+                }
+            }
+        }
+        orders
+    }
+    /// Collects the expired offers of the exchanges trading in the home currency
+    ///
+    /// The ones expiring elsewhere are refunded in their currency and remembered by the agents
+    /// at their price in the home currency straight away
+    pub fn tick_failures(
+        &mut self,
+        agents: &mut Agents,
+        currencies: &Currencies,
+        expired_trades: &mut HashMap<InstrumentId, Vec<FailedOffer<Trade>>>,
+    ) -> Result<(), SimulationError> {
+        for exchange in self.exchanges.iter_mut() {
+            let mut expired = HashMap::new();
+            exchange.market.tick_failures(&mut expired);
+            let currency = exchange.market.currency;
+            if currency != HOME_CURRENCY {
+                agents.refund_failed_offers_in(&expired, currency)?;
+                for (instrument_id, offers) in expired.iter() {
+                    for FailedOffer(offer, action) in offers.iter() {
+                        let failed_price =
+                            Money::from(currencies.to_home(offer.strike_price.to_f64(), currency));
+                        agents.add_failed_offer(
+                            *instrument_id,
+                            offer.offerer_id,
+                            failed_price,
+                            action,
+                        );
+                    }
+                }
+                continue;
+            }
+            for (instrument_id, offers) in expired {
+                expired_trades
+                    .entry(instrument_id)
+                    .or_default()
+                    .extend(offers);
+            }
+        }
+        Ok(())
+    }
+    /// Cancels the offers for the company on every exchange and removes it from the simulation
+    pub fn delist_company(
+        &mut self,
+        company_id: u64,
+        agents: &mut Agents,
+        companies: &mut Companies,
+        etfs: &mut Etfs,
+    ) -> Result<(), SimulationError> {
+        for exchange in self.exchanges.iter_mut().skip(1) {
+            exchange.market.cancel_company(company_id, agents)?;
+            exchange.listings.remove(&company_id);
+        }
+        self.in_flight
+            .retain(|(_, _, order)| order.instrument_id.equity() != Some(company_id));
+        self.primary_mut()
+            .delist_company(company_id, agents, companies, etfs)
+    }
+    /// Fees collected by every exchange
+    pub fn fees_collected(&self) -> Vec<Money> {
+        self.exchanges
+            .iter()
+            .map(|exchange| exchange.market.fees_collected)
+            .collect()
+    }
+    /// Cash every agent has put aside in its buy offers on all the exchanges, in the home currency
+    pub fn escrowed_cash(&self, currencies: &Currencies) -> HashMap<u64, Money> {
+        let mut escrowed_cash = HashMap::new();
+        for exchange in self.exchanges.iter() {
+            let currency = exchange.market.currency;
+            for (agent_id, cash) in exchange.market.house.escrowed_cash() {
+                *escrowed_cash.entry(agent_id).or_default() +=
+                    Money::from(currencies.to_home(cash.to_f64(), currency));
+            }
+        }
+        escrowed_cash
+    }
+    /// Number of orders on their way to an exchange
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+}
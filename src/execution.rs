@@ -0,0 +1,119 @@
+use crate::{
+    instrument::InstrumentId,
+    log,
+    logger::Log,
+    market::Market,
+    money::Money,
+    trade_house::{Trade, TradeAction},
+    transaction::TodoTransaction,
+};
+use serde::{Deserialize, Serialize};
+
+/// How a large order is sliced into the child orders sent to the `Market`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ExecutionAlgorithm {
+    /// The same number of shares every tick
+    Twap,
+    /// More shares on the ticks the company trades more
+    Vwap,
+}
+
+/// An order too large to be sent at once, worked over `duration` ticks
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ParentOrder {
+    pub agent_id: u64,
+    pub company_id: u64,
+    pub action: TradeAction,
+    pub limit_price: Money,
+    pub remaining_shares: u64,
+    pub algorithm: ExecutionAlgorithm,
+    /// Tick by which the whole order has been sent
+    pub end_tick: u64,
+}
+
+/// Works the parent orders of the institutions
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ExecutionDesk {
+    pub orders: Vec<ParentOrder>,
+}
+
+impl ParentOrder {
+    pub fn new(
+        agent_id: u64,
+        company_id: u64,
+        action: TradeAction,
+        limit_price: impl Into<Money>,
+        number_of_shares: u64,
+        (algorithm, start_tick, duration): (ExecutionAlgorithm, u64, u64),
+    ) -> Self {
+        Self {
+            agent_id,
+            company_id,
+            action,
+            limit_price: limit_price.into(),
+            remaining_shares: number_of_shares,
+            algorithm,
+            end_tick: start_tick + duration.max(1),
+        }
+    }
+    /// Number of shares to send this tick
+    pub fn next_slice(&self, current_tick: u64, market: &Market) -> u64 {
+        let ticks_left = self.end_tick.saturating_sub(current_tick);
+        if ticks_left <= 1 {
+            return self.remaining_shares;
+        }
+        let twap_slice = self.remaining_shares.div_ceil(ticks_left);
+        if self.algorithm == ExecutionAlgorithm::Twap {
+            return twap_slice;
+        }
+        // the rest of the ticks are expected to trade the average volume
+        let volume = market.volume(InstrumentId::Equity(self.company_id)) as f64;
+        let expected_volume = volume
+            + market.average_volume(InstrumentId::Equity(self.company_id))
+                * (ticks_left - 1) as f64;
+        if expected_volume == 0.0 {
+            return twap_slice;
+        }
+        let slice = (self.remaining_shares as f64 * volume / expected_volume).ceil() as u64;
+        slice.min(self.remaining_shares)
+    }
+}
+
+impl ExecutionDesk {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn add(&mut self, order: ParentOrder) {
+        log!(info "Parent order: {:?}", order);
+        self.orders.push(order);
+    }
+    /// Child orders to send this tick, finished parent orders are dropped
+    pub fn slices(&mut self, current_tick: u64, market: &Market) -> Vec<TodoTransaction> {
+        let mut child_orders = Vec::new();
+        for order in self.orders.iter_mut() {
+            let number_of_shares = order.next_slice(current_tick, market);
+            if number_of_shares == 0 {
+                continue;
+            }
+            order.remaining_shares -= number_of_shares;
+            child_orders.push(TodoTransaction {
+                agent_id: order.agent_id,
+                instrument_id: InstrumentId::Equity(order.company_id),
+                strike_price: order.limit_price,
+                action: order.action,
+                trade: Trade::new(number_of_shares),
+            });
+        }
+        self.orders.retain(|order| order.remaining_shares != 0);
+        child_orders
+    }
+    /// Drops the orders of a company which can't be traded anymore
+    pub fn cancel_company_orders(&mut self, company_id: u64) {
+        self.orders.retain(|order| order.company_id != company_id);
+    }
+    pub fn has_order(&self, agent_id: u64, company_id: u64) -> bool {
+        self.orders
+            .iter()
+            .any(|order| order.agent_id == agent_id && order.company_id == company_id)
+    }
+}
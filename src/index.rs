@@ -0,0 +1,130 @@
+use crate::entities::companies::Companies;
+use serde::{Deserialize, Serialize};
+
+/// Level of an index on the day it is created
+pub const INDEX_BASE_LEVEL: f64 = 1_000.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum IndexWeighting {
+    /// Every company counts as much as its market capitalization
+    CapWeighted,
+    /// Every company counts as much, the weights are reset on constituent changes
+    EqualWeighted,
+}
+
+/// An aggregate of the prices of the listed companies
+///
+/// The level is the value of the units held of each constituent divided by the divisor,
+/// the divisor is adjusted whenever the units change so the level doesn't jump
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MarketIndex {
+    pub weighting: IndexWeighting,
+    /// `(company_id, units)` of every constituent, sorted by company
+    pub constituents: Vec<(u64, f64)>,
+    pub divisor: f64,
+    /// Level of the index at every tick it was computed
+    pub history: Vec<f64>,
+}
+
+impl MarketIndex {
+    pub fn new(weighting: IndexWeighting, companies: &Companies) -> Self {
+        let mut index = Self {
+            weighting,
+            constituents: Vec::new(),
+            divisor: 1.0,
+            history: Vec::new(),
+        };
+        index.constituents = index.target_constituents(companies);
+        let raw_level = index.raw_level(companies);
+        if raw_level > 0.0 {
+            index.divisor = raw_level / INDEX_BASE_LEVEL;
+        }
+        index.history.push(index.level(companies));
+        index
+    }
+    fn raw_level(&self, companies: &Companies) -> f64 {
+        self.constituents
+            .iter()
+            .map(|(company_id, units)| {
+                units * companies.get_current_price(*company_id).unwrap_or(0.0)
+            })
+            .sum()
+    }
+    pub fn level(&self, companies: &Companies) -> f64 {
+        self.raw_level(companies) / self.divisor
+    }
+    /// Every listed company with a price (and shares for a cap-weighted index)
+    fn target_constituents(&self, companies: &Companies) -> Vec<(u64, f64)> {
+        companies
+            .iter()
+            .filter(|company_id| companies.is_listed(*company_id))
+            .filter_map(|company_id| {
+                let price = companies.get_current_price(company_id)?;
+                if price <= 0.0 {
+                    return None;
+                }
+                let units = match self.weighting {
+                    IndexWeighting::CapWeighted => {
+                        companies.shares_outstanding[company_id as usize] as f64
+                    }
+                    IndexWeighting::EqualWeighted => 1.0 / price,
+                };
+                (units > 0.0).then_some((company_id, units))
+            })
+            .collect()
+    }
+    pub fn is_constituent(&self, company_id: u64) -> bool {
+        self.constituents
+            .binary_search_by_key(&company_id, |(id, _)| *id)
+            .is_ok()
+    }
+    /// Portion of the index made up by every constituent
+    pub fn weights(&self, companies: &Companies) -> Vec<(u64, f64)> {
+        let raw_level = self.raw_level(companies);
+        if raw_level <= 0.0 {
+            return Vec::new();
+        }
+        self.constituents
+            .iter()
+            .map(|(company_id, units)| {
+                let price = companies.get_current_price(*company_id).unwrap_or(0.0);
+                (*company_id, units * price / raw_level)
+            })
+            .collect()
+    }
+    /// Records the level of the index, returns true if the constituents changed
+    pub fn tick(&mut self, companies: &Companies) -> bool {
+        let level = self.history.last().copied().unwrap_or(INDEX_BASE_LEVEL);
+        let mut target_constituents = self.target_constituents(companies);
+        let constituents_changed = target_constituents.len() != self.constituents.len()
+            || target_constituents
+                .iter()
+                .zip(self.constituents.iter())
+                .any(|((a, _), (b, _))| a != b);
+        if self.weighting == IndexWeighting::EqualWeighted && !constituents_changed {
+            // the weights drift with the prices until the next constituent change
+            target_constituents = self.constituents.clone();
+        }
+        if target_constituents != self.constituents {
+            // continue from the level before the change
+            let previous_level = self.level(companies);
+            let previous_level = if previous_level > 0.0 {
+                previous_level
+            } else {
+                level
+            };
+            self.constituents = target_constituents;
+            let raw_level = self.raw_level(companies);
+            if raw_level > 0.0 && previous_level > 0.0 {
+                self.divisor = raw_level / previous_level;
+            }
+        }
+        let level = if self.constituents.is_empty() {
+            level
+        } else {
+            self.level(companies)
+        };
+        self.history.push(level);
+        constituents_changed
+    }
+}
@@ -0,0 +1,478 @@
+use crate::{
+    economy::Economy,
+    entities::{agents::Agents, companies::Companies},
+    execution::{ExecutionAlgorithm, ExecutionDesk, ParentOrder},
+    index::MarketIndex,
+    log,
+    logger::Log,
+    max, min,
+    money::Money,
+    strategy::{intrinsic_value, AgentStrategy},
+    trade_house::TradeAction,
+    MIN_STRIKE_PRICE,
+};
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Number of retail agents pooling their money into a mutual fund
+pub const MUTUAL_FUND_INVESTORS: usize = 200;
+/// Number of retail agents saving for their retirement in a pension fund
+pub const PENSION_FUND_MEMBERS: usize = 500;
+/// Number of wealthy agents backing a hedge fund
+pub const HEDGE_FUND_INVESTORS: usize = 20;
+/// Number of retail agents buying into an index fund
+pub const INDEX_FUND_INVESTORS: usize = 200;
+/// Portion of its value an index fund keeps in cash to pay for its orders
+pub const INDEX_FUND_CASH_BUFFER: f64 = 0.05;
+/// Gap between a holding and its index weight, as a portion of the value of the fund,
+/// which an index fund tolerates
+pub const INDEX_TRACKING_TOLERANCE: f64 = 0.002;
+/// Portion of their balance the investors put into a fund
+pub const FUND_CONTRIBUTION: f64 = 0.1;
+/// Portion of its value a pension fund keeps in shares
+pub const PENSION_TARGET_EQUITY_SHARE: f64 = 0.6;
+/// How far from its target a pension fund drifts before rebalancing
+pub const PENSION_REBALANCING_BAND: f64 = 0.05;
+/// Money a hedge fund trades with per unit of its own capital
+pub const HEDGE_FUND_LEVERAGE: f64 = 2.0;
+/// Portion of its cash a fund puts into a single order
+pub const FUND_ORDER_SIZE: f64 = 0.2;
+/// Number of ticks a parent order is worked over
+pub const ORDER_EXECUTION_TICKS: u64 = 10;
+/// How much worse than the current price a fund accepts to get its order done
+pub const EXECUTION_PRICE_TOLERANCE: f64 = 0.02;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum InstitutionKind {
+    /// Buys the companies trading furthest under their intrinsic value
+    MutualFund,
+    /// Keeps `PENSION_TARGET_EQUITY_SHARE` of its value in shares
+    PensionFund,
+    /// Trades the news with borrowed money
+    HedgeFund,
+    /// Holds the companies of the market index in proportion to their weight
+    IndexFund,
+}
+
+/// A fund trading as a single agent with the money of many
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Institution {
+    pub agent_id: u64,
+    pub kind: InstitutionKind,
+    /// `(agent_id, units)` of everyone who put money in the fund, a unit is issued for every unit of
+    /// money contributed and is a claim on an equal part of the net asset value
+    pub investors: Vec<(u64, f64)>,
+    /// Money borrowed on top of the contributions, owed until it's repaid
+    pub borrowed: Money,
+    /// Set for an index fund when its holdings need to follow the index
+    pub needs_rebalancing: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Institutions(pub Vec<Institution>);
+
+impl InstitutionKind {
+    fn number_of_investors(&self) -> usize {
+        match self {
+            Self::MutualFund => MUTUAL_FUND_INVESTORS,
+            Self::PensionFund => PENSION_FUND_MEMBERS,
+            Self::HedgeFund => HEDGE_FUND_INVESTORS,
+            Self::IndexFund => INDEX_FUND_INVESTORS,
+        }
+    }
+    fn execution_algorithm(&self) -> ExecutionAlgorithm {
+        match self {
+            Self::MutualFund | Self::IndexFund => ExecutionAlgorithm::Twap,
+            Self::PensionFund | Self::HedgeFund => ExecutionAlgorithm::Vwap,
+        }
+    }
+}
+
+fn limit_price(action: TradeAction, current_price: f64) -> f64 {
+    let limit_price = match action {
+        TradeAction::Buy => current_price * (1.0 + EXECUTION_PRICE_TOLERANCE),
+        TradeAction::Sell => current_price * (1.0 - EXECUTION_PRICE_TOLERANCE),
+    };
+    max(MIN_STRIKE_PRICE, limit_price)
+}
+
+impl Institutions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Creates the fund as a new agent, pooling the money of randomly picked retail agents
+    ///
+    /// Returns the agent id of the fund
+    pub fn rand_found(
+        &mut self,
+        rng: &mut impl Rng,
+        agents: &mut Agents,
+        kind: InstitutionKind,
+    ) -> u64 {
+        let mut candidates = agents
+            .iter()
+            .filter(|agent_id| {
+                agents.is_active(*agent_id)
+                    && agents.strategies[*agent_id as usize] != AgentStrategy::Institutional
+            })
+            .collect::<Vec<_>>();
+        if kind == InstitutionKind::HedgeFund {
+            // only the wealthiest can get into a hedge fund
+            candidates.sort_by(|a, b| {
+                let balance = |agent_id: &u64| agents.balances.get(*agent_id).unwrap_or_default();
+                balance(b).cmp(&balance(a))
+            });
+            candidates.truncate(kind.number_of_investors() * 5);
+        }
+        let investor_ids = candidates
+            .choose_multiple(rng, kind.number_of_investors())
+            .copied()
+            .collect::<Vec<_>>();
+        let mut investors = Vec::with_capacity(investor_ids.len());
+        let mut capital = Money::ZERO;
+        for agent_id in investor_ids {
+            let contribution = agents
+                .balances
+                .get(agent_id)
+                .unwrap_or_default()
+                .scale(FUND_CONTRIBUTION);
+            if contribution <= Money::ZERO || agents.balances.add(agent_id, -contribution).is_err()
+            {
+                continue;
+            }
+            capital += contribution;
+            investors.push((agent_id, contribution.to_f64()));
+        }
+        let borrowed = match kind {
+            InstitutionKind::HedgeFund => capital.scale(HEDGE_FUND_LEVERAGE - 1.0),
+            InstitutionKind::MutualFund
+            | InstitutionKind::PensionFund
+            | InstitutionKind::IndexFund => Money::ZERO,
+        };
+        let agent_ids = agents.create_agents(1, &mut vec![(capital + borrowed).to_f64()]);
+        let agent_id = agent_ids[0];
+        agents.assign_strategies(|_| AgentStrategy::Institutional, &agent_ids);
+        log!(info "New institution: agent_id: {}, kind: {:?}, capital: {}, borrowed: {}", agent_id, kind, capital, borrowed);
+        self.0.push(Institution {
+            agent_id,
+            kind,
+            investors,
+            borrowed,
+            needs_rebalancing: kind == InstitutionKind::IndexFund,
+        });
+        agent_id
+    }
+    /// Leveraged funds pay interest on what they borrowed and repay what their equity no longer backs
+    ///
+    /// The interest a fund can't pay is added to the loan, `escrowed_cash` is the cash each agent
+    /// has put aside for its offers and lots
+    pub fn pay_financing(
+        &mut self,
+        agents: &mut Agents,
+        companies: &Companies,
+        economy: &Economy,
+        escrowed_cash: &HashMap<u64, Money>,
+    ) {
+        for institution in self.0.iter_mut() {
+            if institution.borrowed == Money::ZERO {
+                continue;
+            }
+            let agent_id = institution.agent_id;
+            let Ok(balance) = agents.balances.get(agent_id) else {
+                continue;
+            };
+            let interest = institution.borrowed.scale(economy.interest_per_cycle());
+            let paid = min(interest, max(balance, Money::ZERO));
+            if agents.balances.add(agent_id, -paid).is_err() {
+                continue;
+            }
+            institution.borrowed += interest - paid;
+
+            let cash = balance - paid;
+            let assets = cash
+                + escrowed_cash.get(&agent_id).copied().unwrap_or_default()
+                + agents.marked_shares_of(agent_id, companies);
+            let repayment = min(institution.excess_debt(assets), max(cash, Money::ZERO));
+            if repayment > Money::ZERO && agents.balances.add(agent_id, -repayment).is_ok() {
+                institution.borrowed -= repayment;
+                log!(info "Institution {} repaid {} of its loan", agent_id, repayment);
+            }
+        }
+    }
+    /// What the investors' units are worth, `escrowed_cash` is the cash each agent has put aside
+    /// for its offers and lots
+    pub fn claims(
+        &self,
+        agents: &Agents,
+        companies: &Companies,
+        escrowed_cash: &HashMap<u64, Money>,
+    ) -> HashMap<u64, Money> {
+        let mut claims = HashMap::new();
+        for institution in self.0.iter() {
+            let agent_id = institution.agent_id;
+            let assets = agents.balances.get(agent_id).unwrap_or_default()
+                + escrowed_cash.get(&agent_id).copied().unwrap_or_default()
+                + agents.marked_shares_of(agent_id, companies);
+            let unit_value = institution.unit_value(assets);
+            for (investor_id, units) in institution.investors.iter() {
+                *claims.entry(*investor_id).or_default() += unit_value.scale(*units);
+            }
+        }
+        claims
+    }
+    /// Agent ids of the funds the companies place their bonds with
+    pub fn bond_buyers(&self) -> Vec<u64> {
+        self.0
+            .iter()
+            .filter(|institution| {
+                matches!(
+                    institution.kind,
+                    InstitutionKind::PensionFund | InstitutionKind::MutualFund
+                )
+            })
+            .map(|institution| institution.agent_id)
+            .collect()
+    }
+    /// The index funds follow the index at their next decision
+    pub fn rebalance_index_funds(&mut self) {
+        for institution in self.0.iter_mut() {
+            institution.needs_rebalancing = institution.kind == InstitutionKind::IndexFund;
+        }
+    }
+    /// Every fund without an order being worked decides what to do next
+    pub fn rand_place_orders(
+        &mut self,
+        rng: &mut impl Rng,
+        agents: &Agents,
+        companies: &Companies,
+        economy: &Economy,
+        index: &MarketIndex,
+        desk: &mut ExecutionDesk,
+    ) {
+        for institution in self.0.iter_mut() {
+            let agent_id = institution.agent_id;
+            if desk.orders.iter().any(|order| order.agent_id == agent_id) {
+                continue;
+            }
+            for (company_id, action, number_of_shares) in
+                institution.rand_decide(rng, agents, companies, economy, index)
+            {
+                let Some(current_price) = companies.get_current_price(company_id) else {
+                    continue;
+                };
+                desk.add(ParentOrder::new(
+                    agent_id,
+                    company_id,
+                    action,
+                    limit_price(action, current_price),
+                    number_of_shares,
+                    (
+                        institution.kind.execution_algorithm(),
+                        agents.current_tick,
+                        ORDER_EXECUTION_TICKS,
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+impl Institution {
+    /// Assets less the loan, what belongs to the investors
+    pub fn net_asset_value(&self, assets: Money) -> Money {
+        assets - self.borrowed
+    }
+    /// What a unit of the fund is worth, nothing once the loan is bigger than the assets
+    pub fn unit_value(&self, assets: Money) -> Money {
+        let units = self.investors.iter().map(|(_, units)| units).sum::<f64>();
+        if units <= 0.0 {
+            return Money::ZERO;
+        }
+        max(self.net_asset_value(assets), Money::ZERO).scale(1.0 / units)
+    }
+    /// Part of the loan over `HEDGE_FUND_LEVERAGE` times the net asset value
+    pub fn excess_debt(&self, assets: Money) -> Money {
+        let allowed =
+            max(self.net_asset_value(assets), Money::ZERO).scale(HEDGE_FUND_LEVERAGE - 1.0);
+        max(self.borrowed - allowed, Money::ZERO)
+    }
+    /// Returns `(company_id, action, number_of_shares)` of the orders to place
+    fn rand_decide(
+        &mut self,
+        rng: &mut impl Rng,
+        agents: &Agents,
+        companies: &Companies,
+        economy: &Economy,
+        index: &MarketIndex,
+    ) -> Vec<(u64, TradeAction, u64)> {
+        if self.kind == InstitutionKind::IndexFund {
+            return self.index_orders(agents, companies, index);
+        }
+        self.rand_decide_single(rng, agents, companies, economy)
+            .into_iter()
+            .collect()
+    }
+    /// Orders closing the gaps between the holdings of an index fund and the index
+    fn index_orders(
+        &mut self,
+        agents: &Agents,
+        companies: &Companies,
+        index: &MarketIndex,
+    ) -> Vec<(u64, TradeAction, u64)> {
+        if !self.needs_rebalancing {
+            return Vec::new();
+        }
+        let Ok(cash) = agents.balances.get(self.agent_id).map(Money::to_f64) else {
+            return Vec::new();
+        };
+        let holdings = agents.holdings_of(self.agent_id);
+        let price = |company_id: u64| {
+            companies
+                .get_current_price(company_id)
+                .map(|price| max(price, MIN_STRIKE_PRICE))
+                .unwrap_or(MIN_STRIKE_PRICE)
+        };
+        let held = |company_id: u64| {
+            holdings
+                .iter()
+                .find(|(id, _)| *id == company_id)
+                .map_or(0, |(_, number_of_shares)| *number_of_shares)
+        };
+        let total = cash
+            + holdings
+                .iter()
+                .map(|(company_id, number_of_shares)| price(*company_id) * *number_of_shares as f64)
+                .sum::<f64>();
+
+        // everything which left the index is sold
+        let mut orders = holdings
+            .iter()
+            .filter(|(company_id, _)| !index.is_constituent(*company_id))
+            .map(|(company_id, number_of_shares)| {
+                (*company_id, TradeAction::Sell, *number_of_shares)
+            })
+            .collect::<Vec<_>>();
+        for (company_id, weight) in index.weights(companies) {
+            let target = weight * total * (1.0 - INDEX_FUND_CASH_BUFFER);
+            let gap = target - price(company_id) * held(company_id) as f64;
+            if gap.abs() < INDEX_TRACKING_TOLERANCE * total {
+                continue;
+            }
+            let (action, number_of_shares) = if gap > 0.0 {
+                let buy_price = limit_price(TradeAction::Buy, price(company_id));
+                (TradeAction::Buy, (gap / buy_price).floor() as u64)
+            } else {
+                let number_of_shares = (-gap / price(company_id)).ceil() as u64;
+                (TradeAction::Sell, number_of_shares.min(held(company_id)))
+            };
+            if number_of_shares != 0 {
+                orders.push((company_id, action, number_of_shares));
+            }
+        }
+        if orders.is_empty() {
+            self.needs_rebalancing = false;
+        }
+        orders
+    }
+    /// Returns `(company_id, action, number_of_shares)` of the order to place
+    fn rand_decide_single(
+        &self,
+        rng: &mut impl Rng,
+        agents: &Agents,
+        companies: &Companies,
+        economy: &Economy,
+    ) -> Option<(u64, TradeAction, u64)> {
+        let cash = agents.balances.get(self.agent_id).ok()?.to_f64();
+        let listed = companies
+            .iter()
+            .filter(|company_id| companies.is_listed(*company_id))
+            .collect::<Vec<_>>();
+        let price = |company_id: u64| {
+            companies
+                .get_current_price(company_id)
+                .map(|price| max(price, MIN_STRIKE_PRICE))
+        };
+        let shares_worth = |company_id: u64, money: f64| {
+            let number_of_shares =
+                (money / limit_price(TradeAction::Buy, price(company_id)?)).floor() as u64;
+            (number_of_shares != 0).then_some((company_id, TradeAction::Buy, number_of_shares))
+        };
+        match self.kind {
+            InstitutionKind::MutualFund => {
+                let (company_id, _) = listed
+                    .iter()
+                    .filter_map(|company_id| {
+                        let value = intrinsic_value(companies, *company_id, economy)?;
+                        Some((*company_id, value / price(*company_id)?))
+                    })
+                    .filter(|(_, value_to_price)| *value_to_price > 1.0)
+                    .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+                shares_worth(company_id, cash * FUND_ORDER_SIZE)
+            }
+            InstitutionKind::PensionFund => {
+                let holdings = agents.holdings_of(self.agent_id);
+                let equity = holdings
+                    .iter()
+                    .filter_map(|(company_id, number_of_shares)| {
+                        Some(price(*company_id)? * *number_of_shares as f64)
+                    })
+                    .sum::<f64>();
+                let total = equity + cash;
+                if total <= 0.0 {
+                    return None;
+                }
+                let equity_share = equity / total;
+                let gap = PENSION_TARGET_EQUITY_SHARE * total - equity;
+                if equity_share < PENSION_TARGET_EQUITY_SHARE - PENSION_REBALANCING_BAND {
+                    return shares_worth(*listed.choose(rng)?, gap);
+                }
+                if equity_share <= PENSION_TARGET_EQUITY_SHARE + PENSION_REBALANCING_BAND {
+                    return None;
+                }
+                // trim the biggest position
+                let (company_id, number_of_shares) = holdings.into_iter().max_by(|a, b| {
+                    let value = |(company_id, number_of_shares): &(u64, u64)| {
+                        price(*company_id).unwrap_or(0.0) * *number_of_shares as f64
+                    };
+                    value(a).total_cmp(&value(b))
+                })?;
+                let to_sell = ((-gap / price(company_id)?).ceil() as u64).min(number_of_shares);
+                (to_sell != 0).then_some((company_id, TradeAction::Sell, to_sell))
+            }
+            InstitutionKind::HedgeFund => {
+                let holdings = agents.holdings_of(self.agent_id);
+                // get out of the companies with bad news first
+                if let Some((company_id, number_of_shares)) = holdings
+                    .iter()
+                    .find(|(company_id, _)| companies.news[*company_id as usize] < 0.0)
+                {
+                    return Some((*company_id, TradeAction::Sell, *number_of_shares));
+                }
+                // the cash doesn't cover the loan the losses left unbacked, sell to repay it
+                let value = |(company_id, number_of_shares): &(u64, u64)| {
+                    price(*company_id).unwrap_or(0.0) * *number_of_shares as f64
+                };
+                let assets = cash + holdings.iter().map(value).sum::<f64>();
+                let shortfall =
+                    (self.excess_debt(Money::from(assets)) - Money::from(cash)).to_f64();
+                if shortfall > 0.0 {
+                    let (company_id, number_of_shares) = holdings
+                        .into_iter()
+                        .max_by(|a, b| value(a).total_cmp(&value(b)))?;
+                    let to_sell =
+                        ((shortfall / price(company_id)?).ceil() as u64).min(number_of_shares);
+                    return (to_sell != 0).then_some((company_id, TradeAction::Sell, to_sell));
+                }
+                let company_id = *listed
+                    .iter()
+                    .filter(|company_id| companies.news[**company_id as usize] > 0.0)
+                    .max_by(|a, b| {
+                        companies.news[**a as usize].total_cmp(&companies.news[**b as usize])
+                    })?;
+                shares_worth(company_id, cash * FUND_ORDER_SIZE)
+            }
+            InstitutionKind::IndexFund => None,
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+/// Number of low bits of an encoded `InstrumentId` holding the id, the kind is in the bits above
+const ID_BITS: u32 = 56;
+const ID_MASK: u64 = (1 << ID_BITS) - 1;
+
+/// Anything which can be held and traded
+///
+/// Encodes to a `u64` with the kind in the top byte, equities being kind 0,
+/// so the holdings saved under a bare company id load as its common stock.
+/// Kind 1 is kept for options
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum InstrumentId {
+    /// Common stock of the company
+    Equity(u64),
+    /// Bond of the given id
+    Bond(u64),
+    /// Units of the ETF of the given id
+    Etf(u64),
+    /// Cash in the given currency, priced in the home currency
+    Fx(u64),
+}
+
+impl InstrumentId {
+    pub fn encode(&self) -> u64 {
+        let (kind, id) = match *self {
+            Self::Equity(id) => (0, id),
+            Self::Bond(id) => (2, id),
+            Self::Etf(id) => (3, id),
+            Self::Fx(id) => (4, id),
+        };
+        (kind << ID_BITS) | (id & ID_MASK)
+    }
+    /// `None` for a kind no instrument is encoded with
+    pub fn decode(encoded: u64) -> Option<Self> {
+        let id = encoded & ID_MASK;
+        let instrument_id = match encoded >> ID_BITS {
+            0 => Self::Equity(id),
+            2 => Self::Bond(id),
+            3 => Self::Etf(id),
+            4 => Self::Fx(id),
+            _ => return None,
+        };
+        Some(instrument_id)
+    }
+    /// The currency of a currency pair, `None` for every other instrument
+    pub fn currency(&self) -> Option<u64> {
+        match *self {
+            Self::Fx(currency) => Some(currency),
+            _ => None,
+        }
+    }
+    /// The company of a share, `None` for every other instrument
+    pub fn equity(&self) -> Option<u64> {
+        match *self {
+            Self::Equity(company_id) => Some(company_id),
+            _ => None,
+        }
+    }
+}
@@ -1,25 +1,121 @@
 use std::{
-    fs::File,
+    fs::{self, File},
     io::{BufReader, BufWriter},
+    path::Path,
 };
 
+use entities::companies::AllocationMethod;
+use market::FeeSchedule;
+use money::Money;
 use serde::{de::DeserializeOwned, Serialize};
+use social::GraphKind;
+use wealth::WealthDistribution;
 
+pub mod bonds;
+pub mod currency;
+pub mod economy;
 pub mod entities;
+pub mod etf;
+pub mod exchange;
+pub mod execution;
+pub mod index;
+pub mod institutions;
+pub mod instrument;
 pub mod logger;
 pub mod market;
+pub mod metrics;
+pub mod money;
+pub mod scenario;
+pub mod social;
+pub mod strategy;
 pub mod trade_house;
 pub mod transaction;
+pub mod wealth;
 
 pub static NUM_OF_AGENTS: u64 = 10_000;
 pub static NUM_OF_COMPANIES: u64 = 100;
+pub static NUM_OF_SECTORS: u64 = 10;
 
 pub static AGENTS_DATA_FILENAME: &str = "data/agents.bin";
 pub static COMPANIES_DATA_FILENAME: &str = "data/companies.bin";
+pub static INSTITUTIONS_DATA_FILENAME: &str = "data/institutions.bin";
+pub static ETFS_DATA_FILENAME: &str = "data/etfs.bin";
+pub static BONDS_DATA_FILENAME: &str = "data/bonds.bin";
+pub static CURRENCIES_DATA_FILENAME: &str = "data/currencies.bin";
+/// Observed balances to draw the initial balances from, replaces `INITIAL_BALANCE_DISTRIBUTION`
+pub static INITIAL_BALANCES_FILENAME: &str = "data/initial_balances.csv";
+/// Observed holding values to draw the initial holdings from, replaces `INITIAL_HOLDING_DISTRIBUTION`
+pub static INITIAL_HOLDINGS_FILENAME: &str = "data/initial_holdings.csv";
 
 pub static MIN_STRIKE_PRICE: f64 = 5.0;
+/// Tick size of the stocks listed under 100, the dearer ones trading in coarser ticks
+pub static TICK_SIZE: Money = Money(100);
 pub static OFFER_LIFETIME: u64 = 10;
 pub static TIMELINE_SIZE_LIMIT: usize = 1000;
+/// Number of ticks between the listing of new companies
+pub static NEW_COMPANY_LISTING_INTERVAL: u64 = 500;
+pub static IPO_ALLOCATION_METHOD: AllocationMethod = AllocationMethod::ProRata;
+/// Whether newly listed companies discover their IPO price through book-building
+pub static IPO_BOOK_BUILDING: bool = true;
+/// Portion of the agents investing on the fundamentals of the companies
+pub static VALUE_INVESTOR_RATIO: f64 = 0.1;
+/// Portion of the agents learning from their own P&L, off unless studying them
+pub static LEARNING_AGENT_RATIO: f64 = 0.0;
+pub static INITIAL_BALANCE_DISTRIBUTION: WealthDistribution = WealthDistribution::Uniform {
+    min: 1_000.0,
+    max: 1_000_000.0,
+};
+/// Value of the shares every agent starts with
+pub static INITIAL_HOLDING_DISTRIBUTION: WealthDistribution = WealthDistribution::LogNormal {
+    mu: 8.0,
+    sigma: 1.0,
+};
+pub static NUM_OF_MUTUAL_FUNDS: u64 = 5;
+pub static NUM_OF_PENSION_FUNDS: u64 = 2;
+pub static NUM_OF_HEDGE_FUNDS: u64 = 3;
+pub static NUM_OF_INDEX_FUNDS: u64 = 2;
+/// Number of market makers creating and redeeming the units of the ETFs
+pub static NUM_OF_AUTHORIZED_PARTICIPANTS: u64 = 2;
+/// `(name, rate in the home currency)` of every currency, the first one is the home currency
+pub static CURRENCIES: &[(&str, f64)] = &[("USD", 1.0), ("EUR", 1.08), ("GBP", 1.27)];
+/// Number of dealers quoting the currencies
+pub static NUM_OF_FX_DEALERS: u64 = 2;
+/// `(fees, latency in ticks, currency)` of every exchange, the first one is the primary exchange listing every company
+pub static EXCHANGES: &[(FeeSchedule, u64, u64)] = &[
+    (
+        FeeSchedule {
+            maker: 0.001,
+            taker: 0.002,
+        },
+        0,
+        0,
+    ),
+    (
+        FeeSchedule {
+            maker: -0.001,
+            taker: 0.003,
+        },
+        1,
+        1,
+    ),
+    (
+        FeeSchedule {
+            maker: 0.0,
+            taker: 0.001,
+        },
+        2,
+        2,
+    ),
+];
+/// Number of agents trading the gaps between the exchanges
+pub static NUM_OF_ARBITRAGEURS: u64 = 2;
+/// Number of agents joining the market every news cycle
+pub static NEW_AGENTS_PER_NEWS_CYCLE: u64 = 10;
+/// Shape of the network through which agents copy each other's preferences
+pub static SOCIAL_GRAPH: GraphKind = GraphKind::SmallWorld {
+    neighbors: 6,
+    rewiring_probability: 0.1,
+};
 
 #[derive(Debug)]
 pub enum SerializationError {
@@ -35,6 +131,13 @@ pub enum DeserializationError {
     FailedToReadFile,
 }
 
+#[derive(Debug)]
+pub enum ScenarioError {
+    FileNotFound,
+    /// The line number (starting from 1) which couldn't be understood
+    InvalidLine(usize),
+}
+
 #[derive(Debug)]
 pub enum SimulationError {
     AgentNotFound(u64),
@@ -43,7 +146,13 @@ pub enum SimulationError {
     UnDoable,
 }
 
+/// Writes the data to the file, creating its directory if needed
 pub fn save<T: Serialize>(data: T, file_path: &str) -> Result<(), SerializationError> {
+    if let Some(directory) = Path::new(file_path).parent() {
+        if fs::create_dir_all(directory).is_err() {
+            return Err(SerializationError::FailedToCreateFile);
+        }
+    }
     let Ok(file) = File::create(file_path) else {
         return Err(SerializationError::FailedToCreateFile);
     };
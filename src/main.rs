@@ -1,5 +1,3 @@
-// Main thing to do now is for agents to hold long for certain companies
-
 use rand::{random, Rng};
 use rand_chacha::rand_core::SeedableRng;
 use rand_chacha::ChaCha8Rng;
@@ -10,18 +8,42 @@ use std::sync::{
     Arc,
 };
 use stocks::{
+    bonds::{Bonds, BOND_TRADING_PROBABILITY},
+    currency::Currencies,
+    economy::Economy,
     entities::{
         agents::{Agent, Agents},
         companies::{Companies, Company},
     },
+    etf::{Etfs, ETF_TRADING_PROBABILITY},
+    exchange::Exchanges,
+    execution::ExecutionDesk,
+    index::{IndexWeighting, MarketIndex},
+    institutions::{InstitutionKind, Institutions},
+    instrument::InstrumentId,
     load, log,
     logger::Log,
-    market::Market,
     max,
-    trade_house::{FailedOffer, StockOption, Trade},
+    metrics::{
+        average_returns_by_strategy, gini, wealth_percentiles, HoldingPeriodDistribution,
+        WEALTH_PERCENTILES,
+    },
+    min,
+    money::Money,
+    save,
+    scenario::Scenario,
+    social::SocialNetwork,
+    strategy::{market_state, value_investor_decision, AgentStrategy},
+    trade_house::{FailedOffer, Trade, TradeAction},
     transaction::TodoTransaction,
-    SimulationError, AGENTS_DATA_FILENAME, COMPANIES_DATA_FILENAME, MIN_STRIKE_PRICE,
-    NUM_OF_AGENTS, NUM_OF_COMPANIES,
+    wealth::WealthDistribution,
+    SimulationError, AGENTS_DATA_FILENAME, BONDS_DATA_FILENAME, COMPANIES_DATA_FILENAME,
+    CURRENCIES, CURRENCIES_DATA_FILENAME, ETFS_DATA_FILENAME, EXCHANGES, INITIAL_BALANCES_FILENAME,
+    INITIAL_BALANCE_DISTRIBUTION, INITIAL_HOLDINGS_FILENAME, INITIAL_HOLDING_DISTRIBUTION,
+    INSTITUTIONS_DATA_FILENAME, IPO_BOOK_BUILDING, MIN_STRIKE_PRICE, NEW_AGENTS_PER_NEWS_CYCLE,
+    NEW_COMPANY_LISTING_INTERVAL, NUM_OF_AGENTS, NUM_OF_ARBITRAGEURS,
+    NUM_OF_AUTHORIZED_PARTICIPANTS, NUM_OF_COMPANIES, NUM_OF_FX_DEALERS, NUM_OF_HEDGE_FUNDS,
+    NUM_OF_INDEX_FUNDS, NUM_OF_MUTUAL_FUNDS, NUM_OF_PENSION_FUNDS, SOCIAL_GRAPH,
 };
 
 fn spend_function(x: f64) -> f64 {
@@ -29,12 +51,47 @@ fn spend_function(x: f64) -> f64 {
     0.99 * (1.0 - (-0.01 * x * x).exp()) + 0.01
 }
 
-fn rand_spend_portion_wealth(rng: &mut impl Rng) -> f64 {
+fn rand_spend_portion_wealth(rng: &mut impl Rng, economy: &Economy) -> f64 {
     let Ok(normal) = Normal::new(0.0, 1.0) else {
         // If the normal distribution fails, fuck it then
         return 0.01;
     };
-    spend_function(normal.sample(rng))
+    spend_function(normal.sample(rng)) * economy.spending_multiplier()
+}
+
+/// Cash every agent has put aside for its offers on the exchanges and its bids on the lots
+fn escrowed_cash_by_agent(
+    exchanges: &Exchanges,
+    companies: &Companies,
+    currencies: &Currencies,
+) -> HashMap<u64, Money> {
+    let mut escrowed_cash = exchanges.escrowed_cash(currencies);
+    for (agent_id, cash) in companies.escrowed_cash() {
+        *escrowed_cash.entry(agent_id).or_default() += cash;
+    }
+    escrowed_cash
+}
+
+/// Takes a failed company off the exchanges and out of the pending orders, its bonds defaulting
+fn delist_company(
+    company_id: u64,
+    agents: &mut Agents,
+    companies: &mut Companies,
+    bonds: &mut Bonds,
+    etfs: &mut Etfs,
+    exchanges: &mut Exchanges,
+    execution_desk: &mut ExecutionDesk,
+) {
+    execution_desk.cancel_company_orders(company_id);
+    if let Err(e) = exchanges
+        .primary_mut()
+        .default_bonds(company_id, agents, companies, bonds)
+    {
+        log!(warn "Failed to default the bonds of company {}\n{:?}", company_id, e);
+    }
+    if let Err(e) = exchanges.delist_company(company_id, agents, companies, etfs) {
+        log!(warn "Failed to delist company {}\n{:?}", company_id, e);
+    }
 }
 
 fn main() {
@@ -59,6 +116,29 @@ fn main() {
         }
     };
 
+    let balance_distribution = match WealthDistribution::load_empirical(INITIAL_BALANCES_FILENAME) {
+        Ok(balance_distribution) => {
+            log!(info "Loaded initial balances");
+            balance_distribution
+        }
+        Err(ref e) => {
+            log!(warn "Initial balances file not found\n{:?}", e);
+            INITIAL_BALANCE_DISTRIBUTION.clone()
+        }
+    };
+    let holding_distribution = match WealthDistribution::load_empirical(INITIAL_HOLDINGS_FILENAME) {
+        Ok(holding_distribution) => {
+            log!(info "Loaded initial holdings");
+            holding_distribution
+        }
+        Err(ref e) => {
+            log!(warn "Initial holdings file not found\n{:?}", e);
+            INITIAL_HOLDING_DISTRIBUTION.clone()
+        }
+    };
+
+    // the funds are made of agents, so they only carry over with the agents
+    let resumed = agent_file.is_ok();
     let mut agents = match agent_file {
         Ok(agent_data) => {
             log!(info "Loaded agents");
@@ -70,18 +150,113 @@ fn main() {
             let rng1 = ChaCha8Rng::seed_from_u64(seed + 1);
             let rng2 = ChaCha8Rng::seed_from_u64(seed + 2);
             let rng3 = ChaCha8Rng::seed_from_u64(seed + 3);
-            a.rand_introduce_new_agents(rng1, rng2, NUM_OF_AGENTS, companies.num_of_companies)
-                .unwrap();
+            a.rand_introduce_new_agents(
+                rng1,
+                rng2,
+                &balance_distribution,
+                NUM_OF_AGENTS,
+                companies.num_of_companies,
+            )
+            .unwrap();
             a.rand_give_preferences(rng3, companies.num_of_companies)
                 .unwrap();
+            let agent_ids = a.iter().collect::<Vec<_>>();
+            a.rand_assign_strategies(&mut rng, &agent_ids);
+            a.rand_assign_horizons(&mut rng, &agent_ids);
+            a.rand_assign_incomes(&mut rng, &agent_ids);
+            a.rand_give_initial_holdings(
+                &mut rng,
+                &mut companies,
+                &holding_distribution,
+                &agent_ids,
+            );
             a
         }
     };
 
-    let mut market = Market::new();
+    let mut institutions = match load::<Institutions>(INSTITUTIONS_DATA_FILENAME) {
+        Ok(institutions) if resumed => {
+            log!(info "Loaded institutions");
+            institutions
+        }
+        _ => {
+            let mut institutions = Institutions::new();
+            for (kind, number_of_funds) in [
+                (InstitutionKind::MutualFund, NUM_OF_MUTUAL_FUNDS),
+                (InstitutionKind::PensionFund, NUM_OF_PENSION_FUNDS),
+                (InstitutionKind::HedgeFund, NUM_OF_HEDGE_FUNDS),
+                (InstitutionKind::IndexFund, NUM_OF_INDEX_FUNDS),
+            ] {
+                for _ in 0..number_of_funds {
+                    institutions.rand_found(&mut rng, &mut agents, kind);
+                }
+            }
+            institutions
+        }
+    };
+    let mut execution_desk = ExecutionDesk::new();
+    let mut cap_weighted_index = MarketIndex::new(IndexWeighting::CapWeighted, &companies);
+    let mut equal_weighted_index = MarketIndex::new(IndexWeighting::EqualWeighted, &companies);
+    let mut etfs = match load::<Etfs>(ETFS_DATA_FILENAME) {
+        Ok(etfs) if resumed => {
+            log!(info "Loaded ETFs");
+            etfs
+        }
+        _ => {
+            let mut etfs = Etfs::new();
+            etfs.launch(&cap_weighted_index, &companies);
+            etfs.launch(&equal_weighted_index, &companies);
+            for _ in 0..NUM_OF_AUTHORIZED_PARTICIPANTS {
+                etfs.found_authorized_participant(&mut agents);
+            }
+            etfs
+        }
+    };
+    // the bonds are held by the agents, so they only carry over with the agents
+    let mut bonds = match load::<Bonds>(BONDS_DATA_FILENAME) {
+        Ok(bonds) if resumed => {
+            log!(info "Loaded bonds");
+            bonds
+        }
+        _ => Bonds::new(),
+    };
+    let mut social_network = SocialNetwork::rand_new(&mut rng, SOCIAL_GRAPH, agents.num_of_agents);
+    let mut currencies = match load::<Currencies>(CURRENCIES_DATA_FILENAME) {
+        Ok(currencies) if resumed => {
+            log!(info "Loaded currencies");
+            currencies
+        }
+        _ => {
+            let mut currencies = Currencies::new(CURRENCIES);
+            for _ in 0..NUM_OF_FX_DEALERS {
+                currencies.found_dealer(&mut agents);
+            }
+            currencies
+        }
+    };
+    let mut exchanges = Exchanges::new(EXCHANGES);
+    for company_id in companies.iter() {
+        exchanges.rand_cross_list(&mut rng, company_id);
+    }
+    for _ in 0..NUM_OF_ARBITRAGEURS {
+        exchanges.found_arbitrageur(&mut agents, &currencies);
+    }
+    let mut economy = Economy::new();
+    let mut scenario = match std::env::args().nth(1) {
+        Some(scenario_file) => match Scenario::load(&scenario_file) {
+            Ok(scenario) => {
+                log!(info "Loaded scenario with {} events", scenario.events().len());
+                scenario
+            }
+            Err(e) => {
+                log!(warn "Failed to load the scenario\n{:?}", e);
+                Scenario::new()
+            }
+        },
+        None => Scenario::new(),
+    };
 
-    let mut expired_trades: HashMap<u64, Vec<FailedOffer<Trade>>> = HashMap::new();
-    let mut expired_options: HashMap<u64, Vec<FailedOffer<StockOption>>> = HashMap::new();
+    let mut expired_trades: HashMap<InstrumentId, Vec<FailedOffer<Trade>>> = HashMap::new();
 
     let mut todo_transactions: Vec<TodoTransaction> = Vec::new();
 
@@ -94,46 +269,255 @@ fn main() {
     .expect("Error setting Ctrl-C handler");
     while running.load(Ordering::SeqCst) {
         i += 1;
+        agents.current_tick = i as u64;
         agents.try_offers.clear();
         println!("{}", i);
+        exchanges.primary_mut().tick_halts();
+        currencies.quote(&mut agents, exchanges.primary_mut(), &mut companies);
+        // the slices are sized on the volume of the last tick
+        todo_transactions.extend(execution_desk.slices(i as u64, exchanges.primary()));
+        exchanges.primary_mut().tick_volumes();
+        if let Err(e) = scenario.apply(
+            i as u64,
+            &mut companies,
+            &mut agents,
+            exchanges.primary_mut(),
+            &mut economy,
+            &mut rng,
+        ) {
+            log!(warn "Failed to apply the scenario\n{:?}", e);
+        }
         if i % 5 == 0 {
             for company_id in companies.iter() {
                 let Some(market_value) = companies.market_values.get_mut(company_id as usize)
                 else {
                     continue;
                 };
-                market.tick_individual_company(company_id, market_value);
+                exchanges
+                    .primary_mut()
+                    .tick_individual_company(company_id, market_value);
+            }
+            if let Err(e) = exchanges.tick_failures(&mut agents, &currencies, &mut expired_trades) {
+                log!(warn "Failed to refund the expired offers\n{:?}", e);
+            }
+            // the index funds track the cap-weighted index
+            if cap_weighted_index.tick(&companies) {
+                institutions.rebalance_index_funds();
+                etfs.rebalance(&cap_weighted_index, &companies);
             }
-            market.tick_failures(&mut expired_trades, &mut expired_options);
+            if equal_weighted_index.tick(&companies) {
+                etfs.rebalance(&equal_weighted_index, &companies);
+            }
+            exchanges.primary_mut().tick_etfs(&mut etfs);
+            exchanges.primary_mut().tick_bonds(&mut bonds);
+            exchanges.primary_mut().tick_currencies(&mut currencies);
+            let (share_orders, unit_orders) = etfs.arbitrage(&mut agents, &companies);
+            todo_transactions.extend(share_orders);
+            todo_transactions.extend(unit_orders);
         }
         if i % 20 == 0 {
-            companies.rand_release_news(&mut agents, &mut rng);
+            economy.rand_tick(&mut rng);
+            agents.accrue_interest(economy.interest_per_cycle());
+            let escrowed_cash = escrowed_cash_by_agent(&exchanges, &companies, &currencies);
+            let mut other_assets = institutions.claims(&agents, &companies, &escrowed_cash);
+            for (agent_id, cash) in escrowed_cash {
+                *other_assets.entry(agent_id).or_default() += cash;
+            }
+            for agent_id in agents.earn_and_consume(&companies, &other_assets) {
+                log!(info "Agent {} went broke and is leaving", agent_id);
+                agents.leave(agent_id);
+            }
+            todo_transactions.extend(agents.liquidation_orders(&companies));
+            currencies.rand_drift(&mut rng);
+            currencies.repatriate(&mut agents, exchanges.primary_mut(), &mut companies);
+
+            let first_new_agent_id = agents.num_of_agents;
+            if let Err(e) = agents.rand_introduce_new_agents(
+                ChaCha8Rng::seed_from_u64(rng.gen()),
+                ChaCha8Rng::seed_from_u64(rng.gen()),
+                &balance_distribution,
+                NEW_AGENTS_PER_NEWS_CYCLE,
+                companies.num_of_companies,
+            ) {
+                log!(warn "Failed to introduce new agents\n{:?}", e);
+            }
+            let new_agent_ids = (first_new_agent_id..agents.num_of_agents).collect::<Vec<_>>();
+            agents.rand_assign_strategies(&mut rng, &new_agent_ids);
+            agents.rand_assign_horizons(&mut rng, &new_agent_ids);
+            agents.rand_assign_incomes(&mut rng, &new_agent_ids);
+            social_network.rand_add_agents(&mut rng, SOCIAL_GRAPH, &new_agent_ids);
+
+            let wealth = agents.wealth(&companies, &currencies);
+            log!(info "Wealth: gini: {}, percentiles {:?}: {:?}", gini(&wealth), WEALTH_PERCENTILES, wealth_percentiles(&wealth));
+            let defaulted_companies = exchanges
+                .primary_mut()
+                .pay_bonds(&mut agents, &mut companies, &mut bonds)
+                .unwrap_or_else(|e| {
+                    log!(warn "Failed to pay the bonds\n{:?}", e);
+                    Vec::new()
+                });
+            // the defaulted companies are gone before the news can open offerings for them
+            for company_id in defaulted_companies {
+                delist_company(
+                    company_id,
+                    &mut agents,
+                    &mut companies,
+                    &mut bonds,
+                    &mut etfs,
+                    &mut exchanges,
+                    &mut execution_desk,
+                );
+            }
+            // borrowing comes before the follow-on offerings opened with the news
+            let mut bond_buyers = institutions.bond_buyers();
+            bond_buyers.extend(agents.iter().filter(|agent_id| {
+                agents.is_active(*agent_id)
+                    && agents.strategies[*agent_id as usize] == AgentStrategy::Value
+            }));
+            bonds.rand_consider_issuing(
+                &mut rng,
+                &mut agents,
+                &mut companies,
+                &economy,
+                &bond_buyers,
+            );
+            for company_id in companies.rand_release_news(&mut agents, &economy, &mut rng) {
+                delist_company(
+                    company_id,
+                    &mut agents,
+                    &mut companies,
+                    &mut bonds,
+                    &mut etfs,
+                    &mut exchanges,
+                    &mut execution_desk,
+                );
+            }
+            institutions.pay_financing(
+                &mut agents,
+                &companies,
+                &economy,
+                &escrowed_cash_by_agent(&exchanges, &companies, &currencies),
+            );
+            institutions.rand_place_orders(
+                &mut rng,
+                &agents,
+                &companies,
+                &economy,
+                &cap_weighted_index,
+                &mut execution_desk,
+            );
+            log!(info "Index: cap-weighted: {:?}, equal-weighted: {:?}", cap_weighted_index.history.last(), equal_weighted_index.history.last());
+            log!(info "Exchange fees collected: {:?}", exchanges.fees_collected().iter().map(|fees| fees.to_f64()).collect::<Vec<_>>());
+            log!(info "FX rates: {:?}, fundamentals: {:?}", currencies.rates, currencies.fundamentals);
+            log!(info "ETF premiums: {:?}", etfs.etfs.iter().map(|etf| etf.premium(&companies)).collect::<Vec<_>>());
+            log!(info "Bonds: active: {}, defaulted: {}", bonds.bonds.iter().filter(|bond| bond.is_active()).count(), bonds.bonds.iter().filter(|bond| bond.defaulted).count());
         }
-        agents
-            .alert_agents(&expired_trades, &expired_options)
-            .unwrap();
+        if i % NEW_COMPANY_LISTING_INTERVAL as i128 == 0 {
+            let company_id = companies.rand_list_new_company(i as u64, IPO_BOOK_BUILDING, &mut rng);
+            exchanges.rand_cross_list(&mut rng, company_id);
+            let max_preference = companies.new_listing_preference(company_id);
+            agents.rand_give_preferences_for_company(&mut rng, company_id, max_preference);
+        }
+        agents.alert_agents(&expired_trades).unwrap();
         expired_trades.clear();
-        expired_options.clear();
 
         for agent_id in agents.iter() {
-            let (company_id, mut action) = agents
-                .preferences
-                .get_preferred_random(agent_id, &mut rng)
-                .unwrap();
-
-            // small portion of people who sell low and buy high, because .... IDK WHY
-            if rng.gen_ratio(5, 100) {
-                action = action.complement();
+            if !agents.is_active(agent_id) {
+                continue;
             }
+            let (company_id, action) = match agents.strategies[agent_id as usize] {
+                AgentStrategy::Sentiment => {
+                    if rng.gen_bool(ETF_TRADING_PROBABILITY) {
+                        let budget = agents.balances.get(agent_id).unwrap().to_f64()
+                            * rand_spend_portion_wealth(&mut rng, &economy);
+                        todo_transactions.extend(
+                            etfs.rand_retail_order(&mut rng, &agents, &companies, agent_id, budget),
+                        );
+                        continue;
+                    }
+                    let Ok((company_id, mut action)) =
+                        agents.preferences.get_preferred_random(agent_id, &mut rng)
+                    else {
+                        // the agent has no opinion on any listed company
+                        continue;
+                    };
+                    if !companies.is_listed(company_id) {
+                        continue;
+                    }
+
+                    // small portion of people who sell low and buy high, because .... IDK WHY
+                    if rng.gen_ratio(5, 100) {
+                        action = action.complement();
+                    }
+                    (company_id, action)
+                }
+                AgentStrategy::Value => {
+                    if rng.gen_bool(BOND_TRADING_PROBABILITY) {
+                        let budget = agents.balances.get(agent_id).unwrap().to_f64()
+                            * rand_spend_portion_wealth(&mut rng, &economy);
+                        todo_transactions.extend(bonds.rand_value_order(
+                            &mut rng, &agents, &companies, &economy, agent_id, budget,
+                        ));
+                        continue;
+                    }
+                    let company_id = companies.rand_company_id(&mut rng);
+                    let Some(action) = value_investor_decision(
+                        &companies,
+                        company_id,
+                        &economy,
+                        agents
+                            .holdings
+                            .get(agent_id, InstrumentId::Equity(company_id)),
+                    ) else {
+                        // the price is fair, keep holding
+                        continue;
+                    };
+                    (company_id, action)
+                }
+                AgentStrategy::Learning => {
+                    let company_id = companies.rand_company_id(&mut rng);
+                    if !companies.is_listed(company_id) {
+                        continue;
+                    }
+                    let state = market_state(
+                        companies.news[company_id as usize],
+                        agents
+                            .holdings
+                            .get(agent_id, InstrumentId::Equity(company_id)),
+                    );
+                    let realized_pnl = agents.realized_pnl[agent_id as usize].to_f64();
+                    let Some(learner) = agents.learners[agent_id as usize].as_mut() else {
+                        continue;
+                    };
+                    let Some(action) = learner.rand_decide(&mut rng, state, realized_pnl) else {
+                        continue;
+                    };
+                    (company_id, action)
+                }
+                // the funds trade through the execution desk
+                AgentStrategy::Institutional => continue,
+                // the market makers only trade through `Etfs::arbitrage`
+                AgentStrategy::AuthorizedParticipant => continue,
+                // and the arbitrageurs through `Exchanges::arbitrage`
+                AgentStrategy::Arbitrageur => continue,
+                // the dealers only quote the currencies
+                AgentStrategy::FxDealer => continue,
+            };
 
             let failable_value = rng.gen_range(10.0..2_000.0);
             let current_price = companies
                 .get_current_price(company_id)
                 .unwrap_or(failable_value);
             companies.market_values[company_id as usize].current_price = current_price;
+            if action == TradeAction::Sell
+                && !agents.is_willing_to_sell(agent_id, company_id, current_price)
+            {
+                // holding until the horizon
+                continue;
+            }
             let strike_price = max(MIN_STRIKE_PRICE, current_price + rng.gen_range(-10.0..10.0));
-            let want_to_spend =
-                agents.balances.get(agent_id).unwrap() * rand_spend_portion_wealth(&mut rng);
+            let want_to_spend = agents.balances.get(agent_id).unwrap().to_f64()
+                * rand_spend_portion_wealth(&mut rng, &economy);
             let rough_amount_of_stocks = (want_to_spend / strike_price).floor() as u64;
             if rough_amount_of_stocks == 0 {
                 // bruh, just don't trade anything
@@ -142,24 +526,30 @@ fn main() {
 
             todo_transactions.push(TodoTransaction {
                 agent_id,
-                company_id,
-                strike_price,
+                instrument_id: InstrumentId::Equity(company_id),
+                strike_price: Money::from(strike_price),
                 action,
                 trade: Trade::new(rough_amount_of_stocks),
             });
         }
         let news_probability_distribution = &companies.generate_preferences_from_news(&mut rng);
         agents.rand_give_preferences_from_news(&mut rng, news_probability_distribution);
-        let Err(e) = market.rand_do_trade(
-            &mut rng,
+        social_network.rand_spread(&mut rng, &mut agents);
+        exchanges.submit(
+            todo_transactions.drain(..),
             &mut agents,
             &mut companies,
-            &mut todo_transactions,
-        ) else {
-            todo_transactions.clear();
+            &currencies,
+            i as u64,
+        );
+        for (exchange_id, order) in exchanges.arbitrage(&agents, &companies, &currencies) {
+            exchanges.send(exchange_id, order, i as u64);
+        }
+        let Err(e) =
+            exchanges.rand_do_trade(&mut rng, &mut agents, &mut companies, &currencies, i as u64)
+        else {
             continue;
         };
-        todo_transactions.clear();
         match e {
             SimulationError::AgentNotFound(agent_id) => {
                 log!(warn "Agent not found: {}", agent_id);
@@ -173,19 +563,46 @@ fn main() {
         }
     }
     log!(info "Exiting at index {:?}", i);
+    log!(info "Closed holding periods: {:?}", HoldingPeriodDistribution::new(&agents.closed_holding_periods));
+    log!(info "Open holding periods: {:?}", HoldingPeriodDistribution::new(&agents.open_holding_periods()));
+    log!(info "Average returns by strategy: {:?}", average_returns_by_strategy(&agents, &companies));
+    log!(info "Best returns: {:?}", &agents.rank_by_returns(&companies)[..min(10, agents.num_of_agents as usize)]);
     log!(info "Saving data");
 
-    /*
-    if let Err(e) = save(agents.save().unwrap(), AGENTS_DATA_FILENAME) {
-        log!(warn "Failed to save agents data\n{:?}", e);
-    } else {
-        log!(info "Saved agents");
+    match agents.save() {
+        Ok(saved_agents) => {
+            if let Err(e) = save(saved_agents, AGENTS_DATA_FILENAME) {
+                log!(warn "Failed to save agents data\n{:?}", e);
+            } else {
+                log!(info "Saved agents");
+            }
+        }
+        Err(e) => log!(warn "Failed to save agents data\n{:?}", e),
     }
     if let Err(e) = save(companies.save(), COMPANIES_DATA_FILENAME) {
         log!(warn "Failed to save company data\n{:?}", e);
     } else {
         log!(info "Saved companies");
     }
-    */
+    if let Err(e) = save(&institutions, INSTITUTIONS_DATA_FILENAME) {
+        log!(warn "Failed to save institution data\n{:?}", e);
+    } else {
+        log!(info "Saved institutions");
+    }
+    if let Err(e) = save(&etfs, ETFS_DATA_FILENAME) {
+        log!(warn "Failed to save ETF data\n{:?}", e);
+    } else {
+        log!(info "Saved ETFs");
+    }
+    if let Err(e) = save(&bonds, BONDS_DATA_FILENAME) {
+        log!(warn "Failed to save bond data\n{:?}", e);
+    } else {
+        log!(info "Saved bonds");
+    }
+    if let Err(e) = save(&currencies, CURRENCIES_DATA_FILENAME) {
+        log!(warn "Failed to save currency data\n{:?}", e);
+    } else {
+        log!(info "Saved currencies");
+    }
     log!(info "Exit");
 }
@@ -1,7 +1,12 @@
 use crate::{
+    bonds::Bonds,
+    currency::{Currencies, HOME_CURRENCY},
     entities::{agents::Agents, companies::Companies, companies::MarketValue},
+    etf::Etfs,
+    instrument::InstrumentId,
     max, min,
-    trade_house::{FailedOffer, Offer, StockOption, Trade, TradeAction, TradeHouse},
+    money::Money,
+    trade_house::{FailedOffer, Offer, Trade, TradeAction, TradeHouse},
     transaction::{TodoTransaction, Transaction},
     SimulationError,
 };
@@ -16,10 +21,36 @@ pub struct Market {
     /// When it is called, the maximum and minimum values in the vec are stored in
     /// `highest_price` and `lowest_price`. And the average is set at `current_price`
     /// Also calculate the standard deviation and store it in `standard_deviation`
-    recent_transactions: HashMap<u64, Vec<f64>>,
+    recent_transactions: HashMap<InstrumentId, Vec<f64>>,
     pub house: TradeHouse,
+    /// Number of ticks left in the trading halt of each company
+    halts: HashMap<u64, u64>,
+    /// Number of ticks left in the trading halt of the whole market
+    market_halt: u64,
+    /// Number of shares of each instrument traded during the current tick
+    volumes: HashMap<InstrumentId, u64>,
+    /// Moving average of the number of shares of each instrument traded per tick
+    average_volumes: HashMap<InstrumentId, f64>,
+    pub fees: FeeSchedule,
+    /// Fees charged so far, net of the rebates paid out of them
+    pub fees_collected: Money,
+    /// Currency the prices are quoted in and the trades settled in
+    pub currency: u64,
 }
 
+/// Fees charged on every trade as a portion of its value
+///
+/// The maker is the agent whose offer was waiting in the book, the taker the one who hit it.
+/// A negative fee is a rebate
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct FeeSchedule {
+    pub maker: f64,
+    pub taker: f64,
+}
+
+/// Weight of the latest tick in the moving average of the volume
+pub const VOLUME_AVERAGE_WEIGHT: f64 = 0.1;
+
 #[derive(Debug)]
 pub enum ActionState {
     AddedToOffers,
@@ -31,6 +62,12 @@ impl Market {
     pub fn new() -> Self {
         Self::default()
     }
+    pub fn with_fees(fees: FeeSchedule) -> Self {
+        Self {
+            fees,
+            ..Self::default()
+        }
+    }
 
     pub fn rand_do_trade(
         &mut self,
@@ -40,6 +77,7 @@ impl Market {
         transactions: &mut [TodoTransaction],
     ) -> Result<(), SimulationError> {
         for todo_transaction in transactions.iter() {
+            let todo_transaction = &Self::snap_to_tick(todo_transaction, companies);
             let Ok(Some(possible_offers)) = self.trade(
                 rng.gen_ratio(6, 10),
                 todo_transaction,
@@ -50,22 +88,39 @@ impl Market {
                 continue;
             };
 
-            if rng.gen_ratio(7, 10) {
-                continue;
-            }
-
             let offer_idx = rng.gen_range(0..possible_offers.len());
-            let all_offers = self.house.get_mut_trade_offers(todo_transaction.company_id);
+            let all_offers = self
+                .house
+                .get_mut_trade_offers(todo_transaction.instrument_id);
             let target_offers = match todo_transaction.action {
                 TradeAction::Buy => &mut all_offers.seller_offers,
                 TradeAction::Sell => &mut all_offers.buyer_offers,
             };
+            // a buyer taking a dearer ask pays the difference on top of what it put aside
+            let extra_cost = match (todo_transaction.action, target_offers.get(offer_idx)) {
+                (TradeAction::Buy, Some(offer)) => max(
+                    offer.strike_price - todo_transaction.strike_price,
+                    Money::ZERO,
+                )
+                .times(min(
+                    offer.data.number_of_shares,
+                    todo_transaction.trade.number_of_shares,
+                )),
+                _ => Money::ZERO,
+            };
+            let affordable = agents
+                .balances
+                .get_in(todo_transaction.agent_id, self.currency)
+                .is_ok_and(|balance| balance >= extra_cost);
+            if rng.gen_ratio(7, 10) || !affordable {
+                agents.refund_assets_from_todotransaction_in(todo_transaction, self.currency)?;
+                continue;
+            }
             let offer = target_offers.remove(offer_idx);
 
             let transaction = self
                 .convert_trade_offer_and_todo_transaction_to_transaction(&offer, todo_transaction);
-            self.add_transaction(todo_transaction.company_id, transaction.strike_price);
-            agents.exchange_assets_from_transaction(&transaction)?;
+            self.settle(&transaction, todo_transaction, agents)?;
         }
         Ok(())
     }
@@ -77,21 +132,34 @@ impl Market {
         companies: &mut Companies,
         acceptable_strike_price_deviation: f64,
     ) -> Result<Option<Vec<Offer<Trade>>>, SimulationError> {
-        agents.deduct_assets_from_todotransaction(todo_transaction)?;
-
-        //
-        if companies.check_lots_from_todotransaction(todo_transaction)
+        let tradable = match todo_transaction.instrument_id.equity() {
+            Some(company_id) => companies.is_listed(company_id) && !self.is_halted(company_id),
+            None => self.market_halt == 0,
+        };
+        if !tradable {
+            return Err(SimulationError::UnDoable);
+        }
+        let todo_transaction = &Self::snap_to_tick(todo_transaction, companies);
+        // if the bet can't be placed, try the market instead
+        if todo_transaction.action == TradeAction::Buy
+            && self.currency == HOME_CURRENCY
             && willing_to_accept_company_shares_if_they_are_present
+            && companies.check_lots_from_todotransaction(todo_transaction)
+            && companies
+                .add_bet_from_todotransaction(agents, todo_transaction)
+                .is_ok()
         {
-            companies.add_bet_from_todotransaction(todo_transaction);
             return Ok(None);
         }
 
+        agents.deduct_assets_from_todotransaction_in(todo_transaction, self.currency)?;
+
         // Check if there is an appropriate trade offer
+        let strike_price = todo_transaction.strike_price;
         let appropriate_trade_offer = self.house.get_appropriate_trade_offer(
-            todo_transaction.company_id,
-            todo_transaction.strike_price,
-            acceptable_strike_price_deviation,
+            todo_transaction.instrument_id,
+            strike_price,
+            Money::from(acceptable_strike_price_deviation),
             todo_transaction.action.complement(),
         );
 
@@ -107,7 +175,9 @@ impl Market {
                 .add_trade_offer_from_todo_transaction(todo_transaction);
             return Ok(None);
         }
-        let all_offers = self.house.get_mut_trade_offers(todo_transaction.company_id);
+        let all_offers = self
+            .house
+            .get_mut_trade_offers(todo_transaction.instrument_id);
         let target_offers = match todo_transaction.action {
             TradeAction::Buy => &mut all_offers.seller_offers,
             TradeAction::Sell => &mut all_offers.buyer_offers,
@@ -116,7 +186,7 @@ impl Market {
         for offer_idx in offer_idxs.iter() {
             let offer = target_offers[*offer_idx].clone();
             // Don't autoresolve if it can be slightly worse for us
-            if offer.strike_price > todo_transaction.strike_price {
+            if offer.strike_price > strike_price {
                 continue;
             }
 
@@ -125,8 +195,7 @@ impl Market {
 
             let transaction = self
                 .convert_trade_offer_and_todo_transaction_to_transaction(&offer, todo_transaction);
-            self.add_transaction(todo_transaction.company_id, transaction.strike_price);
-            agents.exchange_assets_from_transaction(&transaction)?;
+            self.settle(&transaction, todo_transaction, agents)?;
             return Ok(None);
         }
 
@@ -138,6 +207,77 @@ impl Market {
         ))
     }
 
+    /// The order with its strike price on the tick grid of the instrument,
+    /// the buyers rounding down and the sellers up so nobody pays more than it asked to
+    pub fn snap_to_tick(
+        todo_transaction: &TodoTransaction,
+        companies: &Companies,
+    ) -> TodoTransaction {
+        let tick_size = companies.tick_size(todo_transaction.instrument_id);
+        let strike_price = todo_transaction.strike_price;
+        let strike_price = match todo_transaction.action {
+            TradeAction::Buy => strike_price.floor_to(tick_size),
+            TradeAction::Sell => strike_price.ceil_to(tick_size),
+        };
+        TodoTransaction {
+            agent_id: todo_transaction.agent_id,
+            instrument_id: todo_transaction.instrument_id,
+            strike_price: max(strike_price, tick_size),
+            action: todo_transaction.action,
+            trade: todo_transaction.trade.clone(),
+        }
+    }
+
+    /// Records the trade and hands over the shares and the money, charging the taker and the maker their fees
+    ///
+    /// A buyer taking an offer put aside its own strike price, the difference with the price of the trade
+    /// is given back (or charged)
+    fn settle(
+        &mut self,
+        transaction: &Transaction,
+        taker: &TodoTransaction,
+        agents: &mut Agents,
+    ) -> Result<(), SimulationError> {
+        let taker_id = taker.agent_id;
+        if taker.action == TradeAction::Buy {
+            let price_improvement =
+                (taker.strike_price - transaction.strike_price).times(transaction.number_of_shares);
+            agents
+                .balances
+                .add_in(taker_id, self.currency, price_improvement)?;
+        }
+        self.add_transaction(
+            transaction.instrument_id,
+            transaction.strike_price.to_f64(),
+            transaction.number_of_shares,
+        );
+        agents.exchange_assets_from_transaction_in(transaction, self.currency)?;
+        let value = transaction.strike_price.times(transaction.number_of_shares);
+        let maker_id = if taker.action == TradeAction::Buy {
+            transaction.seller_id
+        } else {
+            transaction.buyer_id
+        };
+        for (agent_id, rate) in [(taker_id, self.fees.taker), (maker_id, self.fees.maker)] {
+            if rate == 0.0 {
+                continue;
+            }
+            let fee = if rate < 0.0 {
+                // the rebates are paid out of the fees collected, the taker's included
+                max(value.scale(rate), -self.fees_collected)
+            } else {
+                // nobody is charged more than it has left
+                min(
+                    value.scale(rate),
+                    agents.balances.get_in(agent_id, self.currency)?,
+                )
+            };
+            agents.balances.add_in(agent_id, self.currency, -fee)?;
+            self.fees_collected += fee;
+        }
+        Ok(())
+    }
+
     pub fn convert_trade_offer_and_todo_transaction_to_transaction(
         &mut self,
         offer: &Offer<Trade>,
@@ -150,11 +290,11 @@ impl Market {
 
         if offer.data.number_of_shares == todo_transaction.trade.number_of_shares {
             self.house
-                .remove_trade_offer(todo_transaction.company_id, offer.clone());
+                .remove_trade_offer(todo_transaction.instrument_id, offer.clone());
             return Transaction::new(
                 buyer_id,
                 seller_id,
-                todo_transaction.company_id,
+                todo_transaction.instrument_id,
                 todo_transaction.trade.number_of_shares,
                 offer.strike_price,
             );
@@ -163,7 +303,7 @@ impl Market {
         if offer.data.number_of_shares > todo_transaction.trade.number_of_shares {
             self.house.add_trade_offer(
                 offer.offerer_id,
-                todo_transaction.company_id,
+                todo_transaction.instrument_id,
                 offer.strike_price,
                 Trade::new(offer.data.number_of_shares - todo_transaction.trade.number_of_shares),
                 todo_transaction.action.complement(),
@@ -171,14 +311,14 @@ impl Market {
             return Transaction::new(
                 buyer_id,
                 seller_id,
-                todo_transaction.company_id,
+                todo_transaction.instrument_id,
                 todo_transaction.trade.number_of_shares,
                 offer.strike_price,
             );
         }
         self.house.add_trade_offer(
             todo_transaction.agent_id,
-            todo_transaction.company_id,
+            todo_transaction.instrument_id,
             todo_transaction.strike_price,
             Trade::new(todo_transaction.trade.number_of_shares - offer.data.number_of_shares),
             todo_transaction.action,
@@ -186,18 +326,142 @@ impl Market {
         Transaction::new(
             buyer_id,
             seller_id,
-            todo_transaction.company_id,
+            todo_transaction.instrument_id,
             offer.data.number_of_shares,
             offer.strike_price,
         )
     }
 
-    pub fn add_transaction(&mut self, company_id: u64, price: f64) {
-        let tracker = self.recent_transactions.entry(company_id).or_default();
+    /// Average price of the trades of the instrument since the last tick,
+    /// or the quotes of its book when nothing traded
+    fn last_price(&mut self, instrument_id: InstrumentId) -> Option<f64> {
+        let prices = self.recent_transactions.entry(instrument_id).or_default();
+        if !prices.is_empty() {
+            let price = prices.iter().sum::<f64>() / prices.len() as f64;
+            prices.clear();
+            return Some(price);
+        }
+        match (
+            self.house.best_bid(instrument_id),
+            self.house.best_ask(instrument_id),
+        ) {
+            (Some(best_bid), Some(best_ask)) => Some((best_bid + best_ask).to_f64() / 2.0),
+            (Some(quote), None) | (None, Some(quote)) => Some(quote.to_f64()),
+            (None, None) => None,
+        }
+    }
+    /// Prices the ETFs at the average of their trades since the last tick,
+    /// or at the quotes of their book when nothing traded
+    pub fn tick_etfs(&mut self, etfs: &mut Etfs) {
+        for etf in etfs.etfs.iter_mut() {
+            if let Some(price) = self.last_price(InstrumentId::Etf(etf.id)) {
+                etf.price = Money::from(price);
+            }
+        }
+    }
+    /// Sets the rates of the currencies from their trades the same way as the ETFs
+    pub fn tick_currencies(&mut self, currencies: &mut Currencies) {
+        for currency in 1..currencies.rates.len() {
+            if let Some(rate) = self.last_price(InstrumentId::Fx(currency as u64)) {
+                currencies.rates[currency] = rate;
+            }
+        }
+    }
+    /// Prices the bonds still paying coupons the same way as the ETFs
+    pub fn tick_bonds(&mut self, bonds: &mut Bonds) {
+        for bond in bonds.bonds.iter_mut().filter(|bond| bond.is_active()) {
+            if let Some(price) = self.last_price(InstrumentId::Bond(bond.id)) {
+                bond.price = Money::from(price);
+            }
+        }
+    }
+    /// Pays the coupons and repays the maturing bonds, after returning the ones offered for sale
+    ///
+    /// Returns the companies in default
+    pub fn pay_bonds(
+        &mut self,
+        agents: &mut Agents,
+        companies: &mut Companies,
+        bonds: &mut Bonds,
+    ) -> Result<Vec<u64>, SimulationError> {
+        for bond_id in bonds.maturing(agents.current_tick) {
+            self.cancel_instrument(InstrumentId::Bond(bond_id), agents)?;
+        }
+        Ok(bonds.pay_coupons(agents, companies))
+    }
+    /// Cancels and refunds the offers for the bonds of a bankrupt company and pays out its bondholders
+    ///
+    /// Needs to happen before `delist_company`, the bondholders come before the shareholders
+    pub fn default_bonds(
+        &mut self,
+        company_id: u64,
+        agents: &mut Agents,
+        companies: &mut Companies,
+        bonds: &mut Bonds,
+    ) -> Result<(), SimulationError> {
+        for bond_id in bonds.company_bonds(company_id) {
+            self.cancel_instrument(InstrumentId::Bond(bond_id), agents)?;
+        }
+        bonds.default_company(agents, companies, company_id)
+    }
+    /// Cancels and refunds the offers of the agent for the instrument, the book staying open
+    pub fn cancel_agent_offers(
+        &mut self,
+        instrument_id: InstrumentId,
+        agent_id: u64,
+        agents: &mut Agents,
+    ) -> Result<(), SimulationError> {
+        let cancelled = self.house.cancel_agent_offers(instrument_id, agent_id);
+        agents.refund_failed_offers_in(&cancelled.failed_trade_offer, self.currency)
+    }
+    /// Cancels and refunds the offers for an instrument which stops trading
+    fn cancel_instrument(
+        &mut self,
+        instrument_id: InstrumentId,
+        agents: &mut Agents,
+    ) -> Result<(), SimulationError> {
+        let cancelled = self.house.cancel_offers(instrument_id);
+        self.recent_transactions.remove(&instrument_id);
+        self.volumes.remove(&instrument_id);
+        self.average_volumes.remove(&instrument_id);
+        agents.refund_failed_offers_in(&cancelled.failed_trade_offer, self.currency)
+    }
+
+    pub fn add_transaction(
+        &mut self,
+        instrument_id: InstrumentId,
+        price: f64,
+        number_of_shares: u64,
+    ) {
+        let tracker = self.recent_transactions.entry(instrument_id).or_default();
         tracker.push(price);
+        *self.volumes.entry(instrument_id).or_default() += number_of_shares;
+    }
+    /// Number of shares of the instrument traded so far this tick
+    pub fn volume(&self, instrument_id: InstrumentId) -> u64 {
+        self.volumes.get(&instrument_id).copied().unwrap_or(0)
+    }
+    pub fn average_volume(&self, instrument_id: InstrumentId) -> f64 {
+        self.average_volumes
+            .get(&instrument_id)
+            .copied()
+            .unwrap_or(0.0)
+    }
+    /// Folds the volume of the tick into the moving averages and starts counting again
+    pub fn tick_volumes(&mut self) {
+        for (instrument_id, average_volume) in self.average_volumes.iter_mut() {
+            let volume = self.volumes.get(instrument_id).copied().unwrap_or(0);
+            *average_volume += VOLUME_AVERAGE_WEIGHT * (volume as f64 - *average_volume);
+        }
+        for (instrument_id, volume) in self.volumes.drain() {
+            self.average_volumes
+                .entry(instrument_id)
+                .or_insert(VOLUME_AVERAGE_WEIGHT * volume as f64);
+        }
     }
     pub fn tick_individual_company(&mut self, company_id: u64, market_value: &mut MarketValue) {
-        let recent_transactions = self.recent_transactions.entry(company_id).or_default();
+        let instrument_id = InstrumentId::Equity(company_id);
+        let recent_transactions = self.recent_transactions.entry(instrument_id).or_default();
         if recent_transactions.is_empty() {
             market_value.highest_price = market_value.current_price;
             market_value.lowest_price = market_value.current_price;
@@ -219,18 +483,87 @@ impl Market {
         market_value.overall_movement_end = *recent_transactions.last().unwrap();
 
         self.recent_transactions
-            .entry(company_id)
+            .entry(instrument_id)
             .and_modify(|transactions| transactions.clear());
     }
 
+    /// Moves the trades and the volume of another exchange into this one,
+    /// so the prices and volumes are made from every trade
+    ///
+    /// `rate` is the price of the currency of the other exchange in the currency of this one
+    pub fn consolidate(&mut self, other: &mut Market, rate: f64) {
+        for (instrument_id, prices) in other.recent_transactions.drain() {
+            self.recent_transactions
+                .entry(instrument_id)
+                .or_default()
+                .extend(prices.into_iter().map(|price| price * rate));
+        }
+        for (instrument_id, volume) in other.volumes.drain() {
+            *self.volumes.entry(instrument_id).or_default() += volume;
+        }
+    }
+
+    /// Cancels and refunds all the offers of a bankrupt company and removes it from the simulation
+    pub fn delist_company(
+        &mut self,
+        company_id: u64,
+        agents: &mut Agents,
+        companies: &mut Companies,
+        etfs: &mut Etfs,
+    ) -> Result<(), SimulationError> {
+        self.cancel_company(company_id, agents)?;
+        companies.delist(company_id, agents, etfs)
+    }
+    /// Cancels and refunds all the offers for the instruments of the company
+    pub fn cancel_company(
+        &mut self,
+        company_id: u64,
+        agents: &mut Agents,
+    ) -> Result<(), SimulationError> {
+        let cancelled = self.house.cancel_company_offers(company_id);
+        agents.refund_failed_offers_in(&cancelled.failed_trade_offer, self.currency)?;
+        let is_company_instrument =
+            |instrument_id: &InstrumentId| instrument_id.equity() == Some(company_id);
+        self.recent_transactions
+            .retain(|instrument_id, _| !is_company_instrument(instrument_id));
+        self.volumes
+            .retain(|instrument_id, _| !is_company_instrument(instrument_id));
+        self.average_volumes
+            .retain(|instrument_id, _| !is_company_instrument(instrument_id));
+        Ok(())
+    }
+
+    /// Stops the trading of a company, or the whole market if no company is given
+    ///
+    /// A halt of 0 ticks is ignored
+    pub fn halt(&mut self, company_id: Option<u64>, duration: u64) {
+        if duration == 0 {
+            return;
+        }
+        let Some(company_id) = company_id else {
+            self.market_halt = max(self.market_halt, duration);
+            return;
+        };
+        let halt = self.halts.entry(company_id).or_default();
+        *halt = max(*halt, duration);
+    }
+    pub fn is_halted(&self, company_id: u64) -> bool {
+        self.market_halt != 0 || self.halts.contains_key(&company_id)
+    }
+    pub fn tick_halts(&mut self) {
+        self.market_halt = self.market_halt.saturating_sub(1);
+        self.halts.retain(|_, halt| {
+            *halt = halt.saturating_sub(1);
+            *halt != 0
+        });
+    }
+
     pub fn tick_failures(
         &mut self,
-        expired_trades: &mut HashMap<u64, Vec<FailedOffer<Trade>>>,
-        expired_options: &mut HashMap<u64, Vec<FailedOffer<StockOption>>>,
+        expired_trades: &mut HashMap<InstrumentId, Vec<FailedOffer<Trade>>>,
     ) {
         let house_tick_data = self.house.tick();
         expired_trades.extend(house_tick_data.failed_trade_offer);
-        expired_options.extend(house_tick_data.failed_option_offer);
     }
 }
 
@@ -238,6 +571,15 @@ impl MarketValue {
     pub fn new() -> Self {
         Self::default()
     }
+    pub fn at_price(price: f64) -> Self {
+        Self {
+            current_price: price,
+            highest_price: price,
+            lowest_price: price,
+            overall_movement_start: price,
+            overall_movement_end: price,
+        }
+    }
     pub fn rand(rng: &mut impl Rng) -> Self {
         Self {
             current_price: rng.gen_range(0.0..100.0),
@@ -0,0 +1,97 @@
+use crate::{
+    entities::{agents::Agents, companies::Companies},
+    strategy::AgentStrategy,
+};
+
+/// Summary of how long the agents held their positions, in ticks
+#[derive(Debug, Clone, PartialEq)]
+pub struct HoldingPeriodDistribution {
+    pub count: usize,
+    pub mean: f64,
+    pub median: u64,
+    pub p90: u64,
+    pub max: u64,
+}
+
+/// Percentiles reported for the wealth of the agents
+pub const WEALTH_PERCENTILES: [f64; 5] = [10.0, 25.0, 50.0, 90.0, 99.0];
+
+/// Value under which `percentile` percent of the sorted data falls
+pub fn percentile<T: Copy>(sorted_data: &[T], percentile: f64) -> Option<T> {
+    if sorted_data.is_empty() {
+        return None;
+    }
+    let index = ((sorted_data.len() - 1) as f64 * percentile / 100.0).round() as usize;
+    sorted_data.get(index).copied()
+}
+
+impl HoldingPeriodDistribution {
+    /// Returns `None` if there are no holding periods
+    pub fn new(holding_periods: &[u64]) -> Option<Self> {
+        let mut sorted_data = holding_periods.to_vec();
+        sorted_data.sort_unstable();
+        Some(Self {
+            count: sorted_data.len(),
+            mean: sorted_data.iter().sum::<u64>() as f64 / sorted_data.len() as f64,
+            median: percentile(&sorted_data, 50.0)?,
+            p90: percentile(&sorted_data, 90.0)?,
+            max: *sorted_data.last()?,
+        })
+    }
+}
+
+/// Average returns of the agents following each strategy, skipping strategies no one follows
+pub fn average_returns_by_strategy(
+    agents: &Agents,
+    companies: &Companies,
+) -> Vec<(AgentStrategy, f64)> {
+    [
+        AgentStrategy::Sentiment,
+        AgentStrategy::Value,
+        AgentStrategy::Learning,
+        AgentStrategy::Institutional,
+        AgentStrategy::AuthorizedParticipant,
+        AgentStrategy::Arbitrageur,
+        AgentStrategy::FxDealer,
+    ]
+    .into_iter()
+    .filter_map(|strategy| {
+        let returns = agents
+            .iter()
+            .filter(|agent_id| agents.strategies[*agent_id as usize] == strategy)
+            .map(|agent_id| agents.returns(agent_id, companies))
+            .collect::<Vec<_>>();
+        if returns.is_empty() {
+            return None;
+        }
+        Some((strategy, returns.iter().sum::<f64>() / returns.len() as f64))
+    })
+    .collect()
+}
+
+/// Inequality of the values, 0 when everyone has the same and close to 1 when one has everything
+pub fn gini(values: &[f64]) -> f64 {
+    let mut sorted_data = values.to_vec();
+    sorted_data.sort_by(f64::total_cmp);
+    let total = sorted_data.iter().sum::<f64>();
+    if sorted_data.is_empty() || total <= 0.0 {
+        return 0.0;
+    }
+    let n = sorted_data.len() as f64;
+    let weighted_sum = sorted_data
+        .iter()
+        .enumerate()
+        .map(|(i, value)| (i + 1) as f64 * value)
+        .sum::<f64>();
+    2.0 * weighted_sum / (n * total) - (n + 1.0) / n
+}
+
+/// The `WEALTH_PERCENTILES` of the wealth of the agents
+pub fn wealth_percentiles(wealth: &[f64]) -> Vec<f64> {
+    let mut sorted_data = wealth.to_vec();
+    sorted_data.sort_by(f64::total_cmp);
+    WEALTH_PERCENTILES
+        .iter()
+        .filter_map(|p| percentile(&sorted_data, *p))
+        .collect()
+}
@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    iter::Sum,
+    ops::{Add, AddAssign, Neg, Sub, SubAssign},
+};
+
+/// Number of minor units in a unit of any currency
+pub const MINOR_UNITS: i64 = 10_000;
+
+/// An amount of money or a price, counted in whole minor units so it adds up exactly
+///
+/// Rounding only happens when a rate is applied to it, or a float is turned into it
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub struct Money(pub i64);
+
+impl Money {
+    pub const ZERO: Self = Self(0);
+    /// The smallest amount there is
+    pub const MINOR_UNIT: Self = Self(1);
+    /// A whole unit of the currency
+    pub const UNIT: Self = Self(MINOR_UNITS);
+
+    /// Exactly `units` whole units of the currency
+    pub fn from_units(units: u64) -> Self {
+        Self::UNIT.times(units)
+    }
+    /// The whole units in the amount, the rest being dropped, none when it is negative
+    pub fn whole_units(self) -> u64 {
+        (self.0.max(0) / MINOR_UNITS) as u64
+    }
+    /// Rounds to the nearest minor unit
+    pub fn from_f64(amount: f64) -> Self {
+        Self((amount * MINOR_UNITS as f64).round() as i64)
+    }
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / MINOR_UNITS as f64
+    }
+    /// Value of `quantity` of something at this price
+    ///
+    /// Saturates instead of overflowing, so an absurd quantity costs more than anyone has
+    pub fn times(self, quantity: u64) -> Self {
+        let value = i64::try_from(quantity)
+            .ok()
+            .and_then(|quantity| self.0.checked_mul(quantity));
+        match value {
+            Some(value) => Self(value),
+            None if self.0 == 0 => Self::ZERO,
+            None if self.0 < 0 => Self(i64::MIN),
+            None => Self(i64::MAX),
+        }
+    }
+    /// The portion of the amount given by `rate`, rounded to the nearest minor unit
+    pub fn scale(self, rate: f64) -> Self {
+        Self((self.0 as f64 * rate).round() as i64)
+    }
+    /// The highest multiple of the tick size not above the amount
+    pub fn floor_to(self, tick_size: Self) -> Self {
+        Self(self.0.div_euclid(tick_size.0) * tick_size.0)
+    }
+    /// The lowest multiple of the tick size not below the amount
+    pub fn ceil_to(self, tick_size: Self) -> Self {
+        Self(-(-self.0).div_euclid(tick_size.0) * tick_size.0)
+    }
+}
+
+impl From<f64> for Money {
+    fn from(amount: f64) -> Self {
+        Self::from_f64(amount)
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let minor_units = self.0.unsigned_abs();
+        write!(
+            f,
+            "{}{}.{:04}",
+            sign,
+            minor_units / MINOR_UNITS as u64,
+            minor_units % MINOR_UNITS as u64
+        )
+    }
+}
+
+impl Add for Money {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Self(self.0 + other.0)
+    }
+}
+
+impl Sub for Money {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        Self(self.0 - other.0)
+    }
+}
+
+impl Neg for Money {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self(-self.0)
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, other: Self) {
+        self.0 += other.0;
+    }
+}
+
+impl SubAssign for Money {
+    fn sub_assign(&mut self, other: Self) {
+        self.0 -= other.0;
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        Self(iter.map(|money| money.0).sum())
+    }
+}
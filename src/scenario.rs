@@ -0,0 +1,202 @@
+use crate::{
+    economy::Economy,
+    entities::{agents::Agents, companies::Companies},
+    log,
+    logger::Log,
+    market::Market,
+    money::Money,
+    ScenarioError, SimulationError, IPO_BOOK_BUILDING,
+};
+use rand::Rng;
+use std::fs;
+
+/// Who an event is aimed at, `*` in a scenario file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    All,
+    One(u64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScenarioEvent {
+    /// `news <company_id|*> <profit_percent>`, overrides the news of the company
+    News {
+        company: Target,
+        profit_percent: f64,
+    },
+    /// `hype <company_id> <strength>`
+    Hype { company_id: u64, strength: f64 },
+    /// `cash <agent_id|*> <amount>`, gives (or takes) money from agents
+    Cash { agent: Target, amount: f64 },
+    /// `halt <company_id|*> <duration>`, stops trading for a number of ticks
+    Halt { company: Target, duration: u64 },
+    /// `list`, lists a new company
+    List,
+    /// `rate <interest_rate>`, sets the yearly interest rate
+    InterestRate(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduledEvent {
+    pub tick: u64,
+    pub event: ScenarioEvent,
+}
+
+/// Events to apply to the simulation at certain ticks
+///
+/// A scenario file has an event per line, `<tick> <event> <arguments...>`,
+/// everything after a `#` is ignored
+/// ```text
+/// 500  news 7 -90  # company 7 misses its earnings by 90%
+/// 2000 news * -50  # market-wide panic
+/// 2000 halt * 10
+/// ```
+#[derive(Debug, Default)]
+pub struct Scenario {
+    events: Vec<ScheduledEvent>,
+    next_event: usize,
+}
+
+fn parse_target(word: &str) -> Option<Target> {
+    if word == "*" {
+        return Some(Target::All);
+    }
+    word.parse().ok().map(Target::One)
+}
+
+fn parse_event(words: &[&str]) -> Option<ScenarioEvent> {
+    let event = match words {
+        ["news", company, profit_percent] => ScenarioEvent::News {
+            company: parse_target(company)?,
+            profit_percent: profit_percent.parse().ok()?,
+        },
+        ["hype", company_id, strength] => ScenarioEvent::Hype {
+            company_id: company_id.parse().ok()?,
+            strength: strength.parse().ok()?,
+        },
+        ["cash", agent, amount] => ScenarioEvent::Cash {
+            agent: parse_target(agent)?,
+            amount: amount.parse().ok()?,
+        },
+        ["halt", company, duration] => ScenarioEvent::Halt {
+            company: parse_target(company)?,
+            duration: duration.parse().ok().filter(|&duration| duration != 0)?,
+        },
+        ["list"] => ScenarioEvent::List,
+        ["rate", interest_rate] => ScenarioEvent::InterestRate(interest_rate.parse().ok()?),
+        _ => return None,
+    };
+    Some(event)
+}
+
+impl Scenario {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn load(file_path: &str) -> Result<Self, ScenarioError> {
+        let Ok(data) = fs::read_to_string(file_path) else {
+            return Err(ScenarioError::FileNotFound);
+        };
+        Self::parse(&data)
+    }
+    pub fn parse(data: &str) -> Result<Self, ScenarioError> {
+        let mut events = Vec::new();
+        for (line_number, line) in data.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let Some((tick, event)) = words.split_first() else {
+                continue;
+            };
+            let (Ok(tick), Some(event)) = (tick.parse(), parse_event(event)) else {
+                return Err(ScenarioError::InvalidLine(line_number + 1));
+            };
+            events.push(ScheduledEvent { tick, event });
+        }
+        // stable, so events of the same tick keep the order of the file
+        events.sort_by_key(|event| event.tick);
+        Ok(Self {
+            events,
+            next_event: 0,
+        })
+    }
+    pub fn events(&self) -> &[ScheduledEvent] {
+        &self.events
+    }
+    pub fn is_finished(&self) -> bool {
+        self.next_event == self.events.len()
+    }
+    /// Applies every event scheduled up to (and including) the tick which hasn't been applied yet
+    pub fn apply(
+        &mut self,
+        tick: u64,
+        companies: &mut Companies,
+        agents: &mut Agents,
+        market: &mut Market,
+        economy: &mut Economy,
+        rng: &mut impl Rng,
+    ) -> Result<(), SimulationError> {
+        while let Some(scheduled_event) = self.events.get(self.next_event) {
+            if scheduled_event.tick > tick {
+                break;
+            }
+            self.next_event += 1;
+            log!(info "Scenario: tick: {}, event: {:?}", tick, scheduled_event.event);
+            match scheduled_event.event {
+                ScenarioEvent::News {
+                    company,
+                    profit_percent,
+                } => {
+                    let company_ids = match company {
+                        Target::All => companies.iter().collect(),
+                        Target::One(company_id) => vec![company_id],
+                    };
+                    let mut hypeable_companies = Vec::new();
+                    for company_id in company_ids {
+                        if !companies.is_listed(company_id) {
+                            continue;
+                        }
+                        if let Some(news) =
+                            companies.release_news(company_id, profit_percent / 100.0)
+                        {
+                            hypeable_companies.push((company_id, news));
+                        }
+                    }
+                    companies.send_hype(&mut hypeable_companies);
+                }
+                ScenarioEvent::Hype {
+                    company_id,
+                    strength,
+                } => {
+                    if companies.is_listed(company_id) {
+                        companies.inject_hype(company_id, strength);
+                    }
+                }
+                ScenarioEvent::Cash { agent, amount } => {
+                    let agent_ids = match agent {
+                        Target::All => agents.iter().collect(),
+                        Target::One(agent_id) => vec![agent_id],
+                    };
+                    for agent_id in agent_ids {
+                        // a missing agent shouldn't cancel the rest of the events of the tick
+                        if let Err(e) = agents.balances.add(agent_id, Money::from(amount)) {
+                            log!(warn "Failed to give cash to agent {}\n{:?}", agent_id, e);
+                        }
+                    }
+                }
+                ScenarioEvent::Halt { company, duration } => match company {
+                    Target::All => market.halt(None, duration),
+                    Target::One(company_id) => market.halt(Some(company_id), duration),
+                },
+                ScenarioEvent::List => {
+                    let company_id = companies.rand_list_new_company(tick, IPO_BOOK_BUILDING, rng);
+                    let max_preference = companies.new_listing_preference(company_id);
+                    agents.rand_give_preferences_for_company(rng, company_id, max_preference);
+                }
+                ScenarioEvent::InterestRate(interest_rate) => {
+                    economy.set_interest_rate(interest_rate)
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{entities::agents::Agents, trade_house::TradeAction};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Number of the most recent `Timeline` entries an agent copies from
+pub const RECENT_ENTRIES: usize = 20;
+/// Chance of an agent copying an entry from each of its neighbors every tick
+pub const COPY_PROBABILITY: f64 = 0.05;
+/// Portion of the best connected agents which are influencers
+pub const INFLUENCER_FRACTION: f64 = 0.01;
+/// How much more likely the entries of an influencer are copied
+pub const INFLUENCER_WEIGHT: f64 = 5.0;
+
+/// Shape of the social graph
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum GraphKind {
+    /// Every pair of agents is as likely to be connected
+    Random { average_degree: u64 },
+    /// A ring where every agent knows its `neighbors` closest agents,
+    /// some connections are rewired to a random agent
+    SmallWorld {
+        neighbors: u64,
+        rewiring_probability: f64,
+    },
+    /// New agents connect to `edges_per_agent` agents, preferring the well connected ones
+    ScaleFree { edges_per_agent: u64 },
+}
+
+/// Who listens to whom, the connections go both ways
+#[derive(Debug, Clone, Default)]
+pub struct SocialNetwork {
+    pub neighbors: Vec<Vec<u64>>,
+    pub influencers: Vec<bool>,
+}
+
+impl SocialNetwork {
+    pub fn new(num_of_agents: u64) -> Self {
+        Self {
+            neighbors: vec![Vec::new(); num_of_agents as usize],
+            influencers: vec![false; num_of_agents as usize],
+        }
+    }
+    pub fn rand_new(rng: &mut impl Rng, kind: GraphKind, num_of_agents: u64) -> Self {
+        let mut network = Self::new(num_of_agents);
+        if num_of_agents < 2 {
+            return network;
+        }
+        match kind {
+            GraphKind::Random { average_degree } => {
+                for _ in 0..(num_of_agents * average_degree / 2) {
+                    let a = rng.gen_range(0..num_of_agents);
+                    let b = rng.gen_range(0..num_of_agents);
+                    network.connect(a, b);
+                }
+            }
+            GraphKind::SmallWorld {
+                neighbors,
+                rewiring_probability,
+            } => {
+                for a in 0..num_of_agents {
+                    for offset in 1..=(neighbors / 2) {
+                        let mut b = (a + offset) % num_of_agents;
+                        if rng.gen_bool(rewiring_probability) {
+                            b = rng.gen_range(0..num_of_agents);
+                        }
+                        network.connect(a, b);
+                    }
+                }
+            }
+            GraphKind::ScaleFree { edges_per_agent } => {
+                let seed_size = (edges_per_agent + 1).min(num_of_agents);
+                // every edge adds both ends, so picking from it favors the well connected agents
+                let mut edge_ends = Vec::new();
+                for a in 0..seed_size {
+                    for b in (a + 1)..seed_size {
+                        network.connect(a, b);
+                        edge_ends.extend([a, b]);
+                    }
+                }
+                for a in seed_size..num_of_agents {
+                    for _ in 0..edges_per_agent {
+                        let b = edge_ends[rng.gen_range(0..edge_ends.len())];
+                        if network.connect(a, b) {
+                            edge_ends.extend([a, b]);
+                        }
+                    }
+                }
+            }
+        }
+        network.update_influencers();
+        network
+    }
+    /// Connects agents which just joined the simulation to the existing agents
+    pub fn rand_add_agents(&mut self, rng: &mut impl Rng, kind: GraphKind, agent_ids: &[u64]) {
+        let edges_per_agent = match kind {
+            GraphKind::Random { average_degree } => average_degree / 2,
+            GraphKind::SmallWorld { neighbors, .. } => neighbors / 2,
+            GraphKind::ScaleFree { edges_per_agent } => edges_per_agent,
+        };
+        for &agent_id in agent_ids {
+            let num_of_agents = self.neighbors.len() as u64;
+            if agent_id >= num_of_agents {
+                self.neighbors.resize(agent_id as usize + 1, Vec::new());
+            }
+            if num_of_agents == 0 {
+                continue;
+            }
+            for _ in 0..edges_per_agent {
+                let mut other_id = rng.gen_range(0..num_of_agents);
+                // a random neighbor of a random agent is picked in proportion to its degree
+                if let (GraphKind::ScaleFree { .. }, Some(neighbors)) =
+                    (kind, self.neighbors.get(other_id as usize))
+                {
+                    if !neighbors.is_empty() {
+                        other_id = neighbors[rng.gen_range(0..neighbors.len())];
+                    }
+                }
+                self.connect(agent_id, other_id);
+            }
+        }
+        self.update_influencers();
+    }
+    /// Returns false if the agents were already connected, or are the same agent
+    pub fn connect(&mut self, a: u64, b: u64) -> bool {
+        if a == b || self.neighbors[a as usize].contains(&b) {
+            return false;
+        }
+        self.neighbors[a as usize].push(b);
+        self.neighbors[b as usize].push(a);
+        true
+    }
+    pub fn degree(&self, agent_id: u64) -> usize {
+        self.neighbors
+            .get(agent_id as usize)
+            .map_or(0, |neighbors| neighbors.len())
+    }
+    /// Makes the `INFLUENCER_FRACTION` best connected agents influencers
+    pub fn update_influencers(&mut self) {
+        let mut agent_ids = (0..self.neighbors.len() as u64).collect::<Vec<_>>();
+        agent_ids.sort_by_key(|agent_id| std::cmp::Reverse(self.degree(*agent_id)));
+        let num_of_influencers = (self.neighbors.len() as f64 * INFLUENCER_FRACTION).ceil();
+        self.influencers = vec![false; self.neighbors.len()];
+        for agent_id in agent_ids.into_iter().take(num_of_influencers as usize) {
+            self.influencers[agent_id as usize] = true;
+        }
+    }
+    pub fn is_influencer(&self, agent_id: u64) -> bool {
+        self.influencers
+            .get(agent_id as usize)
+            .copied()
+            .unwrap_or(false)
+    }
+    /// Agents copy some of the recent preferences of their neighbors
+    ///
+    /// Everything is copied from the timelines as they were at the start of the tick,
+    /// so a rumor travels one step per tick
+    pub fn rand_spread(&self, rng: &mut impl Rng, agents: &mut Agents) {
+        let mut copies: Vec<Vec<(u64, TradeAction)>> = vec![Vec::new(); self.neighbors.len()];
+        for (agent_id, neighbors) in self.neighbors.iter().enumerate() {
+            for &neighbor_id in neighbors {
+                let probability = if self.is_influencer(neighbor_id) {
+                    (COPY_PROBABILITY * INFLUENCER_WEIGHT).min(1.0)
+                } else {
+                    COPY_PROBABILITY
+                };
+                if !rng.gen_bool(probability) {
+                    continue;
+                }
+                let Some(timeline) = agents.preferences.0.get(neighbor_id as usize) else {
+                    continue;
+                };
+                if let Ok(entry) = timeline.recency_bias(RECENT_ENTRIES, rng) {
+                    copies[agent_id].push(entry);
+                }
+            }
+        }
+        for (agent_id, copied) in copies.iter().enumerate() {
+            if copied.is_empty() {
+                continue;
+            }
+            if let Some(timeline) = agents.preferences.0.get_mut(agent_id) {
+                timeline.add(copied);
+            }
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{
+    economy::{Economy, NEWS_CYCLES_PER_YEAR},
+    entities::companies::Companies,
+    trade_house::TradeAction,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Yearly return asked on top of the risk-free rate for holding a stock
+pub const EQUITY_RISK_PREMIUM: f64 = 0.05;
+/// Number of news cycles of profit a value investor counts on
+pub const DCF_HORIZON: u64 = 40;
+/// Discount on the intrinsic value a value investor waits for before buying,
+/// and the premium it waits for before selling
+pub const MARGIN_OF_SAFETY: f64 = 0.3;
+/// How much a single reward moves the value a learning agent gives to an action
+pub const LEARNING_RATE: f64 = 0.1;
+/// Chance of a learning agent trying a random action instead of the best one
+pub const EXPLORATION_RATE: f64 = 0.1;
+/// News (in percent) past which a learning agent sees the company as moving
+pub const NEWS_STATE_THRESHOLD: f64 = 10.0;
+/// Bad, neutral or good news, with or without holding the company
+pub const NUM_OF_MARKET_STATES: usize = 6;
+/// Buy, sell or hold
+pub const NUM_OF_LEARNED_ACTIONS: usize = 3;
+
+/// How an agent picks the companies it trades
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum AgentStrategy {
+    /// Follows its preferences, built from the news and the hype
+    #[default]
+    Sentiment,
+    /// Compares the price with the intrinsic value of the company and holds long
+    Value,
+    /// Learns which action pays in which market state from its own realized P&L
+    Learning,
+    /// A fund, trading through large orders worked over several ticks
+    Institutional,
+    /// Creates and redeems the units of the ETFs to close the gap between their price and NAV
+    AuthorizedParticipant,
+    /// Buys a company on the exchange where it is cheapest and sells it where it is dearest
+    Arbitrageur,
+    /// Quotes the foreign currencies against the home currency around their fundamental rate
+    FxDealer,
+}
+
+/// Epsilon-greedy bandit over the discretized market states
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Learner {
+    /// Value of every action in every market state
+    pub values: [[f64; NUM_OF_LEARNED_ACTIONS]; NUM_OF_MARKET_STATES],
+    /// `(state, action)` waiting for its reward
+    pub last_decision: Option<(usize, usize)>,
+    /// Realized P&L at the time of the last decision
+    pub last_realized_pnl: f64,
+}
+
+/// Value of a share from the cash of the company and its discounted future profits
+///
+/// Returns `None` if the company has no shares in circulation
+pub fn intrinsic_value(companies: &Companies, company_id: u64, economy: &Economy) -> Option<f64> {
+    let id = company_id as usize;
+    let shares_outstanding = *companies.shares_outstanding.get(id)?;
+    if shares_outstanding == 0 {
+        return None;
+    }
+    let discount_rate = economy.interest_per_cycle() + EQUITY_RISK_PREMIUM / NEWS_CYCLES_PER_YEAR;
+    let growth_rate = economy.inflation_per_cycle();
+    let expected_profit = companies.expected_profits[id];
+    let discounted_profits = (1..=DCF_HORIZON)
+        .map(|cycle| {
+            expected_profit * ((1.0 + growth_rate) / (1.0 + discount_rate)).powi(cycle as i32)
+        })
+        .sum::<f64>();
+    Some((companies.balances[id].to_f64() + discounted_profits) / shares_outstanding as f64)
+}
+
+/// Discretizes what a learning agent sees of a company
+pub fn market_state(news: f64, number_of_shares_held: u64) -> usize {
+    let news_state = if news < -NEWS_STATE_THRESHOLD {
+        0
+    } else if news <= NEWS_STATE_THRESHOLD {
+        1
+    } else {
+        2
+    };
+    news_state * 2 + (number_of_shares_held != 0) as usize
+}
+
+impl Learner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Rewards the last decision with the P&L realized since
+    pub fn learn(&mut self, realized_pnl: f64) {
+        let reward = realized_pnl - self.last_realized_pnl;
+        self.last_realized_pnl = realized_pnl;
+        let Some((state, action)) = self.last_decision.take() else {
+            return;
+        };
+        let value = &mut self.values[state][action];
+        *value += LEARNING_RATE * (reward - *value);
+    }
+    /// Returns `None` if holding is the best action
+    pub fn rand_decide(
+        &mut self,
+        rng: &mut impl Rng,
+        state: usize,
+        realized_pnl: f64,
+    ) -> Option<TradeAction> {
+        self.learn(realized_pnl);
+        let action = if rng.gen_bool(EXPLORATION_RATE) {
+            rng.gen_range(0..NUM_OF_LEARNED_ACTIONS)
+        } else {
+            // ties go to the first action, so that the decision only depends on the seed
+            let values = &self.values[state];
+            (0..NUM_OF_LEARNED_ACTIONS).fold(0, |best, action| {
+                if values[action] > values[best] {
+                    action
+                } else {
+                    best
+                }
+            })
+        };
+        self.last_decision = Some((state, action));
+        match action {
+            0 => Some(TradeAction::Buy),
+            1 => Some(TradeAction::Sell),
+            _ => None,
+        }
+    }
+}
+
+/// What a value investor does with a company, `None` if the price is within the margin of safety
+pub fn value_investor_action(
+    current_price: f64,
+    intrinsic_value: f64,
+    number_of_shares_held: u64,
+) -> Option<TradeAction> {
+    if current_price < intrinsic_value * (1.0 - MARGIN_OF_SAFETY) {
+        return Some(TradeAction::Buy);
+    }
+    if number_of_shares_held != 0 && current_price > intrinsic_value * (1.0 + MARGIN_OF_SAFETY) {
+        return Some(TradeAction::Sell);
+    }
+    None
+}
+
+/// What a value investor does with a listed company, `None` if it leaves the company alone
+pub fn value_investor_decision(
+    companies: &Companies,
+    company_id: u64,
+    economy: &Economy,
+    number_of_shares_held: u64,
+) -> Option<TradeAction> {
+    if !companies.is_listed(company_id) {
+        return None;
+    }
+    let current_price = companies.get_current_price(company_id)?;
+    let intrinsic_value = intrinsic_value(companies, company_id, economy)?;
+    value_investor_action(current_price, intrinsic_value, number_of_shares_held)
+}
@@ -1,4 +1,4 @@
-use crate::{transaction::TodoTransaction, OFFER_LIFETIME};
+use crate::{instrument::InstrumentId, money::Money, transaction::TodoTransaction, OFFER_LIFETIME};
 use rand::random;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -6,16 +6,14 @@ use std::collections::HashMap;
 /// Basically stores all the requested trades that weren't immediately resolved
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct TradeHouse {
-    trade_offers: HashMap<u64, Offers<Trade>>,
-    option_offers: HashMap<u64, Offers<StockOption>>,
+    trade_offers: HashMap<InstrumentId, Offers<Trade>>,
 }
 
 pub struct TickData {
-    pub failed_trade_offer: HashMap<u64, Vec<FailedOffer<Trade>>>,
-    pub failed_option_offer: HashMap<u64, Vec<FailedOffer<StockOption>>>,
+    pub failed_trade_offer: HashMap<InstrumentId, Vec<FailedOffer<Trade>>>,
 }
 
-/// All the offers of the certain company
+/// All the offers of the certain instrument
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Offers<T>
 where
@@ -23,8 +21,8 @@ where
 {
     pub seller_offers: Vec<Offer<T>>,
     pub buyer_offers: Vec<Offer<T>>,
-    pub lowest_strike_price: f64,
-    pub highest_strike_price: f64,
+    pub lowest_strike_price: Money,
+    pub highest_strike_price: Money,
 }
 
 /// A specific offer
@@ -37,7 +35,7 @@ where
 {
     pub id: u64,
     pub offerer_id: u64,
-    pub strike_price: f64,
+    pub strike_price: Money,
     pub data: T,
     pub lifetime: u64,
 }
@@ -55,22 +53,6 @@ impl Trade {
 #[derive(Debug)]
 pub struct FailedOffer<T: Clone + Default>(pub Offer<T>, pub TradeAction);
 
-/// A specific option offer
-/// MAYBE RECONSIDER THE ATTRIBUTES
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
-pub struct StockOption {
-    pub number_of_shares: u64,
-    pub time_to_expiry: u64,
-}
-impl StockOption {
-    pub fn new(number_of_shares: u64, time_to_expiry: u64) -> Self {
-        Self {
-            number_of_shares,
-            time_to_expiry,
-        }
-    }
-}
-
 #[derive(PartialEq, Clone, Debug, Serialize, Deserialize, Copy)]
 pub enum TradeAction {
     Buy,
@@ -90,31 +72,57 @@ impl TradeHouse {
     pub fn new() -> Self {
         Self {
             trade_offers: HashMap::new(),
-            option_offers: HashMap::new(),
         }
     }
 
-    pub fn get_mut_trade_offers(&mut self, company_id: u64) -> &mut Offers<Trade> {
-        self.trade_offers.entry(company_id).or_default()
+    pub fn get_mut_trade_offers(&mut self, instrument_id: InstrumentId) -> &mut Offers<Trade> {
+        self.trade_offers.entry(instrument_id).or_default()
     }
-    pub fn get_trade_relative_buy_offers(&self, company_id: u64) -> Option<f64> {
-        let trade_offers = self.trade_offers.get(&company_id)?;
-        Some(trade_offers.buyer_offers.len() as f64 / trade_offers.seller_offers.len() as f64)
+    /// Highest price offered by the buyers of the instrument
+    pub fn best_bid(&self, instrument_id: InstrumentId) -> Option<Money> {
+        self.trade_offers
+            .get(&instrument_id)?
+            .buyer_offers
+            .iter()
+            .map(|offer| offer.strike_price)
+            .max()
     }
-
-    pub fn get_mut_option_offers(&mut self, company_id: u64) -> &mut Offers<StockOption> {
-        self.option_offers.entry(company_id).or_default()
+    /// Lowest price asked by the sellers of the instrument
+    pub fn best_ask(&self, instrument_id: InstrumentId) -> Option<Money> {
+        self.trade_offers
+            .get(&instrument_id)?
+            .seller_offers
+            .iter()
+            .map(|offer| offer.strike_price)
+            .min()
+    }
+    /// Cash every agent has put aside in its buy offers
+    pub fn escrowed_cash(&self) -> HashMap<u64, Money> {
+        let mut escrowed_cash = HashMap::new();
+        for offer in self
+            .trade_offers
+            .values()
+            .flat_map(|offers| offers.buyer_offers.iter())
+        {
+            *escrowed_cash.entry(offer.offerer_id).or_default() +=
+                offer.strike_price.times(offer.data.number_of_shares);
+        }
+        escrowed_cash
+    }
+    pub fn get_trade_relative_buy_offers(&self, instrument_id: InstrumentId) -> Option<f64> {
+        let trade_offers = self.trade_offers.get(&instrument_id)?;
+        Some(trade_offers.buyer_offers.len() as f64 / trade_offers.seller_offers.len() as f64)
     }
 
     pub fn add_trade_offer(
         &mut self,
         offerer_id: u64,
-        company_id: u64,
-        strike_price: f64,
+        instrument_id: InstrumentId,
+        strike_price: impl Into<Money>,
         trade: Trade,
         offer_ask: TradeAction,
     ) {
-        let company_stock_trade = self.get_mut_trade_offers(company_id);
+        let company_stock_trade = self.get_mut_trade_offers(instrument_id);
         (match offer_ask {
             TradeAction::Buy => &mut company_stock_trade.buyer_offers,
             TradeAction::Sell => &mut company_stock_trade.seller_offers,
@@ -125,54 +133,33 @@ impl TradeHouse {
     pub fn add_trade_offer_from_todo_transaction(&mut self, todo_transaction: &TodoTransaction) {
         self.add_trade_offer(
             todo_transaction.agent_id,
-            todo_transaction.company_id,
+            todo_transaction.instrument_id,
             todo_transaction.strike_price,
             todo_transaction.trade.clone(),
             todo_transaction.action,
         );
     }
 
-    pub fn remove_trade_offer(&mut self, company_id: u64, offer: Offer<Trade>) {
-        self.get_mut_trade_offers(company_id)
-            .remove_offer(offer.id as usize);
-    }
-
-    pub fn add_option_offer(
-        &mut self,
-        offerer_id: u64,
-        company_id: u64,
-        strike_price: f64,
-        option: StockOption,
-        offer_ask: TradeAction,
-    ) {
-        let company_stock_trade = self.get_mut_option_offers(company_id);
-        (match offer_ask {
-            TradeAction::Buy => &mut company_stock_trade.buyer_offers,
-            TradeAction::Sell => &mut company_stock_trade.seller_offers,
-        })
-        .push(Offer::new(offerer_id, strike_price, option));
-    }
-
-    pub fn remove_option_offer(&mut self, company_id: u64, offer: Offer<StockOption>) {
-        self.get_mut_option_offers(company_id)
+    pub fn remove_trade_offer(&mut self, instrument_id: InstrumentId, offer: Offer<Trade>) {
+        self.get_mut_trade_offers(instrument_id)
             .remove_offer(offer.id as usize);
     }
 
     pub fn get_appropriate_trade_offer(
         &mut self,
-        company_id: u64,
-        strike_price: f64,
-        acceptable_strike_price_deviation: f64,
+        instrument_id: InstrumentId,
+        strike_price: Money,
+        acceptable_strike_price_deviation: Money,
         offer_ask: TradeAction,
     ) -> Option<Vec<usize>> {
         match offer_ask {
             TradeAction::Buy => self.get_appropriate_buyer_trade_offer(
-                company_id,
+                instrument_id,
                 strike_price,
                 acceptable_strike_price_deviation,
             ),
             TradeAction::Sell => self.get_appropriate_seller_trade_offer(
-                company_id,
+                instrument_id,
                 strike_price,
                 acceptable_strike_price_deviation,
             ),
@@ -182,13 +169,13 @@ impl TradeHouse {
     /// Returns the indices of trade_house.trade_offers which matches the strike_price
     pub fn get_appropriate_buyer_trade_offer(
         &self,
-        company_id: u64,
-        strike_price: f64,
-        acceptable_strike_price_deviation: f64,
+        instrument_id: InstrumentId,
+        strike_price: Money,
+        acceptable_strike_price_deviation: Money,
     ) -> Option<Vec<usize>> {
         Some(
             self.trade_offers
-                .get(&company_id)?
+                .get(&instrument_id)?
                 .buyer_offers
                 .iter()
                 .enumerate()
@@ -205,13 +192,13 @@ impl TradeHouse {
     /// Returns the indices of trade_house.trade_offers which matches the strike_price
     pub fn get_appropriate_seller_trade_offer(
         &self,
-        company_id: u64,
-        strike_price: f64,
-        acceptable_strike_price_deviation: f64,
+        instrument_id: InstrumentId,
+        strike_price: Money,
+        acceptable_strike_price_deviation: Money,
     ) -> Option<Vec<usize>> {
         Some(
             self.trade_offers
-                .get(&company_id)?
+                .get(&instrument_id)?
                 .seller_offers
                 .iter()
                 .enumerate()
@@ -225,93 +212,58 @@ impl TradeHouse {
         )
     }
 
-    pub fn get_appropriate_option_offer(
-        &self,
-        company_id: u64,
-        strike_price: f64,
-        acceptable_strike_price_deviation: f64,
-        offer_ask: TradeAction,
-    ) -> Option<Vec<usize>> {
-        match offer_ask {
-            TradeAction::Buy => self.get_appropriate_buyer_option_offer(
-                company_id,
-                strike_price,
-                acceptable_strike_price_deviation,
-            ),
-            TradeAction::Sell => self.get_appropriate_seller_option_offer(
-                company_id,
-                strike_price,
-                acceptable_strike_price_deviation,
-            ),
+    /// Removes all the offers for the stock of the company,
+    /// returning them as failed offers so they can be refunded
+    pub fn cancel_company_offers(&mut self, company_id: u64) -> TickData {
+        let instrument_ids = self
+            .trade_offers
+            .keys()
+            .filter(|instrument_id| instrument_id.equity() == Some(company_id))
+            .copied()
+            .collect::<Vec<_>>();
+        let mut trade_offers = HashMap::new();
+        for instrument_id in instrument_ids {
+            if let Some(offers) = self.trade_offers.remove(&instrument_id) {
+                trade_offers.insert(instrument_id, offers.into_failed_offers());
+            }
+        }
+        TickData {
+            failed_trade_offer: trade_offers,
         }
     }
 
-    /// Returns the indices of trade_house.option_offers which matches the strike_price
-    pub fn get_appropriate_buyer_option_offer(
-        &self,
-        company_id: u64,
-        strike_price: f64,
-        acceptable_strike_price_deviation: f64,
-    ) -> Option<Vec<usize>> {
-        Some(
-            self.option_offers
-                .get(&company_id)?
-                .buyer_offers
-                .iter()
-                .enumerate()
-                .filter_map(|(i, offer)| {
-                    if offer.strike_price >= strike_price - acceptable_strike_price_deviation {
-                        return Some(i);
-                    }
-                    None
-                })
-                .collect::<Vec<usize>>(),
-        )
+    /// Removes the offers of the agent for the instrument, returning them as failed
+    pub fn cancel_agent_offers(&mut self, instrument_id: InstrumentId, agent_id: u64) -> TickData {
+        let mut trade_offers = HashMap::new();
+        if let Some(offers) = self.trade_offers.get_mut(&instrument_id) {
+            trade_offers.insert(instrument_id, offers.remove_offers_of(agent_id));
+        }
+        TickData {
+            failed_trade_offer: trade_offers,
+        }
     }
 
-    /// Returns the indices of trade_house.option_offers which matches the strike_price
-    pub fn get_appropriate_seller_option_offer(
-        &self,
-        company_id: u64,
-        strike_price: f64,
-        acceptable_strike_price_deviation: f64,
-    ) -> Option<Vec<usize>> {
-        Some(
-            self.option_offers
-                .get(&company_id)?
-                .seller_offers
-                .iter()
-                .enumerate()
-                .filter_map(|(i, offer)| {
-                    if offer.strike_price <= strike_price + acceptable_strike_price_deviation {
-                        return Some(i);
-                    }
-                    None
-                })
-                .collect::<Vec<usize>>(),
-        )
+    /// Removes the book of the instrument, returning its offers as failed
+    pub fn cancel_offers(&mut self, instrument_id: InstrumentId) -> TickData {
+        let mut trade_offers = HashMap::new();
+        if let Some(offers) = self.trade_offers.remove(&instrument_id) {
+            trade_offers.insert(instrument_id, offers.into_failed_offers());
+        }
+        TickData {
+            failed_trade_offer: trade_offers,
+        }
     }
 
-    pub fn tick(
-        &mut self,
-    ) -> TickData {
+    pub fn tick(&mut self) -> TickData {
         let mut trade_offers = HashMap::new();
-        let mut option_offers = HashMap::new();
-        for (company_id, offers) in self.trade_offers.iter_mut() {
+        for (instrument_id, offers) in self.trade_offers.iter_mut() {
             let expired_trades = offers.tick();
             if !expired_trades.is_empty() {
-                trade_offers.insert(*company_id, expired_trades);
-            }
-        }
-        for (company_id, offers) in self.option_offers.iter_mut() {
-            let expired_options = offers.tick();
-            if !expired_options.is_empty() {
-                option_offers.insert(*company_id, expired_options);
+                trade_offers.insert(*instrument_id, expired_trades);
             }
         }
         TickData {
             failed_trade_offer: trade_offers,
-            failed_option_offer: option_offers
         }
     }
 }
@@ -365,6 +317,34 @@ impl<T: Clone + Default> Offers<T> {
         self.buyer_offers.push(trade);
     }
 
+    pub fn into_failed_offers(self) -> Vec<FailedOffer<T>> {
+        self.seller_offers
+            .into_iter()
+            .map(|offer| FailedOffer(offer, TradeAction::Sell))
+            .chain(
+                self.buyer_offers
+                    .into_iter()
+                    .map(|offer| FailedOffer(offer, TradeAction::Buy)),
+            )
+            .collect()
+    }
+
+    /// Takes the offers of the offerer out of the book, as failed offers
+    pub fn remove_offers_of(&mut self, offerer_id: u64) -> Vec<FailedOffer<T>> {
+        let mut removed_offers = Vec::new();
+        for i in (0..self.seller_offers.len()).rev() {
+            if self.seller_offers[i].offerer_id == offerer_id {
+                removed_offers.push(FailedOffer(self.seller_offers.remove(i), TradeAction::Sell));
+            }
+        }
+        for i in (0..self.buyer_offers.len()).rev() {
+            if self.buyer_offers[i].offerer_id == offerer_id {
+                removed_offers.push(FailedOffer(self.buyer_offers.remove(i), TradeAction::Buy));
+            }
+        }
+        removed_offers
+    }
+
     pub fn tick(&mut self) -> Vec<FailedOffer<T>> {
         let mut expired_offers = Vec::new();
         for i in (0..self.seller_offers.len()).rev() {
@@ -386,11 +366,11 @@ impl<T: Clone + Default> Offers<T> {
 }
 
 impl<T: Clone + Default> Offer<T> {
-    pub fn new(offerer_id: u64, strike_price: f64, data: T) -> Self {
+    pub fn new(offerer_id: u64, strike_price: impl Into<Money>, data: T) -> Self {
         Self {
             id: random(),
             offerer_id,
-            strike_price,
+            strike_price: strike_price.into(),
             data,
             lifetime: OFFER_LIFETIME,
         }
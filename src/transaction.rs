@@ -1,6 +1,8 @@
 use crate::{
+    instrument::InstrumentId,
     log,
     logger::Log,
+    money::Money,
     trade_house::{Trade, TradeAction},
 };
 use serde::{Deserialize, Serialize};
@@ -14,10 +16,10 @@ pub struct Transaction {
     /// The agent which bought the shares
     pub seller_id: u64,
 
-    pub company_id: u64,
+    pub instrument_id: InstrumentId,
     pub number_of_shares: u64,
     /// The price per share at which the exchange was done
-    pub strike_price: f64,
+    pub strike_price: Money,
 }
 
 /// Represents an exchange of captial between agent & company
@@ -30,14 +32,14 @@ pub struct CompanyTransaction {
 
     pub number_of_shares: u64,
     /// The price per share at which the exchange was done
-    pub strike_price: f64,
+    pub strike_price: Money,
 }
 
-#[derive(Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct TodoTransaction {
     pub agent_id: u64,
-    pub company_id: u64,
-    pub strike_price: f64,
+    pub instrument_id: InstrumentId,
+    pub strike_price: Money,
     pub action: TradeAction,
     pub trade: Trade,
 }
@@ -46,15 +48,34 @@ impl Transaction {
     pub fn new(
         buyer_id: u64,
         seller_id: u64,
-        company_id: u64,
+        instrument_id: InstrumentId,
         number_of_shares: u64,
-        strike_price: f64,
+        strike_price: impl Into<Money>,
     ) -> Self {
-        log!(info "Transaction: buyer_id: {}, seller_id: {}, company_id: {}, number_of_shares: {}, strike_price: {}", buyer_id, seller_id, company_id, number_of_shares, strike_price);
+        let strike_price = strike_price.into();
+        log!(info "Transaction: buyer_id: {}, seller_id: {}, instrument_id: {:?}, number_of_shares: {}, strike_price: {}", buyer_id, seller_id, instrument_id, number_of_shares, strike_price);
         Self {
             buyer_id,
             seller_id,
-            company_id,
+            instrument_id,
+            number_of_shares,
+            strike_price,
+        }
+    }
+}
+
+impl CompanyTransaction {
+    pub fn new(
+        buyer_agent_id: u64,
+        seller_company_id: u64,
+        number_of_shares: u64,
+        strike_price: impl Into<Money>,
+    ) -> Self {
+        let strike_price = strike_price.into();
+        log!(info "CompanyTransaction: buyer_agent_id: {}, seller_company_id: {}, number_of_shares: {}, strike_price: {}", buyer_agent_id, seller_company_id, number_of_shares, strike_price);
+        Self {
+            buyer_agent_id,
+            seller_company_id,
             number_of_shares,
             strike_price,
         }
@@ -0,0 +1,67 @@
+use crate::DeserializationError;
+use rand::Rng;
+use rand_distr::{Distribution, LogNormal, Pareto};
+use std::fs;
+
+/// How the money (or the value of the holdings) of new agents is drawn
+#[derive(Debug, Clone, PartialEq)]
+pub enum WealthDistribution {
+    Uniform {
+        min: f64,
+        max: f64,
+    },
+    /// Heavy tailed, most agents have close to `scale` and a few have a fortune
+    Pareto {
+        scale: f64,
+        shape: f64,
+    },
+    /// The logarithm of the wealth is normally distributed
+    LogNormal {
+        mu: f64,
+        sigma: f64,
+    },
+    /// Resamples observed wealth
+    Empirical(Vec<f64>),
+}
+
+impl WealthDistribution {
+    /// Reads every number of a CSV file, anything else (like a header) is skipped
+    pub fn load_empirical(file_path: &str) -> Result<Self, DeserializationError> {
+        let Ok(data) = fs::read_to_string(file_path) else {
+            return Err(DeserializationError::FileNotFound);
+        };
+        let samples = data
+            .lines()
+            .flat_map(|line| line.split(','))
+            .filter_map(|field| field.trim().parse::<f64>().ok())
+            .filter(|sample| sample.is_finite() && *sample >= 0.0)
+            .collect::<Vec<_>>();
+        if samples.is_empty() {
+            return Err(DeserializationError::FailedToReadFile);
+        }
+        Ok(Self::Empirical(samples))
+    }
+    /// Returns 0 if the parameters don't make a distribution
+    pub fn sample(&self, rng: &mut impl Rng) -> f64 {
+        match self {
+            Self::Uniform { min, max } => {
+                if min >= max {
+                    return *min;
+                }
+                rng.gen_range(*min..*max)
+            }
+            Self::Pareto { scale, shape } => Pareto::new(*scale, *shape)
+                .map(|pareto| pareto.sample(rng))
+                .unwrap_or_default(),
+            Self::LogNormal { mu, sigma } => LogNormal::new(*mu, *sigma)
+                .map(|log_normal| log_normal.sample(rng))
+                .unwrap_or_default(),
+            Self::Empirical(samples) => {
+                if samples.is_empty() {
+                    return 0.0;
+                }
+                samples[rng.gen_range(0..samples.len())]
+            }
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use stocks::{
     entities::agents::{Agent, Agents},
+    instrument::InstrumentId,
+    money::Money,
     trade_house::{Trade, TradeAction},
     transaction::{TodoTransaction, Transaction},
 };
@@ -13,8 +15,8 @@ fn asset_exchange_success() {
 
     let agent1_buys = TodoTransaction {
         agent_id: 0,
-        company_id: 0,
-        strike_price: 1.0,
+        instrument_id: InstrumentId::Equity(0),
+        strike_price: Money::from(1.0),
         action: TradeAction::Buy,
         trade: Trade::new(100),
     };
@@ -23,23 +25,23 @@ fn asset_exchange_success() {
         .unwrap();
     let agent2_sells = TodoTransaction {
         agent_id: 1,
-        company_id: 0,
-        strike_price: 1.0,
+        instrument_id: InstrumentId::Equity(0),
+        strike_price: Money::from(1.0),
         action: TradeAction::Sell,
         trade: Trade::new(100),
     };
     agents
         .deduct_assets_from_todotransaction(&agent2_sells)
         .unwrap();
-    let transaction = Transaction::new(0, 1, 0, 100, 1.0);
+    let transaction = Transaction::new(0, 1, InstrumentId::Equity(0), 100, 1.0);
     agents
         .exchange_assets_from_transaction(&transaction)
         .unwrap();
 
     let agents = agents.save().unwrap();
-    assert_eq!(agents[0].balance, 0.0);
+    assert_eq!(agents[0].balance, Money::ZERO);
     assert_eq!(agents[0].holding.0.get(&0), Some(100).as_ref());
-    assert_eq!(agents[1].balance, 100.0);
+    assert_eq!(agents[1].balance, Money::from(100.0));
     assert_eq!(agents[1].holding.0.get(&0), Some(0).as_ref());
 }
 
@@ -54,8 +56,8 @@ fn asset_exchange_failure_1() {
 
     let agent1_buys = TodoTransaction {
         agent_id: 0,
-        company_id: 0,
-        strike_price: 1.0,
+        instrument_id: InstrumentId::Equity(0),
+        strike_price: Money::from(1.0),
         action: TradeAction::Buy,
         trade: Trade::new(100),
     };
@@ -64,23 +66,23 @@ fn asset_exchange_failure_1() {
         .unwrap();
     let agent2_sells = TodoTransaction {
         agent_id: 1,
-        company_id: 0,
-        strike_price: 1.0,
+        instrument_id: InstrumentId::Equity(0),
+        strike_price: Money::from(1.0),
         action: TradeAction::Sell,
         trade: Trade::new(100),
     };
     agents
         .deduct_assets_from_todotransaction(&agent2_sells)
         .unwrap();
-    let transaction = Transaction::new(0, 1, 0, 100, 1.0);
+    let transaction = Transaction::new(0, 1, InstrumentId::Equity(0), 100, 1.0);
     agents
         .exchange_assets_from_transaction(&transaction)
         .unwrap();
 
     let agents = agents.save().unwrap();
-    assert_eq!(agents[0].balance, 0.0);
+    assert_eq!(agents[0].balance, Money::ZERO);
     assert_eq!(agents[0].holding.0.get(&0), Some(100).as_ref());
-    assert_eq!(agents[1].balance, 100.0);
+    assert_eq!(agents[1].balance, Money::from(100.0));
     assert_eq!(agents[1].holding.0.get(&0), Some(0).as_ref());
 }
 
@@ -92,8 +94,8 @@ fn asset_exchange_failure_2() {
 
     let agent1_buys = TodoTransaction {
         agent_id: 0,
-        company_id: 0,
-        strike_price: 1.0,
+        instrument_id: InstrumentId::Equity(0),
+        strike_price: Money::from(1.0),
         action: TradeAction::Buy,
         trade: Trade::new(100),
     };
@@ -102,22 +104,22 @@ fn asset_exchange_failure_2() {
         .unwrap();
     let agent2_sells = TodoTransaction {
         agent_id: 1,
-        company_id: 0,
-        strike_price: 1.0,
+        instrument_id: InstrumentId::Equity(0),
+        strike_price: Money::from(1.0),
         action: TradeAction::Sell,
         trade: Trade::new(100),
     };
     agents
         .deduct_assets_from_todotransaction(&agent2_sells)
         .unwrap();
-    let transaction = Transaction::new(0, 1, 0, 100, 1.0);
+    let transaction = Transaction::new(0, 1, InstrumentId::Equity(0), 100, 1.0);
     agents
         .exchange_assets_from_transaction(&transaction)
         .unwrap();
 
     let agents = agents.save().unwrap();
-    assert_eq!(agents[0].balance, 0.0);
+    assert_eq!(agents[0].balance, Money::ZERO);
     assert_eq!(agents[0].holding.0.get(&0), Some(100).as_ref());
-    assert_eq!(agents[1].balance, 100.0);
+    assert_eq!(agents[1].balance, Money::from(100.0));
     assert_eq!(agents[1].holding.0.get(&0), Some(0).as_ref());
 }
@@ -0,0 +1,71 @@
+use stocks::{
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company, BANKRUPTCY_NEWS_CYCLES, DELISTING_RECOVERY_RATE},
+    },
+    etf::Etfs,
+    instrument::InstrumentId,
+    market::Market,
+    money::Money,
+    trade_house::{Trade, TradeAction},
+    transaction::TodoTransaction,
+};
+
+#[test]
+fn bankruptcy_after_distress_cycles() {
+    let mut companies = Companies::load(&[Company::new(0, 500.0, 100.0, 0.0, (0.0, 0, 0))]);
+    for _ in 0..(BANKRUPTCY_NEWS_CYCLES - 1) {
+        companies.release_news(0, 0.0);
+        assert!(!companies.update_distress(0));
+    }
+    companies.release_news(0, 0.0);
+    assert!(companies.update_distress(0));
+}
+
+#[test]
+fn delisting_refunds_and_recovers() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 100.0, &[], &[(0, (0, TradeAction::Buy))]),
+        Agent::new(1, 0.0, &[(0, 100)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 500.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    market
+        .trade(
+            false,
+            &TodoTransaction {
+                agent_id: 0,
+                instrument_id: InstrumentId::Equity(0),
+                strike_price: Money::from(1.0),
+                action: TradeAction::Buy,
+                trade: Trade::new(50),
+            },
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+    assert_eq!(agents.balances.get(0).unwrap(), Money::from(50.0));
+
+    market
+        .delist_company(0, &mut agents, &mut companies, &mut Etfs::new())
+        .unwrap();
+
+    // the buy offer is refunded, and the only holder gets the recovered balance
+    assert_eq!(agents.balances.get(0).unwrap(), Money::from(100.0));
+    assert_eq!(
+        agents.balances.get(1).unwrap(),
+        Money::from(500.0 * DELISTING_RECOVERY_RATE)
+    );
+    assert_eq!(agents.holdings.get(1, InstrumentId::Equity(0)), 0);
+    assert!(market
+        .house
+        .get_mut_trade_offers(InstrumentId::Equity(0))
+        .buyer_offers
+        .is_empty());
+    assert!(agents.preferences.0[0].data.is_empty());
+    assert!(!companies.is_listed(0));
+    assert!(companies
+        .generate_preferences_from_news(&mut rand::thread_rng())
+        .is_empty());
+}
@@ -0,0 +1,219 @@
+use stocks::{
+    bonds::{Bonds, BOND_FACE_VALUE},
+    economy::Economy,
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    etf::Etfs,
+    instrument::InstrumentId,
+    load,
+    market::Market,
+    money::Money,
+    save,
+    trade_house::{Trade, TradeAction},
+    transaction::TodoTransaction,
+};
+
+fn order(agent_id: u64, strike_price: f64, action: TradeAction, number: u64) -> TodoTransaction {
+    TodoTransaction {
+        agent_id,
+        instrument_id: InstrumentId::Bond(0),
+        strike_price: Money::from(strike_price),
+        action,
+        trade: Trade::new(number),
+    }
+}
+
+fn issued() -> (Agents, Companies, Bonds) {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 100_000.0, &[], &[]),
+        Agent::new(1, 40_000.0, &[], &[]),
+        Agent::new(2, 0.0, &[(0, 100)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 500.0, 1_000.0, 0.0, (0.0, 0, 0))]);
+    let mut bonds = Bonds::new();
+    let bond_id = bonds.issue(
+        &mut agents,
+        &mut companies,
+        &Economy::new(),
+        0,
+        10_000.0,
+        &[0, 1],
+    );
+    assert_eq!(bond_id, Some(0));
+    (agents, companies, bonds)
+}
+
+#[test]
+fn placed_directly_with_the_buyers() {
+    let (agents, companies, bonds) = issued();
+    let bond = &bonds.bonds[0];
+    // every buyer puts 5% of its balance in
+    assert_eq!(agents.holdings.get(0, InstrumentId::Bond(0)), 5);
+    assert_eq!(agents.holdings.get(1, InstrumentId::Bond(0)), 2);
+    assert_eq!(bond.number_outstanding, 7);
+    assert_eq!(companies.balances[0], Money::from(7_500.0));
+    assert_eq!(agents.balances.get(0).unwrap(), Money::from(95_000.0));
+    // the risk-free rate and the credit spread of a healthy company
+    assert!((bond.coupon_rate - 0.06).abs() < 1e-9);
+    assert!((bond.coupon() - 15.0).abs() < 1e-9);
+    assert_eq!(bond.maturity, 400);
+    assert_eq!(bonds.debt(0), 7_000.0);
+}
+
+#[test]
+fn coupons_and_repayment() {
+    let (mut agents, mut companies, mut bonds) = issued();
+    let mut market = Market::new();
+
+    assert!(market
+        .pay_bonds(&mut agents, &mut companies, &mut bonds)
+        .unwrap()
+        .is_empty());
+    assert_eq!(companies.balances[0], Money::from(7_395.0));
+    assert_eq!(agents.balances.get(0).unwrap(), Money::from(95_075.0));
+
+    // the bonds offered for sale are returned and repaid with the rest
+    market
+        .trade(
+            false,
+            &order(1, 1_100.0, TradeAction::Sell, 2),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+    agents.current_tick = 400;
+    assert!(market
+        .pay_bonds(&mut agents, &mut companies, &mut bonds)
+        .unwrap()
+        .is_empty());
+    assert_eq!(companies.balances[0], Money::from(290.0));
+    assert_eq!(
+        agents.balances.get(1).unwrap(),
+        Money::from(38_000.0 + 2.0 * 1_030.0)
+    );
+    assert_eq!(agents.holdings.get(0, InstrumentId::Bond(0)), 0);
+    assert_eq!(agents.holdings.get(1, InstrumentId::Bond(0)), 0);
+    assert!(market
+        .house
+        .get_mut_trade_offers(InstrumentId::Bond(0))
+        .seller_offers
+        .is_empty());
+    assert!(!bonds.bonds[0].is_active());
+    assert_eq!(bonds.debt(0), 0.0);
+}
+
+#[test]
+fn coupons_skip_bonds_the_registry_does_not_know() {
+    let (mut agents, mut companies, mut bonds) = issued();
+    // held from a run whose bonds weren't carried over
+    agents.holdings.insert(2, InstrumentId::Bond(5), 3);
+
+    assert!(bonds.pay_coupons(&mut agents, &mut companies).is_empty());
+    assert_eq!(companies.balances[0], Money::from(7_395.0));
+    assert_eq!(agents.balances.get(2).unwrap(), Money::ZERO);
+    assert_eq!(agents.holdings.get(2, InstrumentId::Bond(5)), 3);
+}
+
+#[test]
+fn bondholders_recover_before_shareholders() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 100_000.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 100)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 500.0, 1_000.0, 0.0, (0.0, 0, 0))]);
+    let mut bonds = Bonds::new();
+    let mut market = Market::new();
+    bonds.issue(
+        &mut agents,
+        &mut companies,
+        &Economy::new(),
+        0,
+        10_000.0,
+        &[0],
+    );
+
+    // the money is spent, the coupon can't be paid
+    companies.balances[0] = Money::from(50.0);
+    assert_eq!(
+        market
+            .pay_bonds(&mut agents, &mut companies, &mut bonds)
+            .unwrap(),
+        vec![0]
+    );
+    market
+        .default_bonds(0, &mut agents, &mut companies, &mut bonds)
+        .unwrap();
+    market
+        .delist_company(0, &mut agents, &mut companies, &mut Etfs::new())
+        .unwrap();
+
+    assert!(bonds.bonds[0].defaulted);
+    assert_eq!(bonds.bonds[0].price, Money::from(5.0));
+    assert_eq!(agents.holdings.get(0, InstrumentId::Bond(0)), 0);
+    assert_eq!(agents.balances.get(0).unwrap(), Money::from(95_025.0));
+    assert_eq!(
+        agents.realized_pnl[0],
+        Money::from(5.0 * (5.0 - BOND_FACE_VALUE))
+    );
+    // the shareholders share what is left
+    assert_eq!(agents.balances.get(1).unwrap(), Money::from(12.5));
+}
+
+#[test]
+fn bonds_trade_on_their_own_book() {
+    let (mut agents, mut companies, mut bonds) = issued();
+    let mut market = Market::new();
+
+    market
+        .trade(
+            false,
+            &order(0, 990.0, TradeAction::Sell, 5),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+    market
+        .trade(
+            false,
+            &order(1, 990.0, TradeAction::Buy, 3),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+    assert_eq!(agents.holdings.get(1, InstrumentId::Bond(0)), 5);
+    assert_eq!(
+        agents.balances.get(0).unwrap(),
+        Money::from(95_000.0 + 3.0 * 990.0)
+    );
+    assert_eq!(market.volume(InstrumentId::Bond(0)), 3);
+
+    market.tick_bonds(&mut bonds);
+    assert_eq!(bonds.bonds[0].price, Money::from(990.0));
+}
+
+#[test]
+fn distress_lowers_the_fair_value() {
+    let (_, mut companies, bonds) = issued();
+    let economy = Economy::new();
+    let bond = &bonds.bonds[0];
+    // the coupon is the yield asked at issuance
+    assert!((bond.fair_value(&companies, &economy, 0) - BOND_FACE_VALUE).abs() < 1e-6);
+    assert!((bond.fair_value(&companies, &economy, 400) - BOND_FACE_VALUE).abs() < 1e-6);
+
+    companies.distress_cycles[0] = 2;
+    assert!(bond.fair_value(&companies, &economy, 0) < 0.8 * BOND_FACE_VALUE);
+}
+
+#[test]
+fn bonds_are_saved_and_loaded() {
+    let (_, _, bonds) = issued();
+    let file_path = std::env::temp_dir().join("stocks_data").join("bonds.bin");
+    let file_path = file_path.to_str().unwrap();
+    save(&bonds, file_path).unwrap();
+    assert_eq!(load::<Bonds>(file_path).unwrap(), bonds);
+}
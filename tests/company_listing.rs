@@ -0,0 +1,134 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use stocks::{
+    entities::{
+        agents::{Agent, Agents},
+        companies::{
+            founders_shares, Companies, Company, FOLLOW_ON_DISCOUNT, FOLLOW_ON_TARGET_RUNWAY,
+            IPO_RAISE_TO_PROFIT_RATIO,
+        },
+    },
+    money::Money,
+};
+
+#[test]
+fn new_listing_grows_companies() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut agents = Agents::load(&[Agent::new(0, 100.0, &[], &[])]);
+
+    let company_id = companies.rand_list_new_company(0, false, &mut rng);
+    assert_eq!(company_id, 1);
+    assert_eq!(companies.num_of_companies, 2);
+    assert_eq!(companies.market_values.len(), 2);
+    assert_eq!(companies.balances.len(), 2);
+    assert_eq!(companies.news.len(), 2);
+    assert_eq!(companies.lots.len(), 2);
+    assert!(companies.is_listed(company_id));
+
+    // the IPO raises at least the targeted amount at the listing price
+    let lots = &companies.lots[company_id as usize];
+    assert!(!lots.is_blank());
+    assert_eq!(
+        companies.get_current_price(company_id),
+        Some(lots.strike_price.to_f64())
+    );
+    let raise = lots
+        .strike_price
+        .times(lots.number_of_lots * lots.lot_size)
+        .to_f64();
+    assert!(raise >= companies.expected_profits[company_id as usize] * IPO_RAISE_TO_PROFIT_RATIO);
+
+    agents.give_preferences_for_company(|_| 5, company_id);
+    assert_eq!(agents.preferences.0[0].data.len(), 5);
+    assert_eq!(agents.preferences.0[0].data[0].0, company_id);
+}
+
+#[test]
+fn book_built_listing_is_priced_within_its_band() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+
+    let company_id = companies.rand_list_new_company(0, true, &mut rng);
+    let lots = &companies.lots[company_id as usize];
+    assert!(lots.is_book_building());
+    let (lowest_price, highest_price) = lots.price_band.unwrap();
+    let listing_price = Money::from(companies.get_current_price(company_id).unwrap());
+    assert!(lowest_price < listing_price && listing_price < highest_price);
+    let raise = listing_price
+        .times(lots.number_of_lots * lots.lot_size)
+        .to_f64();
+    assert!(raise >= companies.expected_profits[company_id as usize] * IPO_RAISE_TO_PROFIT_RATIO);
+}
+
+#[test]
+fn follow_on_offering_dilutes() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut companies = Companies::load(&[Company::new(0, 1_000.0, 1_000.0, 0.0, (0.0, 0, 0))]);
+    let mut agents = Agents::load(&[Agent::new(0, 1_000_000.0, &[], &[])]);
+    companies.shares_outstanding[0] = 1_000;
+    companies.market_values[0].current_price = 100.0;
+
+    assert!(companies.rand_consider_follow_on_offering(0, &mut rng));
+    let lots = companies.lots[0].clone();
+    let cash_need = 1_000.0 * FOLLOW_ON_TARGET_RUNWAY - 1_000.0;
+    assert_eq!(
+        lots.strike_price,
+        Money::from(100.0 * (1.0 - FOLLOW_ON_DISCOUNT))
+    );
+    assert!(
+        lots.strike_price
+            .times(lots.number_of_lots * lots.lot_size)
+            .to_f64()
+            >= cash_need
+    );
+    // already offering
+    assert!(!companies.rand_consider_follow_on_offering(0, &mut rng));
+
+    companies.lots[0]
+        .add_bet_and_update_agent(&mut agents, 0, lots.number_of_lots)
+        .unwrap();
+    companies.finalize_lots(0, &mut agents, &mut rng).unwrap();
+    let new_shares = lots.number_of_lots * lots.lot_size;
+    assert_eq!(companies.shares_outstanding[0], 1_000 + new_shares);
+    let price = companies.get_current_price(0).unwrap();
+    assert!(price < 100.0 && price > lots.strike_price.to_f64());
+}
+
+#[test]
+fn companies_start_with_their_founders_shares() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut companies = Companies::rand(3, 0, &mut rng);
+    for company_id in companies.iter() {
+        let id = company_id as usize;
+        assert_eq!(
+            companies.shares_outstanding[id],
+            founders_shares(
+                companies.balances[id],
+                companies.market_values[id].current_price
+            )
+        );
+        assert!(companies.shares_outstanding[id] > 0);
+    }
+
+    let company_id = companies.rand_list_new_company(0, false, &mut rng);
+    let founders = companies.shares_outstanding[company_id as usize];
+    assert!(founders > 0);
+    // the IPO dilutes the founders instead of pricing the company off its raise alone
+    let mut agents = Agents::load(&[Agent::new(0, 1_000_000_000.0, &[], &[])]);
+    let lots = companies.lots[company_id as usize].clone();
+    companies.lots[company_id as usize]
+        .add_bet_and_update_agent(&mut agents, 0, 1)
+        .unwrap();
+    companies
+        .finalize_lots(company_id, &mut agents, &mut rng)
+        .unwrap();
+    assert_eq!(
+        companies.shares_outstanding[company_id as usize],
+        founders + lots.lot_size
+    );
+    assert_eq!(
+        companies.get_current_price(company_id),
+        Some(lots.strike_price.to_f64())
+    );
+}
@@ -0,0 +1,365 @@
+use stocks::{
+    currency::{Currencies, FX_DEALER_CAPITAL, FX_QUOTE_PORTION, FX_SPREAD},
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    exchange::Exchanges,
+    instrument::InstrumentId,
+    load,
+    market::{FeeSchedule, Market},
+    money::Money,
+    save,
+    trade_house::{Trade, TradeAction},
+    transaction::TodoTransaction,
+    SimulationError,
+};
+
+fn order(
+    agent_id: u64,
+    instrument_id: InstrumentId,
+    strike_price: f64,
+    action: TradeAction,
+    number: u64,
+) -> TodoTransaction {
+    TodoTransaction {
+        agent_id,
+        instrument_id,
+        strike_price: Money::from(strike_price),
+        action,
+        trade: Trade::new(number),
+    }
+}
+
+fn currencies() -> Currencies {
+    Currencies::new(&[("USD", 1.0), ("EUR", 2.0)])
+}
+
+#[test]
+fn cash_is_kept_per_currency() {
+    let mut agents = Agents::load(&[Agent::new(0, 100.0, &[], &[])]);
+    assert_eq!(agents.balances.get_in(0, 1).unwrap(), Money::ZERO);
+
+    agents.balances.add_in(0, 1, Money::from(50.0)).unwrap();
+    assert_eq!(agents.balances.get_in(0, 1).unwrap(), Money::from(50.0));
+    assert_eq!(agents.balances.get_in(0, 0).unwrap(), Money::from(100.0));
+    assert!(matches!(
+        agents.balances.add_in(0, 1, Money::from(-60.0)),
+        Err(SimulationError::Unspendable)
+    ));
+    assert!(matches!(
+        agents.balances.get_in(1, 1),
+        Err(SimulationError::AgentNotFound(1))
+    ));
+}
+
+#[test]
+fn foreign_cash_is_saved_with_the_agents() {
+    let mut agents = Agents::load(&[Agent::new(0, 100.0, &[], &[]), Agent::new(1, 0.0, &[], &[])]);
+    agents.balances.add_in(0, 1, Money::from(50.0)).unwrap();
+    agents.balances.add_in(1, 2, Money::from(7.5)).unwrap();
+
+    let agents = Agents::load(&agents.save().unwrap());
+    assert_eq!(agents.balances.get(0).unwrap(), Money::from(100.0));
+    assert_eq!(agents.balances.get_in(0, 1).unwrap(), Money::from(50.0));
+    assert_eq!(agents.balances.get_in(0, 2).unwrap(), Money::ZERO);
+    assert_eq!(agents.balances.get_in(1, 2).unwrap(), Money::from(7.5));
+}
+
+#[test]
+fn currencies_are_saved_and_loaded() {
+    let mut agents = Agents::load(&[Agent::new(0, 0.0, &[], &[])]);
+    let mut currencies = currencies();
+    currencies.found_dealer(&mut agents);
+    currencies.rates[1] = 2.5;
+    let file_path = std::env::temp_dir()
+        .join("stocks_data")
+        .join("currencies.bin");
+    let file_path = file_path.to_str().unwrap();
+    save(&currencies, file_path).unwrap();
+    assert_eq!(load::<Currencies>(file_path).unwrap(), currencies);
+}
+
+#[test]
+fn currencies_trade_against_the_home_currency() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 0.0, &[], &[]),
+        Agent::new(1, 1_000.0, &[], &[]),
+    ]);
+    let mut companies = Companies::load(&[]);
+    let mut currencies = currencies();
+    let mut market = Market::new();
+    agents.balances.add_in(0, 1, Money::from(500.0)).unwrap();
+
+    let eur = InstrumentId::Fx(1);
+    market
+        .trade(
+            false,
+            &order(0, eur, 2.1, TradeAction::Sell, 200),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+    market
+        .trade(
+            false,
+            &order(1, eur, 2.1, TradeAction::Buy, 200),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+
+    assert_eq!(agents.balances.get_in(0, 1).unwrap(), Money::from(300.0));
+    assert_eq!(agents.balances.get(0).unwrap(), Money::from(420.0));
+    assert_eq!(agents.balances.get_in(1, 1).unwrap(), Money::from(200.0));
+    assert_eq!(agents.balances.get(1).unwrap(), Money::from(580.0));
+    // a currency is cash, not a holding
+    assert_eq!(agents.holdings.get(1, eur), 0);
+
+    market.tick_currencies(&mut currencies);
+    assert!((currencies.rate(1) - 2.1).abs() < 1e-9);
+    assert!((currencies.to_home(10.0, 1) - 21.0).abs() < 1e-9);
+}
+
+#[test]
+fn foreign_exchanges_settle_in_their_currency() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 0.0, &[(0, 100)], &[]),
+        Agent::new(1, 1_000.0, &[], &[]),
+        Agent::new(2, 1_000.0, &[], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 500.0, 1_000.0, 0.0, (0.0, 0, 0))]);
+    let mut currencies = currencies();
+    let dealer_id = currencies.found_dealer(&mut agents);
+    let mut exchanges = Exchanges::new(&[
+        (FeeSchedule::default(), 0, 0),
+        (FeeSchedule::default(), 0, 1),
+    ]);
+    exchanges.list(1, 0);
+    agents.balances.add_in(1, 1, Money::from(100.0)).unwrap();
+    exchanges.exchanges[1]
+        .market
+        .trade(
+            false,
+            &order(0, InstrumentId::Equity(0), 5.0, TradeAction::Sell, 20),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+
+    // the ask of 5 euros is worth 10 dollars
+    let buy = |agent_id| {
+        order(
+            agent_id,
+            InstrumentId::Equity(0),
+            10.0,
+            TradeAction::Buy,
+            10,
+        )
+    };
+    assert_eq!(exchanges.route(&buy(1), &currencies), 1);
+    assert_eq!(exchanges.route(&buy(2), &currencies), 1);
+
+    // the agent without euros buys them at the ask of the dealer first
+    currencies.quote(&mut agents, exchanges.primary_mut(), &mut companies);
+    exchanges.submit(
+        [buy(1), buy(2)],
+        &mut agents,
+        &mut companies,
+        &currencies,
+        0,
+    );
+    assert_eq!(agents.balances.get_in(2, 1).unwrap(), Money::from(50.0));
+    assert_eq!(
+        agents.balances.get(2).unwrap(),
+        Money::from(1_000.0 - 50.0 * 2.0 * (1.0 + FX_SPREAD))
+    );
+    assert_eq!(
+        agents.balances.get_in(dealer_id, 1).unwrap(),
+        Money::from(FX_DEALER_CAPITAL / 2.0 * (1.0 - FX_QUOTE_PORTION))
+    );
+    exchanges
+        .rand_do_trade(
+            &mut rand::thread_rng(),
+            &mut agents,
+            &mut companies,
+            &currencies,
+            0,
+        )
+        .unwrap();
+    assert_eq!(agents.holdings.get(1, InstrumentId::Equity(0)), 10);
+    assert_eq!(agents.balances.get_in(1, 1).unwrap(), Money::from(50.0));
+    assert_eq!(agents.balances.get(1).unwrap(), Money::from(1_000.0));
+    assert_eq!(agents.holdings.get(2, InstrumentId::Equity(0)), 10);
+    assert_eq!(agents.balances.get_in(2, 1).unwrap(), Money::ZERO);
+    assert_eq!(agents.balances.get_in(0, 1).unwrap(), Money::from(100.0));
+
+    // the primary exchange prices the company in dollars
+    let mut market_value = companies.market_values[0].clone();
+    exchanges
+        .primary_mut()
+        .tick_individual_company(0, &mut market_value);
+    assert_eq!(market_value.current_price, 10.0);
+}
+
+#[test]
+fn arbitrage_goes_through_the_exchange_rate() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 0.0, &[(0, 100)], &[]),
+        Agent::new(1, 1_000.0, &[], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 500.0, 1_000.0, 0.0, (0.0, 0, 0))]);
+    let currencies = currencies();
+    let mut exchanges = Exchanges::new(&[
+        (FeeSchedule::default(), 0, 0),
+        (FeeSchedule::default(), 0, 1),
+    ]);
+    exchanges.list(1, 0);
+    let arbitrageur_id = exchanges.found_arbitrageur(&mut agents, &currencies);
+    exchanges.exchanges[0]
+        .market
+        .trade(
+            false,
+            &order(1, InstrumentId::Equity(0), 9.0, TradeAction::Buy, 10),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+    exchanges.exchanges[1]
+        .market
+        .trade(
+            false,
+            &order(0, InstrumentId::Equity(0), 5.0, TradeAction::Sell, 10),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+    // 5 euros are 10 dollars, dearer than the bid of 9
+    assert!(exchanges
+        .arbitrage(&agents, &companies, &currencies)
+        .is_empty());
+
+    let cheap = Currencies::new(&[("USD", 1.0), ("EUR", 1.6)]);
+    let orders = exchanges.arbitrage(&agents, &companies, &cheap);
+    assert_eq!(orders.len(), 1);
+    let (exchange_id, buy) = &orders[0];
+    assert_eq!(*exchange_id, 1);
+    assert_eq!(buy.agent_id, arbitrageur_id);
+    // priced in euros, paid from the euros the arbitrageur started with
+    assert_eq!(buy.strike_price, Money::from(5.0));
+    assert_eq!(
+        agents.balances.get_in(arbitrageur_id, 1).unwrap(),
+        Money::from(currencies.from_home(1_000_000.0, 1))
+    );
+    // a tenth of the 500_000 euros
+    assert_eq!(buy.trade.number_of_shares, 10_000);
+}
+
+#[test]
+fn agents_bring_their_cash_home_through_the_dealers() {
+    let mut agents = Agents::load(&[Agent::new(0, 0.0, &[], &[])]);
+    let mut companies = Companies::load(&[]);
+    let mut currencies = currencies();
+    let mut market = Market::new();
+    let dealer_id = currencies.found_dealer(&mut agents);
+    agents.balances.add_in(0, 1, Money::from(10.5)).unwrap();
+
+    // without quotes there is nobody to trade with
+    assert!(matches!(
+        Currencies::convert(
+            &mut agents,
+            &mut market,
+            &mut companies,
+            0,
+            1,
+            TradeAction::Sell,
+            10
+        ),
+        Err(SimulationError::UnDoable)
+    ));
+
+    // the dealer quotes a tenth of its cash and its euros around the rate
+    currencies.quote(&mut agents, &mut market, &mut companies);
+    let eur = InstrumentId::Fx(1);
+    let bid = market.house.best_bid(eur).unwrap();
+    let ask = market.house.best_ask(eur).unwrap();
+    assert_eq!(bid, Money::from(2.0 * (1.0 - FX_SPREAD)));
+    assert_eq!(ask, Money::from(2.0 * (1.0 + FX_SPREAD)));
+    assert_eq!(
+        agents.balances.get_in(dealer_id, 1).unwrap(),
+        Money::from(FX_DEALER_CAPITAL / 2.0 * (1.0 - FX_QUOTE_PORTION))
+    );
+
+    // the dealer keeps its euros, the agent sells the whole ones at the bid
+    currencies.repatriate(&mut agents, &mut market, &mut companies);
+    assert_eq!(agents.balances.get(0).unwrap(), bid.times(10));
+    assert_eq!(agents.balances.get_in(0, 1).unwrap(), Money::from(0.5));
+    assert_eq!(
+        agents.balances.get_in(dealer_id, 1).unwrap(),
+        Money::from(FX_DEALER_CAPITAL / 2.0 * (1.0 - FX_QUOTE_PORTION) + 10.0)
+    );
+    assert_eq!(market.volume(eur), 10);
+
+    // the conversions set the rate
+    market.tick_currencies(&mut currencies);
+    assert_eq!(currencies.rate(1), bid.to_f64());
+
+    // nobody sells what it doesn't have
+    assert!(matches!(
+        Currencies::convert(
+            &mut agents,
+            &mut market,
+            &mut companies,
+            0,
+            1,
+            TradeAction::Buy,
+            100
+        ),
+        Err(SimulationError::Unspendable)
+    ));
+}
+
+#[test]
+fn the_dealers_move_their_quotes_with_the_flow() {
+    let mut agents = Agents::load(&[Agent::new(0, 0.0, &[], &[])]);
+    let mut companies = Companies::load(&[]);
+    let mut currencies = currencies();
+    let mut market = Market::new();
+    currencies.found_dealer(&mut agents);
+    agents
+        .balances
+        .add_in(0, 1, Money::from(400_000.0))
+        .unwrap();
+    let eur = InstrumentId::Fx(1);
+
+    currencies.quote(&mut agents, &mut market, &mut companies);
+    let bid = market.house.best_bid(eur).unwrap();
+    let ask = market.house.best_ask(eur).unwrap();
+    Currencies::convert(
+        &mut agents,
+        &mut market,
+        &mut companies,
+        0,
+        1,
+        TradeAction::Sell,
+        400_000,
+    )
+    .unwrap();
+    assert_eq!(agents.balances.get(0).unwrap(), bid.times(400_000));
+
+    // the dealer long of euros quotes them lower, though the fundamental rate didn't move
+    market.tick_currencies(&mut currencies);
+    currencies.quote(&mut agents, &mut market, &mut companies);
+    assert!(market.house.best_bid(eur).unwrap() < bid);
+    assert!(market.house.best_ask(eur).unwrap() < ask);
+    assert_eq!(currencies.fundamentals[1], 2.0);
+
+    // and the rate follows its quotes
+    market.tick_currencies(&mut currencies);
+    assert!(currencies.rate(1) < 2.0);
+}
@@ -0,0 +1,31 @@
+use stocks::entities::companies::{
+    Companies, Company, ANALYST_GUIDANCE_WEIGHT, EARNINGS_PRICE_REACTION, GUIDANCE_PERSISTENCE,
+};
+
+#[test]
+fn surprise_against_consensus() {
+    let mut companies = Companies::load(&[Company::new(0, 10_000.0, 1_000.0, 0.0, (10.0, 0, 0))]);
+    companies.market_values[0].current_price = 100.0;
+    // without a report, the analysts expect the expected profit
+    assert_eq!(companies.consensus_estimate(0), 1_000.0);
+
+    companies.release_earnings(0, 0.1);
+    let report = companies.earnings_reports[0].unwrap();
+    assert!((report.surprise - 10.0).abs() < 1e-9);
+    assert!((companies.news[0] - 10.0).abs() < 1e-9);
+    assert!(report.revenue > report.profit);
+    assert!(
+        (companies.market_values[0].current_price - 100.0 * (1.0 + 10.0 * EARNINGS_PRICE_REACTION))
+            .abs()
+            < 1e-9
+    );
+
+    // the guidance raises the bar for the next report
+    let guidance = 1_000.0 * (1.0 + 0.1 * GUIDANCE_PERSISTENCE);
+    let consensus = ANALYST_GUIDANCE_WEIGHT * guidance + (1.0 - ANALYST_GUIDANCE_WEIGHT) * 1_000.0;
+    assert_eq!(companies.consensus_estimate(0), consensus);
+    companies.release_earnings(0, 0.0);
+    let report = companies.earnings_reports[0].unwrap();
+    assert!(report.surprise < 0.0);
+    assert!(companies.news[0] < 0.0);
+}
@@ -0,0 +1,44 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use stocks::{
+    economy::{Economy, Regime, NEWS_CYCLES_PER_YEAR},
+    entities::agents::{Agent, Agents},
+    money::Money,
+};
+
+#[test]
+fn rate_hike_pays_cash_and_cuts_spending() {
+    let mut economy = Economy::new();
+    let spending_before = economy.spending_multiplier();
+    economy.set_interest_rate(0.2);
+
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    for _ in 0..100 {
+        economy.rand_tick(&mut rng);
+        assert!((economy.interest_rate - 0.2).abs() < 0.05);
+    }
+    economy.interest_rate = 0.2;
+    assert!(economy.spending_multiplier() < spending_before);
+
+    let mut agents = Agents::load(&[Agent::new(0, 1_000.0, &[], &[])]);
+    agents.accrue_interest(economy.interest_per_cycle());
+    assert_eq!(
+        agents.balances.get(0).unwrap(),
+        Money::from(1_000.0 * (1.0 + 0.2 / NEWS_CYCLES_PER_YEAR))
+    );
+}
+
+#[test]
+fn recessions_come_and_go() {
+    let mut economy = Economy::new();
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut recessions = 0;
+    for _ in 0..1_000 {
+        economy.rand_tick(&mut rng);
+        if economy.regime == Regime::Recession {
+            recessions += 1;
+        }
+    }
+    assert!(recessions > 0 && recessions < 1_000);
+    assert!(Regime::Recession.profit_drift() < Regime::Expansion.profit_drift());
+}
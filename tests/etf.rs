@@ -0,0 +1,279 @@
+use stocks::{
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company, DELISTING_RECOVERY_RATE},
+    },
+    etf::{Etfs, CASH_IN_LIEU_FEE, CREATION_UNIT_SIZE},
+    index::{IndexWeighting, MarketIndex},
+    instrument::InstrumentId,
+    load,
+    market::Market,
+    money::Money,
+    save,
+    trade_house::{Trade, TradeAction},
+    transaction::TodoTransaction,
+    SimulationError,
+};
+
+fn companies() -> Companies {
+    let mut companies = Companies::load(&[
+        Company::new(0, 10_000.0, 1_000.0, 0.0, (0.0, 0, 0)),
+        Company::new(1, 10_000.0, 1_000.0, 0.0, (0.0, 0, 0)),
+    ]);
+    companies.market_values[0].current_price = 10.0;
+    companies.market_values[1].current_price = 40.0;
+    companies.shares_outstanding[0] = 300;
+    companies.shares_outstanding[1] = 100;
+    companies
+}
+
+fn etfs(companies: &Companies) -> Etfs {
+    let mut etfs = Etfs::new();
+    etfs.launch(
+        &MarketIndex::new(IndexWeighting::CapWeighted, companies),
+        companies,
+    );
+    etfs
+}
+
+fn order(agent_id: u64, strike_price: f64, action: TradeAction, units: u64) -> TodoTransaction {
+    TodoTransaction {
+        agent_id,
+        instrument_id: InstrumentId::Etf(0),
+        strike_price: Money::from(strike_price),
+        action,
+        trade: Trade::new(units),
+    }
+}
+
+#[test]
+fn basket_follows_the_index_weights() {
+    let companies = companies();
+    let etfs = etfs(&companies);
+    let etf = &etfs.etfs[0];
+    // 3:4 in value, worth 100 per unit
+    assert_eq!(etf.basket, vec![(0, 4_286), (1, 1_429)]);
+    assert!((etf.nav(&companies) - 100.02).abs() < 1e-9);
+    assert_eq!(etf.premium(&companies), Some(0.0));
+}
+
+#[test]
+fn create_and_redeem() {
+    let companies = companies();
+    let mut etfs = etfs(&companies);
+    let mut agents = Agents::load(&[Agent::new(0, 0.0, &[(0, 5_000), (1, 1_429)], &[])]);
+
+    etfs.create(&mut agents, &companies, 0, 0).unwrap();
+    assert_eq!(agents.holdings_of(0), vec![(0, 714)]);
+    assert_eq!(
+        agents.holdings.get(0, InstrumentId::Etf(0)),
+        CREATION_UNIT_SIZE
+    );
+    assert_eq!(etfs.etfs[0].units_outstanding, CREATION_UNIT_SIZE);
+
+    // nothing is taken without the whole basket
+    assert!(matches!(
+        etfs.create(&mut agents, &companies, 0, 0),
+        Err(SimulationError::Unspendable)
+    ));
+    assert_eq!(agents.holdings_of(0), vec![(0, 714)]);
+
+    etfs.redeem(&mut agents, &companies, 0, 0).unwrap();
+    assert_eq!(agents.holdings_of(0), vec![(0, 5_000), (1, 1_429)]);
+    assert_eq!(agents.holdings.get(0, InstrumentId::Etf(0)), 0);
+    assert_eq!(etfs.etfs[0].units_outstanding, 0);
+    assert!(etfs.redeem(&mut agents, &companies, 0, 0).is_err());
+}
+
+#[test]
+fn missing_shares_are_paid_in_cash() {
+    let companies = companies();
+    let mut etfs = etfs(&companies);
+    let mut agents = Agents::load(&[Agent::new(0, 10_000.0, &[(1, 1_429)], &[])]);
+
+    // 4_286 shares at 10 are over the cash limit
+    assert!(etfs.create(&mut agents, &companies, 0, 0).is_err());
+    agents.holdings.insert(0, InstrumentId::Equity(0), 4_000);
+    etfs.create(&mut agents, &companies, 0, 0).unwrap();
+    assert_eq!(agents.holdings_of(0), vec![]);
+    assert_eq!(
+        agents.holdings.get(0, InstrumentId::Etf(0)),
+        CREATION_UNIT_SIZE
+    );
+    assert_eq!(
+        agents.balances.get(0).unwrap(),
+        Money::from(10_000.0 - 2_860.0 * (1.0 + CASH_IN_LIEU_FEE))
+    );
+    // the fund holds what it was given, the cash in place of the missing shares
+    assert_eq!(etfs.etfs[0].holdings, vec![(0, 4_000), (1, 1_429)]);
+    assert_eq!(
+        etfs.etfs[0].cash,
+        Money::from(2_860.0 * (1.0 + CASH_IN_LIEU_FEE))
+    );
+}
+
+#[test]
+fn redemptions_hand_out_what_the_fund_holds() {
+    let mut companies = companies();
+    let mut etfs = etfs(&companies);
+    let mut agents = Agents::load(&[
+        Agent::new(0, 10_000.0, &[(0, 4_000), (1, 1_429)], &[]),
+        Agent::new(1, 0.0, &[(0, 4_286), (1, 1_429)], &[]),
+    ]);
+    etfs.create(&mut agents, &companies, 0, 0).unwrap();
+    etfs.create(&mut agents, &companies, 0, 1).unwrap();
+    let cash = etfs.etfs[0].cash;
+    // agent 1 bought the units of agent 0
+    agents
+        .holdings
+        .pop(0, InstrumentId::Etf(0), CREATION_UNIT_SIZE)
+        .unwrap();
+    agents
+        .holdings
+        .push(1, InstrumentId::Etf(0), CREATION_UNIT_SIZE);
+
+    // company 1 left the index, the next creations only take company 0
+    companies.shares_outstanding[1] = 0;
+    etfs.rebalance(
+        &MarketIndex::new(IndexWeighting::CapWeighted, &companies),
+        &companies,
+    );
+    assert_eq!(etfs.etfs[0].basket, vec![(0, 10_002)]);
+
+    // the first redemption gets half of everything, no matter who delivered what
+    assert_eq!(
+        etfs.redeem(&mut agents, &companies, 0, 1).unwrap(),
+        vec![(0, 4_143), (1, 1_429)]
+    );
+    assert_eq!(agents.holdings_of(1), vec![(0, 4_143), (1, 1_429)]);
+    assert_eq!(agents.balances.get(1).unwrap(), Money(cash.0 / 2));
+    // the last one gets what's left
+    etfs.redeem(&mut agents, &companies, 0, 1).unwrap();
+    assert_eq!(agents.holdings_of(1), vec![(0, 8_286), (1, 2_858)]);
+    assert_eq!(agents.balances.get(1).unwrap(), cash);
+    assert!(etfs.etfs[0].holdings.is_empty());
+    assert_eq!(etfs.etfs[0].cash, Money::ZERO);
+    assert_eq!(etfs.etfs[0].units_outstanding, 0);
+}
+
+#[test]
+fn funds_get_their_part_of_the_delisting_recovery() {
+    let mut companies = companies();
+    let mut etfs = etfs(&companies);
+    let mut agents = Agents::load(&[Agent::new(0, 0.0, &[(0, 5_000), (1, 1_429)], &[])]);
+    etfs.create(&mut agents, &companies, 0, 0).unwrap();
+    let cash = etfs.etfs[0].cash;
+
+    companies.delist(0, &mut agents, &mut etfs).unwrap();
+
+    // the fund and the agent split the recovery by the 4_286 and 714 shares they held
+    let recovery_per_share = Money(Money::from(10_000.0).scale(DELISTING_RECOVERY_RATE).0 / 5_000);
+    assert_eq!(etfs.etfs[0].cash, cash + recovery_per_share.times(4_286));
+    assert_eq!(
+        agents.balances.get(0).unwrap(),
+        recovery_per_share.times(714)
+    );
+    assert_eq!(etfs.etfs[0].holdings, vec![(1, 1_429)]);
+    assert_eq!(etfs.etfs[0].basket, vec![(1, 1_429)]);
+}
+
+#[test]
+fn units_trade_on_their_own_book() {
+    let mut companies = companies();
+    let mut etfs = etfs(&companies);
+    let mut agents = Agents::load(&[
+        Agent::new(0, 0.0, &[], &[]),
+        Agent::new(1, 1_000.0, &[], &[]),
+    ]);
+    agents.holdings.insert(0, InstrumentId::Etf(0), 10);
+    let mut market = Market::new();
+
+    market
+        .trade(
+            false,
+            &order(0, 100.0, TradeAction::Sell, 10),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+    assert_eq!(agents.holdings.get(0, InstrumentId::Etf(0)), 0);
+    market
+        .trade(
+            false,
+            &order(1, 100.0, TradeAction::Buy, 4),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+
+    assert_eq!(agents.holdings.get(1, InstrumentId::Etf(0)), 4);
+    assert_eq!(agents.balances.get(1).unwrap(), Money::from(600.0));
+    assert_eq!(agents.balances.get(0).unwrap(), Money::from(400.0));
+    let seller_offers = &market
+        .house
+        .get_mut_trade_offers(InstrumentId::Etf(0))
+        .seller_offers;
+    assert_eq!(seller_offers.len(), 1);
+    assert_eq!(seller_offers[0].data.number_of_shares, 6);
+    // the stock of company 0 is untouched
+    assert!(market
+        .house
+        .get_mut_trade_offers(InstrumentId::Equity(0))
+        .seller_offers
+        .is_empty());
+    assert_eq!(market.volume(InstrumentId::Etf(0)), 4);
+    assert_eq!(market.volume(InstrumentId::Equity(0)), 0);
+
+    market.tick_etfs(&mut etfs);
+    assert_eq!(etfs.etfs[0].price, Money::from(100.0));
+}
+
+#[test]
+fn authorized_participants_arbitrage_the_gap() {
+    let mut companies = companies();
+    let mut etfs = etfs(&companies);
+    let mut agents = Agents::load(&[Agent::new(0, 0.0, &[(0, 4_286), (1, 1_429)], &[])]);
+    let agent_id = etfs.found_authorized_participant(&mut agents);
+    agents
+        .holdings
+        .insert(agent_id, InstrumentId::Equity(0), 4_286);
+    agents
+        .holdings
+        .insert(agent_id, InstrumentId::Equity(1), 1_429);
+
+    // the basket got cheaper than the units, create and sell them
+    companies.market_values[0].current_price = 8.0;
+    let (share_orders, unit_orders) = etfs.arbitrage(&mut agents, &companies);
+    assert!(share_orders.is_empty());
+    assert_eq!(unit_orders.len(), 1);
+    assert_eq!(unit_orders[0].action, TradeAction::Sell);
+    assert_eq!(unit_orders[0].trade.number_of_shares, CREATION_UNIT_SIZE);
+    assert_eq!(agents.holdings_of(agent_id), vec![]);
+    assert_eq!(etfs.etfs[0].units_outstanding, CREATION_UNIT_SIZE);
+
+    // the units got cheaper than the basket, redeem them and sell the basket
+    companies.market_values[0].current_price = 12.0;
+    let (share_orders, unit_orders) = etfs.arbitrage(&mut agents, &companies);
+    assert!(unit_orders.is_empty());
+    assert_eq!(share_orders.len(), 2);
+    assert!(share_orders
+        .iter()
+        .all(|order| order.action == TradeAction::Sell));
+    assert_eq!(agents.holdings_of(agent_id), vec![(0, 4_286), (1, 1_429)]);
+    assert_eq!(etfs.etfs[0].units_outstanding, 0);
+}
+
+#[test]
+fn etfs_are_saved_and_loaded() {
+    let companies = companies();
+    let mut etfs = etfs(&companies);
+    etfs.etfs[0].units_outstanding = CREATION_UNIT_SIZE;
+    etfs.etfs[0].cash = Money::from(12.5);
+    etfs.authorized_participants.push(3);
+    let file_path = std::env::temp_dir().join("stocks_data").join("etfs.bin");
+    let file_path = file_path.to_str().unwrap();
+    save(&etfs, file_path).unwrap();
+    assert_eq!(load::<Etfs>(file_path).unwrap(), etfs);
+}
@@ -0,0 +1,381 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use stocks::{
+    currency::Currencies,
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    etf::Etfs,
+    exchange::Exchanges,
+    instrument::InstrumentId,
+    market::FeeSchedule,
+    money::Money,
+    trade_house::{Trade, TradeAction},
+    transaction::TodoTransaction,
+};
+
+fn order(agent_id: u64, strike_price: f64, action: TradeAction, number: u64) -> TodoTransaction {
+    TodoTransaction {
+        agent_id,
+        instrument_id: InstrumentId::Equity(0),
+        strike_price: Money::from(strike_price),
+        action,
+        trade: Trade::new(number),
+    }
+}
+
+fn setup(venues: &[(FeeSchedule, u64, u64)]) -> (Agents, Companies, Exchanges) {
+    let agents = Agents::load(&[
+        Agent::new(0, 0.0, &[(0, 100)], &[]),
+        Agent::new(1, 10_000.0, &[], &[]),
+    ]);
+    let companies = Companies::load(&[Company::new(0, 500.0, 1_000.0, 0.0, (0.0, 0, 0))]);
+    let mut exchanges = Exchanges::new(venues);
+    exchanges.list(1, 0);
+    (agents, companies, exchanges)
+}
+
+fn home_only() -> Currencies {
+    Currencies::new(&[("USD", 1.0)])
+}
+
+fn rest(
+    exchanges: &mut Exchanges,
+    exchange_id: usize,
+    order: &TodoTransaction,
+    agents: &mut Agents,
+    companies: &mut Companies,
+) {
+    exchanges.exchanges[exchange_id]
+        .market
+        .trade(false, order, agents, companies, 0.0)
+        .unwrap();
+}
+
+#[test]
+fn orders_go_to_the_best_price_after_fees() {
+    let fees = FeeSchedule {
+        maker: 0.0,
+        taker: 0.01,
+    };
+    let (mut agents, mut companies, mut exchanges) =
+        setup(&[(fees, 0, 0), (fees, 0, 0), (FeeSchedule::default(), 0, 0)]);
+    let currencies = home_only();
+    exchanges.list(2, 0);
+    rest(
+        &mut exchanges,
+        0,
+        &order(0, 10.0, TradeAction::Sell, 10),
+        &mut agents,
+        &mut companies,
+    );
+    rest(
+        &mut exchanges,
+        1,
+        &order(0, 9.95, TradeAction::Sell, 10),
+        &mut agents,
+        &mut companies,
+    );
+    rest(
+        &mut exchanges,
+        2,
+        &order(0, 10.02, TradeAction::Sell, 10),
+        &mut agents,
+        &mut companies,
+    );
+
+    // the fee makes the cheapest ask the dearest one to take
+    assert_eq!(
+        exchanges.route(&order(1, 10.1, TradeAction::Buy, 5), &currencies),
+        2
+    );
+    assert_eq!(
+        exchanges.route(&order(1, 10.0, TradeAction::Buy, 5), &currencies),
+        1
+    );
+    // nothing to take, the order waits where it costs the least
+    assert_eq!(
+        exchanges.route(&order(1, 9.0, TradeAction::Buy, 5), &currencies),
+        0
+    );
+
+    // only the primary exchange trades a halted company
+    exchanges.primary_mut().halt(Some(0), 1);
+    assert_eq!(
+        exchanges.route(&order(1, 10.1, TradeAction::Buy, 5), &currencies),
+        0
+    );
+}
+
+#[test]
+fn takers_pay_and_makers_get_the_rebate() {
+    let fees = FeeSchedule {
+        maker: -0.01,
+        taker: 0.02,
+    };
+    let (mut agents, mut companies, mut exchanges) = setup(&[(fees, 0, 0)]);
+    rest(
+        &mut exchanges,
+        0,
+        &order(0, 10.0, TradeAction::Sell, 10),
+        &mut agents,
+        &mut companies,
+    );
+    rest(
+        &mut exchanges,
+        0,
+        &order(1, 10.0, TradeAction::Buy, 10),
+        &mut agents,
+        &mut companies,
+    );
+
+    assert_eq!(agents.holdings.get(1, InstrumentId::Equity(0)), 10);
+    assert_eq!(agents.balances.get(0).unwrap(), Money::from(101.0));
+    assert_eq!(agents.balances.get(1).unwrap(), Money::from(9_898.0));
+    assert_eq!(exchanges.fees_collected()[0], Money::from(1.0));
+}
+
+#[test]
+fn rebates_are_paid_out_of_the_fees() {
+    let fees = FeeSchedule {
+        maker: -0.01,
+        taker: 0.02,
+    };
+    let (mut agents, mut companies, mut exchanges) = setup(&[(fees, 0, 0)]);
+    // the taker has nothing left for its fee once it paid for the shares
+    agents.balances.add(1, Money::from(-9_900.0)).unwrap();
+    rest(
+        &mut exchanges,
+        0,
+        &order(0, 10.0, TradeAction::Sell, 10),
+        &mut agents,
+        &mut companies,
+    );
+    rest(
+        &mut exchanges,
+        0,
+        &order(1, 10.0, TradeAction::Buy, 10),
+        &mut agents,
+        &mut companies,
+    );
+
+    assert_eq!(agents.holdings.get(1, InstrumentId::Equity(0)), 10);
+    assert_eq!(agents.balances.get(1).unwrap(), Money::ZERO);
+    assert_eq!(agents.balances.get(0).unwrap(), Money::from(100.0));
+    assert_eq!(exchanges.fees_collected()[0], Money::ZERO);
+}
+
+#[test]
+fn orders_arrive_after_the_latency() {
+    let (mut agents, mut companies, mut exchanges) = setup(&[
+        (FeeSchedule::default(), 0, 0),
+        (
+            FeeSchedule {
+                maker: -0.01,
+                taker: 0.0,
+            },
+            2,
+            0,
+        ),
+    ]);
+    let currencies = home_only();
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    // the rebate draws the order to the far exchange
+    exchanges.submit(
+        [order(0, 10.0, TradeAction::Sell, 10)],
+        &mut agents,
+        &mut companies,
+        &currencies,
+        0,
+    );
+    assert_eq!(exchanges.in_flight(), 1);
+
+    exchanges
+        .rand_do_trade(&mut rng, &mut agents, &mut companies, &currencies, 1)
+        .unwrap();
+    assert_eq!(exchanges.in_flight(), 1);
+    assert_eq!(agents.holdings.get(0, InstrumentId::Equity(0)), 100);
+
+    exchanges
+        .rand_do_trade(&mut rng, &mut agents, &mut companies, &currencies, 2)
+        .unwrap();
+    assert_eq!(exchanges.in_flight(), 0);
+    assert_eq!(agents.holdings.get(0, InstrumentId::Equity(0)), 90);
+    assert_eq!(
+        exchanges.exchanges[1]
+            .market
+            .house
+            .best_ask(InstrumentId::Equity(0)),
+        Some(Money::from(10.0))
+    );
+}
+
+#[test]
+fn trades_are_consolidated_into_the_primary_exchange() {
+    let (mut agents, mut companies, mut exchanges) = setup(&[
+        (FeeSchedule::default(), 0, 0),
+        (FeeSchedule::default(), 0, 0),
+    ]);
+    let currencies = home_only();
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    rest(
+        &mut exchanges,
+        1,
+        &order(0, 10.0, TradeAction::Sell, 10),
+        &mut agents,
+        &mut companies,
+    );
+    exchanges.send(1, order(1, 10.0, TradeAction::Buy, 4), 0);
+    exchanges
+        .rand_do_trade(&mut rng, &mut agents, &mut companies, &currencies, 0)
+        .unwrap();
+
+    assert_eq!(agents.holdings.get(1, InstrumentId::Equity(0)), 4);
+    assert_eq!(exchanges.primary().volume(InstrumentId::Equity(0)), 4);
+    assert_eq!(
+        exchanges.exchanges[1]
+            .market
+            .volume(InstrumentId::Equity(0)),
+        0
+    );
+}
+
+#[test]
+fn halts_stop_the_company_on_every_exchange() {
+    let (mut agents, mut companies, mut exchanges) = setup(&[
+        (FeeSchedule::default(), 0, 0),
+        (FeeSchedule::default(), 1, 0),
+    ]);
+    let currencies = home_only();
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    rest(
+        &mut exchanges,
+        1,
+        &order(0, 10.0, TradeAction::Sell, 10),
+        &mut agents,
+        &mut companies,
+    );
+    // sent to the far exchange before the halt, arriving during it
+    exchanges.send(1, order(1, 10.0, TradeAction::Buy, 4), 0);
+    exchanges.primary_mut().halt(Some(0), 5);
+    exchanges
+        .rand_do_trade(&mut rng, &mut agents, &mut companies, &currencies, 1)
+        .unwrap();
+
+    assert_eq!(exchanges.in_flight(), 0);
+    assert_eq!(agents.holdings.get(1, InstrumentId::Equity(0)), 0);
+    assert_eq!(agents.balances.get(1).unwrap(), Money::from(10_000.0));
+    assert_eq!(
+        exchanges.exchanges[1]
+            .market
+            .house
+            .best_ask(InstrumentId::Equity(0)),
+        Some(Money::from(10.0))
+    );
+}
+
+#[test]
+fn arbitrageurs_buy_low_and_sell_high() {
+    let (mut agents, mut companies, mut exchanges) = setup(&[
+        (FeeSchedule::default(), 0, 0),
+        (FeeSchedule::default(), 0, 0),
+    ]);
+    let currencies = home_only();
+    let arbitrageur_id = exchanges.found_arbitrageur(&mut agents, &currencies);
+    rest(
+        &mut exchanges,
+        1,
+        &order(0, 10.0, TradeAction::Sell, 10),
+        &mut agents,
+        &mut companies,
+    );
+    rest(
+        &mut exchanges,
+        0,
+        &order(1, 12.0, TradeAction::Buy, 10),
+        &mut agents,
+        &mut companies,
+    );
+
+    let orders = exchanges.arbitrage(&agents, &companies, &currencies);
+    assert_eq!(orders.len(), 1);
+    let (exchange_id, buy) = &orders[0];
+    assert_eq!(*exchange_id, 1);
+    assert_eq!(buy.agent_id, arbitrageur_id);
+    assert_eq!(buy.action, TradeAction::Buy);
+    assert_eq!(buy.strike_price, Money::from(10.0));
+    assert_eq!(buy.trade.number_of_shares, 10_000);
+
+    // once held, the shares are sold where they fetch the most
+    agents
+        .holdings
+        .insert(arbitrageur_id, InstrumentId::Equity(0), 10);
+    let orders = exchanges.arbitrage(&agents, &companies, &currencies);
+    assert_eq!(orders.len(), 2);
+    let (exchange_id, sell) = &orders[0];
+    assert_eq!(*exchange_id, 0);
+    assert_eq!(sell.action, TradeAction::Sell);
+    assert_eq!(sell.strike_price, Money::from(12.0));
+    assert_eq!(sell.trade.number_of_shares, 10);
+}
+
+#[test]
+fn fees_can_eat_the_gap() {
+    let fees = FeeSchedule {
+        maker: 0.0,
+        taker: 0.1,
+    };
+    let (mut agents, mut companies, mut exchanges) = setup(&[(fees, 0, 0), (fees, 0, 0)]);
+    let currencies = home_only();
+    exchanges.found_arbitrageur(&mut agents, &currencies);
+    rest(
+        &mut exchanges,
+        1,
+        &order(0, 10.0, TradeAction::Sell, 10),
+        &mut agents,
+        &mut companies,
+    );
+    rest(
+        &mut exchanges,
+        0,
+        &order(1, 12.0, TradeAction::Buy, 10),
+        &mut agents,
+        &mut companies,
+    );
+    assert!(exchanges
+        .arbitrage(&agents, &companies, &currencies)
+        .is_empty());
+}
+
+#[test]
+fn delisting_cancels_everywhere() {
+    let (mut agents, mut companies, mut exchanges) = setup(&[
+        (FeeSchedule::default(), 0, 0),
+        (FeeSchedule::default(), 1, 0),
+    ]);
+    rest(
+        &mut exchanges,
+        1,
+        &order(1, 10.0, TradeAction::Buy, 10),
+        &mut agents,
+        &mut companies,
+    );
+    exchanges.send(1, order(1, 10.0, TradeAction::Buy, 10), 0);
+    assert_eq!(agents.balances.get(1).unwrap(), Money::from(9_900.0));
+
+    exchanges
+        .delist_company(0, &mut agents, &mut companies, &mut Etfs::new())
+        .unwrap();
+    assert!(!companies.is_listed(0));
+    assert_eq!(agents.balances.get(1).unwrap(), Money::from(10_000.0));
+    assert!(!exchanges.exchanges[1].lists(InstrumentId::Equity(0)));
+    assert_eq!(exchanges.in_flight(), 0);
+    assert_eq!(
+        exchanges.exchanges[1]
+            .market
+            .house
+            .best_bid(InstrumentId::Equity(0)),
+        None
+    );
+}
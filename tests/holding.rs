@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use stocks::{
+    entities::{
+        agents::{Agent, Agents, Position, STOP_LOSS},
+        companies::{Companies, Company},
+    },
+    instrument::InstrumentId,
+    market::Market,
+    metrics::HoldingPeriodDistribution,
+    money::Money,
+    trade_house::{Trade, TradeAction},
+    transaction::{TodoTransaction, Transaction},
+    OFFER_LIFETIME,
+};
+
+fn order(agent_id: u64, strike_price: f64, action: TradeAction, number: u64) -> TodoTransaction {
+    TodoTransaction {
+        agent_id,
+        instrument_id: InstrumentId::Equity(0),
+        strike_price: Money::from(strike_price),
+        action,
+        trade: Trade::new(number),
+    }
+}
+
+#[test]
+fn hold_until_horizon_or_stop_loss() {
+    let mut agents = Agents::load(&[Agent::new(0, 0.0, &[], &[]), Agent::new(1, 0.0, &[], &[])]);
+    agents.assign_horizons(|_| 100, &[0, 1]);
+    agents.current_tick = 10;
+    agents.receive_shares(0, InstrumentId::Equity(0), 10, 10.0);
+    agents.current_tick = 20;
+    agents.receive_shares(0, InstrumentId::Equity(0), 30, 20.0);
+    assert_eq!(
+        agents.positions.get(0, InstrumentId::Equity(0)),
+        Some(Position {
+            entry_tick: 10,
+            cost_basis: Money::from(17.5)
+        })
+    );
+
+    assert!(!agents.is_willing_to_sell(0, 0, 17.5));
+    assert!(agents.is_willing_to_sell(0, 0, 17.5 * (1.0 - STOP_LOSS)));
+    // nothing to hold on to
+    assert!(agents.is_willing_to_sell(1, 0, 17.5));
+    agents.current_tick = 110;
+    assert!(agents.is_willing_to_sell(0, 0, 17.5));
+}
+
+#[test]
+fn holding_periods_are_recorded() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 0.0, &[], &[]),
+        Agent::new(1, 0.0, &[], &[]),
+        Agent::new(2, 0.0, &[], &[]),
+    ]);
+    agents.current_tick = 5;
+    agents.receive_shares(1, InstrumentId::Equity(0), 100, 1.0);
+    agents.receive_shares(2, InstrumentId::Equity(0), 100, 1.0);
+
+    // the shares of the seller leave its holdings when the offer is made
+    agents
+        .holdings
+        .pop(1, InstrumentId::Equity(0), 100)
+        .unwrap();
+    agents.current_tick = 25;
+    agents
+        .exchange_assets_from_transaction(&Transaction::new(
+            0,
+            1,
+            InstrumentId::Equity(0),
+            100,
+            2.0,
+        ))
+        .unwrap();
+    assert_eq!(agents.closed_holding_periods, vec![20]);
+    assert!(agents.positions.get(1, InstrumentId::Equity(0)).is_none());
+    assert_eq!(
+        agents
+            .positions
+            .get(0, InstrumentId::Equity(0))
+            .unwrap()
+            .entry_tick,
+        25
+    );
+
+    agents.current_tick = 35;
+    let mut open_holding_periods = agents.open_holding_periods();
+    open_holding_periods.sort();
+    assert_eq!(open_holding_periods, vec![10, 30]);
+    let distribution = HoldingPeriodDistribution::new(&open_holding_periods).unwrap();
+    assert_eq!(distribution.mean, 20.0);
+    assert_eq!(distribution.max, 30);
+    assert!(HoldingPeriodDistribution::new(&[]).is_none());
+}
+
+#[test]
+fn partly_filled_offer_keeps_the_position() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 1_000.0, &[], &[]),
+        Agent::new(1, 0.0, &[], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 0.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    agents.assign_horizons(|_| 100, &[0, 1]);
+    agents.current_tick = 5;
+    agents.receive_shares(1, InstrumentId::Equity(0), 10, 2.0);
+    let position = agents.positions.get(1, InstrumentId::Equity(0));
+
+    // sold at the stop loss, but only 4 of the 10 shares find a buyer
+    agents.current_tick = 10;
+    for todo_transaction in [
+        order(1, 1.0, TradeAction::Sell, 10),
+        order(0, 1.0, TradeAction::Buy, 4),
+    ] {
+        market
+            .trade(false, &todo_transaction, &mut agents, &mut companies, 0.0)
+            .unwrap();
+    }
+    assert_eq!(agents.holdings.get(1, InstrumentId::Equity(0)), 0);
+    assert_eq!(agents.escrow.get(1, InstrumentId::Equity(0)), 6);
+    assert_eq!(agents.positions.get(1, InstrumentId::Equity(0)), position);
+    assert!(agents.closed_holding_periods.is_empty());
+
+    // the rest of the offer expires and the shares go back to the same position
+    let mut expired = HashMap::new();
+    for _ in 0..OFFER_LIFETIME {
+        market.tick_failures(&mut expired);
+    }
+    agents.refund_failed_offers(&expired).unwrap();
+    assert_eq!(agents.holdings.get(1, InstrumentId::Equity(0)), 6);
+    assert_eq!(agents.escrow.get(1, InstrumentId::Equity(0)), 0);
+    assert_eq!(agents.positions.get(1, InstrumentId::Equity(0)), position);
+    // still within the horizon
+    assert!(!agents.is_willing_to_sell(1, 0, 2.0));
+}
@@ -0,0 +1,90 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use stocks::{
+    economy::Economy,
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    etf::Etfs,
+    execution::ExecutionDesk,
+    index::{IndexWeighting, MarketIndex, INDEX_BASE_LEVEL},
+    institutions::{InstitutionKind, Institutions, INDEX_FUND_INVESTORS},
+    trade_house::TradeAction,
+};
+
+fn companies() -> Companies {
+    let mut companies = Companies::load(&[
+        Company::new(0, 10_000.0, 1_000.0, 0.0, (0.0, 0, 0)),
+        Company::new(1, 10_000.0, 1_000.0, 0.0, (0.0, 0, 0)),
+    ]);
+    companies.market_values[0].current_price = 10.0;
+    companies.market_values[1].current_price = 40.0;
+    companies.shares_outstanding[0] = 300;
+    companies.shares_outstanding[1] = 100;
+    companies
+}
+
+#[test]
+fn cap_and_equal_weighting() {
+    let mut companies = companies();
+    let mut cap_weighted = MarketIndex::new(IndexWeighting::CapWeighted, &companies);
+    let mut equal_weighted = MarketIndex::new(IndexWeighting::EqualWeighted, &companies);
+    assert_eq!(cap_weighted.history, vec![INDEX_BASE_LEVEL]);
+    assert_eq!(
+        cap_weighted.weights(&companies),
+        vec![(0, 300.0 / 700.0), (1, 400.0 / 700.0)]
+    );
+    assert_eq!(equal_weighted.weights(&companies), vec![(0, 0.5), (1, 0.5)]);
+
+    companies.market_values[0].current_price = 20.0;
+    assert!(!cap_weighted.tick(&companies));
+    assert!(!equal_weighted.tick(&companies));
+    assert!((cap_weighted.history[1] - INDEX_BASE_LEVEL * 1_000.0 / 700.0).abs() < 1e-9);
+    assert!((equal_weighted.history[1] - INDEX_BASE_LEVEL * 1.5).abs() < 1e-9);
+}
+
+#[test]
+fn constituent_changes_keep_the_level() {
+    let mut companies = companies();
+    let mut agents = Agents::new();
+    let mut index = MarketIndex::new(IndexWeighting::CapWeighted, &companies);
+    companies.delist(0, &mut agents, &mut Etfs::new()).unwrap();
+    assert!(index.tick(&companies));
+    assert!(!index.is_constituent(0));
+    assert!((index.history[1] - INDEX_BASE_LEVEL).abs() < 1e-9);
+}
+
+#[test]
+fn index_fund_holds_the_index() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let companies = companies();
+    let mut agents = Agents::load(
+        &(0..INDEX_FUND_INVESTORS as u64)
+            .map(|agent_id| Agent::new(agent_id, 1_000.0, &[], &[]))
+            .collect::<Vec<_>>(),
+    );
+    let mut institutions = Institutions::new();
+    institutions.rand_found(&mut rng, &mut agents, InstitutionKind::IndexFund);
+    let index = MarketIndex::new(IndexWeighting::CapWeighted, &companies);
+    let mut desk = ExecutionDesk::new();
+    institutions.rand_place_orders(
+        &mut rng,
+        &agents,
+        &companies,
+        &Economy::new(),
+        &index,
+        &mut desk,
+    );
+    assert_eq!(desk.orders.len(), 2);
+    assert!(desk
+        .orders
+        .iter()
+        .all(|order| order.action == TradeAction::Buy));
+    // in proportion to the weights, 3:4 in value
+    let value = |company_id: usize| {
+        desk.orders[company_id].remaining_shares as f64
+            * companies.market_values[company_id].current_price
+    };
+    assert!((value(0) / value(1) - 0.75).abs() < 0.01);
+}
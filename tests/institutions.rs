@@ -0,0 +1,227 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::collections::HashMap;
+use stocks::{
+    economy::Economy,
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    execution::{ExecutionAlgorithm, ExecutionDesk, ParentOrder},
+    index::{IndexWeighting, MarketIndex},
+    institutions::{
+        InstitutionKind, Institutions, FUND_CONTRIBUTION, HEDGE_FUND_INVESTORS,
+        HEDGE_FUND_LEVERAGE, MUTUAL_FUND_INVESTORS,
+    },
+    instrument::InstrumentId,
+    load,
+    market::Market,
+    money::Money,
+    save,
+    strategy::AgentStrategy,
+    trade_house::TradeAction,
+};
+
+#[test]
+fn twap_and_vwap_slices() {
+    let mut market = Market::new();
+    let mut desk = ExecutionDesk::new();
+    desk.add(ParentOrder::new(
+        0,
+        0,
+        TradeAction::Buy,
+        10.0,
+        100,
+        (ExecutionAlgorithm::Twap, 0, 4),
+    ));
+    let mut sent = Vec::new();
+    for tick in 0..4 {
+        sent.extend(desk.slices(tick, &market));
+    }
+    assert_eq!(
+        sent.iter()
+            .map(|order| order.trade.number_of_shares)
+            .collect::<Vec<_>>(),
+        vec![25, 25, 25, 25]
+    );
+    assert!(desk.orders.is_empty());
+
+    // a busy tick gets a bigger slice than the average one
+    for _ in 0..50 {
+        market.add_transaction(InstrumentId::Equity(0), 10.0, 10);
+        market.tick_volumes();
+    }
+    market.add_transaction(InstrumentId::Equity(0), 10.0, 30);
+    let order = ParentOrder::new(
+        0,
+        0,
+        TradeAction::Buy,
+        10.0,
+        100,
+        (ExecutionAlgorithm::Vwap, 0, 4),
+    );
+    assert!(order.next_slice(0, &market) > 25);
+    assert_eq!(order.next_slice(3, &market), 100);
+}
+
+#[test]
+fn funds_pool_and_lever_capital() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut agents = Agents::load(
+        &(0..HEDGE_FUND_INVESTORS as u64)
+            .map(|agent_id| Agent::new(agent_id, 1_000.0, &[], &[]))
+            .collect::<Vec<_>>(),
+    );
+    let mut institutions = Institutions::new();
+    let fund_id = institutions.rand_found(&mut rng, &mut agents, InstitutionKind::HedgeFund);
+    let capital = 1_000.0 * FUND_CONTRIBUTION * HEDGE_FUND_INVESTORS as f64;
+    assert_eq!(
+        agents.strategies[fund_id as usize],
+        AgentStrategy::Institutional
+    );
+    assert_eq!(
+        agents.balances.get(fund_id).unwrap(),
+        Money::from(capital * HEDGE_FUND_LEVERAGE)
+    );
+    assert_eq!(
+        agents.balances.get(0).unwrap(),
+        Money::from(1_000.0 * (1.0 - FUND_CONTRIBUTION))
+    );
+
+    // the hedge fund trades the best news
+    let mut companies = Companies::load(&[
+        Company::new(0, 10_000.0, 1_000.0, -20.0, (0.0, 0, 0)),
+        Company::new(1, 10_000.0, 1_000.0, 40.0, (0.0, 0, 0)),
+    ]);
+    companies.market_values[0].current_price = 10.0;
+    companies.market_values[1].current_price = 10.0;
+    let economy = Economy::new();
+    let index = MarketIndex::new(IndexWeighting::CapWeighted, &companies);
+    let mut desk = ExecutionDesk::new();
+    institutions.rand_place_orders(&mut rng, &agents, &companies, &economy, &index, &mut desk);
+    assert_eq!(desk.orders.len(), 1);
+    assert_eq!(desk.orders[0].company_id, 1);
+    assert_eq!(desk.orders[0].action, TradeAction::Buy);
+
+    let balance = agents.balances.get(fund_id).unwrap();
+    institutions.pay_financing(&mut agents, &companies, &economy, &HashMap::new());
+    assert!(agents.balances.get(fund_id).unwrap() < balance);
+}
+
+#[test]
+fn institutions_are_saved_and_loaded() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut agents = Agents::load(
+        &(0..HEDGE_FUND_INVESTORS as u64)
+            .map(|agent_id| Agent::new(agent_id, 1_000.0, &[], &[]))
+            .collect::<Vec<_>>(),
+    );
+    let mut institutions = Institutions::new();
+    institutions.rand_found(&mut rng, &mut agents, InstitutionKind::HedgeFund);
+    let file_path = std::env::temp_dir()
+        .join("stocks_data")
+        .join("institutions.bin");
+    let file_path = file_path.to_str().unwrap();
+    save(&institutions, file_path).unwrap();
+    assert_eq!(load::<Institutions>(file_path).unwrap(), institutions);
+}
+
+#[test]
+fn fund_units_claim_the_net_asset_value() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut agents = Agents::load(
+        &(0..MUTUAL_FUND_INVESTORS as u64)
+            .map(|agent_id| Agent::new(agent_id, 1_000.0, &[], &[]))
+            .collect::<Vec<_>>(),
+    );
+    let mut institutions = Institutions::new();
+    let fund_id = institutions.rand_found(&mut rng, &mut agents, InstitutionKind::MutualFund);
+    let mut companies = Companies::load(&[Company::new(0, 10_000.0, 1_000.0, 0.0, (0.0, 0, 0))]);
+    companies.market_values[0].current_price = 10.0;
+
+    let claims = institutions.claims(&agents, &companies, &HashMap::new());
+    assert_eq!(claims[&0], Money::from(1_000.0 * FUND_CONTRIBUTION));
+
+    // half of the fund goes into shares which double
+    let capital = agents.balances.get(fund_id).unwrap();
+    agents.balances.add(fund_id, -capital.scale(0.5)).unwrap();
+    agents.holdings.push(
+        fund_id,
+        InstrumentId::Equity(0),
+        (capital.scale(0.5).to_f64() / 10.0) as u64,
+    );
+    companies.market_values[0].current_price = 20.0;
+    let claims = institutions.claims(&agents, &companies, &HashMap::new());
+    assert_eq!(claims[&0], Money::from(1_000.0 * FUND_CONTRIBUTION * 1.5));
+}
+
+#[test]
+fn hedge_fund_repays_what_its_equity_no_longer_backs() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut agents = Agents::load(
+        &(0..HEDGE_FUND_INVESTORS as u64)
+            .map(|agent_id| Agent::new(agent_id, 1_000.0, &[], &[]))
+            .collect::<Vec<_>>(),
+    );
+    let mut institutions = Institutions::new();
+    let fund_id = institutions.rand_found(&mut rng, &mut agents, InstitutionKind::HedgeFund);
+    let capital = Money::from(1_000.0 * FUND_CONTRIBUTION * HEDGE_FUND_INVESTORS as f64);
+    assert_eq!(institutions.0[0].borrowed, capital);
+    let companies = Companies::load(&[]);
+    let mut economy = Economy::new();
+    economy.set_interest_rate(0.0);
+
+    // the fund lost half of its capital, its leverage allows half the loan
+    agents.balances.add(fund_id, -capital.scale(0.5)).unwrap();
+    institutions.pay_financing(&mut agents, &companies, &economy, &HashMap::new());
+    assert_eq!(institutions.0[0].borrowed, capital.scale(0.5));
+    assert_eq!(agents.balances.get(fund_id).unwrap(), capital);
+    let investors = institutions.0[0].investors.len() as u64;
+    assert_eq!(
+        institutions.claims(&agents, &companies, &HashMap::new())[&0],
+        capital.scale(0.5).scale(1.0 / investors as f64)
+    );
+
+    // the interest a fund without cash can't pay is added to the loan
+    economy.set_interest_rate(0.5);
+    let interest = capital.scale(0.5).scale(economy.interest_per_cycle());
+    agents.balances.add(fund_id, -capital).unwrap();
+    institutions.pay_financing(&mut agents, &companies, &economy, &HashMap::new());
+    assert_eq!(institutions.0[0].borrowed, capital.scale(0.5) + interest);
+}
+
+#[test]
+fn hedge_fund_sells_to_repay_the_loan() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut agents = Agents::load(
+        &(0..HEDGE_FUND_INVESTORS as u64)
+            .map(|agent_id| Agent::new(agent_id, 1_000.0, &[], &[]))
+            .collect::<Vec<_>>(),
+    );
+    let mut institutions = Institutions::new();
+    let fund_id = institutions.rand_found(&mut rng, &mut agents, InstitutionKind::HedgeFund);
+    let mut companies = Companies::load(&[
+        Company::new(0, 10_000.0, 1_000.0, 0.0, (0.0, 0, 0)),
+        Company::new(1, 10_000.0, 1_000.0, 40.0, (0.0, 0, 0)),
+    ]);
+    companies.market_values[0].current_price = 10.0;
+    companies.market_values[1].current_price = 10.0;
+
+    // everything went into shares which lost half their value
+    let balance = agents.balances.get(fund_id).unwrap();
+    agents.balances.add(fund_id, -balance).unwrap();
+    agents.holdings.push(
+        fund_id,
+        InstrumentId::Equity(0),
+        (balance.to_f64() / 10.0) as u64,
+    );
+    companies.market_values[0].current_price = 5.0;
+
+    let economy = Economy::new();
+    let index = MarketIndex::new(IndexWeighting::CapWeighted, &companies);
+    let mut desk = ExecutionDesk::new();
+    institutions.rand_place_orders(&mut rng, &agents, &companies, &economy, &index, &mut desk);
+    assert_eq!(desk.orders.len(), 1);
+    assert_eq!(desk.orders[0].company_id, 0);
+    assert_eq!(desk.orders[0].action, TradeAction::Sell);
+}
@@ -0,0 +1,52 @@
+use stocks::{
+    entities::agents::{Agent, Agents},
+    instrument::InstrumentId,
+};
+
+#[test]
+fn encoding_round_trips() {
+    for instrument_id in [
+        InstrumentId::Equity(7),
+        InstrumentId::Bond(7),
+        InstrumentId::Etf(7),
+        InstrumentId::Fx(7),
+    ] {
+        assert_eq!(
+            InstrumentId::decode(instrument_id.encode()),
+            Some(instrument_id)
+        );
+    }
+    // no kind is encoded in the byte past the known ones, nor in the one kept for options
+    assert_eq!(InstrumentId::decode((1 << 56) | 7), None);
+    assert_eq!(InstrumentId::decode((5 << 56) | 7), None);
+    assert_eq!(InstrumentId::decode(u64::MAX), None);
+    // holdings saved under a bare company id are its common stock
+    assert_eq!(InstrumentId::Equity(7).encode(), 7);
+    assert_eq!(InstrumentId::Equity(7).equity(), Some(7));
+    assert_eq!(InstrumentId::Etf(7).equity(), None);
+}
+
+#[test]
+fn instruments_are_held_apart() {
+    let mut agents = Agents::load(&[Agent::new(0, 0.0, &[(3, 10)], &[])]);
+    agents.holdings.insert(0, InstrumentId::Etf(3), 5);
+    agents.holdings.insert(0, InstrumentId::Bond(3), 2);
+
+    assert_eq!(agents.holdings.get(0, InstrumentId::Equity(3)), 10);
+    assert_eq!(agents.holdings.get(0, InstrumentId::Etf(3)), 5);
+    assert_eq!(agents.holdings.get(0, InstrumentId::Bond(3)), 2);
+    // only the shares count as holdings of a company
+    assert_eq!(agents.holdings_of(0), vec![(3, 10)]);
+
+    assert_eq!(agents.holdings.remove_company(3), vec![(0, 10)]);
+    assert_eq!(agents.holdings.get(0, InstrumentId::Equity(3)), 0);
+    assert_eq!(agents.holdings.get(0, InstrumentId::Etf(3)), 5);
+    assert_eq!(agents.holdings.get(0, InstrumentId::Bond(3)), 2);
+}
+
+#[test]
+fn unknown_instruments_are_not_loaded_as_shares() {
+    let agents = Agents::load(&[Agent::new(0, 0.0, &[(3, 10), ((5 << 56) | 3, 4)], &[])]);
+    assert_eq!(agents.holdings.iter().count(), 1);
+    assert_eq!(agents.holdings.get(0, InstrumentId::Equity(3)), 10);
+}
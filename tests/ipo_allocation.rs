@@ -0,0 +1,107 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use stocks::{
+    entities::{
+        agents::{Agent, Agents},
+        companies::{AllocationMethod, Companies, Company, Lots},
+    },
+    instrument::InstrumentId,
+    money::Money,
+};
+
+fn oversubscribed_ipo(allocation_method: AllocationMethod) -> (Agents, Companies) {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 1_000.0, &[], &[]),
+        Agent::new(1, 1_000.0, &[], &[]),
+        Agent::new(2, 1_000.0, &[], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 0.0, 0.0, 0.0, (10.0, 4, 10))]);
+    let lots = &mut companies.lots[0];
+    lots.allocation_method = allocation_method;
+    // 10 lots bet on 4 lots, the ratio isn't an integer
+    lots.add_bet_and_update_agent(&mut agents, 0, 1).unwrap();
+    lots.add_bet_and_update_agent(&mut agents, 1, 3).unwrap();
+    lots.add_bet_and_update_agent(&mut agents, 2, 6).unwrap();
+    (agents, companies)
+}
+
+fn finalize(allocation_method: AllocationMethod) -> [u64; 3] {
+    let (mut agents, mut companies) = oversubscribed_ipo(allocation_method);
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let transactions = companies.finalize_lots(0, &mut agents, &mut rng).unwrap();
+
+    let shares = [0, 1, 2].map(|agent_id| agents.holdings.get(agent_id, InstrumentId::Equity(0)));
+    assert_eq!(shares.iter().sum::<u64>(), 40);
+    assert_eq!(
+        transactions
+            .iter()
+            .map(|transaction| transaction.number_of_shares)
+            .sum::<u64>(),
+        40
+    );
+    // unallocated bets are refunded in full
+    for agent_id in 0..3 {
+        assert_eq!(
+            agents.balances.get(agent_id).unwrap(),
+            Money::from(1_000.0 - shares[agent_id as usize] as f64 * 10.0)
+        );
+    }
+    assert_eq!(companies.balances[0], Money::from(400.0));
+    assert!(companies.lots[0].is_blank());
+    shares
+}
+
+#[test]
+fn pro_rata_allocation() {
+    assert_eq!(finalize(AllocationMethod::ProRata), [10, 10, 20]);
+}
+
+#[test]
+fn largest_first_allocation() {
+    assert_eq!(finalize(AllocationMethod::LargestFirst), [0, 0, 40]);
+}
+
+#[test]
+fn lottery_allocation() {
+    finalize(AllocationMethod::Lottery);
+}
+
+#[test]
+fn small_investor_reservation() {
+    let shares = finalize(AllocationMethod::SmallInvestorReservation {
+        reserved_fraction: 0.75,
+        max_small_bet: 3,
+    });
+    assert_eq!(shares, [10, 20, 10]);
+}
+
+#[test]
+fn book_building_price_discovery() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 1_000.0, &[], &[]),
+        Agent::new(1, 1_000.0, &[], &[]),
+        Agent::new(2, 1_000.0, &[], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 0.0, 0.0, 0.0, (0.0, 0, 0))]);
+    companies.lots[0] = Lots::new_book_building((8.0, 12.0), 4, 10);
+    let lots = &mut companies.lots[0];
+    // out of band bids are brought within the band
+    lots.add_bid_and_update_agent(&mut agents, 0, Money::from(15.0), 2)
+        .unwrap();
+    lots.add_bid_and_update_agent(&mut agents, 1, Money::from(10.0), 2)
+        .unwrap();
+    lots.add_bid_and_update_agent(&mut agents, 2, Money::from(9.0), 3)
+        .unwrap();
+    assert_eq!(agents.balances.get(0).unwrap(), Money::from(760.0));
+    assert_eq!(lots.discover_price(), Some(Money::from(10.0)));
+
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    companies.finalize_lots(0, &mut agents, &mut rng).unwrap();
+    assert_eq!(agents.holdings.get(0, InstrumentId::Equity(0)), 20);
+    assert_eq!(agents.holdings.get(1, InstrumentId::Equity(0)), 20);
+    assert_eq!(agents.holdings.get(2, InstrumentId::Equity(0)), 0);
+    assert_eq!(agents.balances.get(0).unwrap(), Money::from(800.0));
+    assert_eq!(agents.balances.get(1).unwrap(), Money::from(800.0));
+    assert_eq!(agents.balances.get(2).unwrap(), Money::from(1_000.0));
+    assert_eq!(companies.balances[0], Money::from(400.0));
+}
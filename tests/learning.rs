@@ -0,0 +1,70 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use stocks::{
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    instrument::InstrumentId,
+    metrics::average_returns_by_strategy,
+    strategy::{market_state, AgentStrategy, Learner},
+    trade_house::TradeAction,
+};
+
+#[test]
+fn learns_from_realized_pnl() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut learner = Learner::new();
+    let state = market_state(50.0, 0);
+    let mut realized_pnl = 0.0;
+    for _ in 0..1_000 {
+        let action = learner.rand_decide(&mut rng, state, realized_pnl);
+        // buying on good news always pays
+        if action == Some(TradeAction::Buy) {
+            realized_pnl += 10.0;
+        }
+    }
+    let values = learner.values[state];
+    assert!(values[0] > values[1]);
+    assert!(values[0] > values[2]);
+}
+
+#[test]
+fn deterministic_under_seed() {
+    let decisions = |seed: u64| {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut learner = Learner::new();
+        (0..100)
+            .map(|i| learner.rand_decide(&mut rng, market_state(i as f64 - 50.0, i % 2), i as f64))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(decisions(7), decisions(7));
+}
+
+#[test]
+fn returns_by_strategy() {
+    let mut agents = Agents::load(&[Agent::new(0, 0.0, &[], &[]), Agent::new(1, 0.0, &[], &[])]);
+    agents.assign_strategies(
+        |agent_id| {
+            if agent_id == 0 {
+                AgentStrategy::Learning
+            } else {
+                AgentStrategy::Sentiment
+            }
+        },
+        &[0, 1],
+    );
+    assert!(agents.learners[0].is_some());
+    assert!(agents.learners[1].is_none());
+
+    let mut companies = Companies::load(&[Company::new(0, 10_000.0, 1_000.0, 0.0, (0.0, 0, 0))]);
+    companies.market_values[0].current_price = 2.0;
+    agents.receive_shares(0, InstrumentId::Equity(0), 10, 1.0);
+    assert_eq!(
+        average_returns_by_strategy(&agents, &companies),
+        vec![
+            (AgentStrategy::Sentiment, 0.0),
+            (AgentStrategy::Learning, 1.0)
+        ]
+    );
+}
@@ -0,0 +1,316 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::collections::HashMap;
+use stocks::{
+    bonds::{Bonds, TICKS_PER_COUPON},
+    currency::Currencies,
+    economy::Economy,
+    entities::{
+        agents::{Agent, Agents},
+        companies::{tick_size, Companies, Company},
+    },
+    exchange::Exchanges,
+    instrument::InstrumentId,
+    market::{FeeSchedule, Market},
+    money::Money,
+    trade_house::{Trade, TradeAction},
+    transaction::TodoTransaction,
+    TICK_SIZE,
+};
+
+fn order(agent_id: u64, strike_price: f64, action: TradeAction, number: u64) -> TodoTransaction {
+    TodoTransaction {
+        agent_id,
+        instrument_id: InstrumentId::Equity(0),
+        strike_price: Money::from(strike_price),
+        action,
+        trade: Trade::new(number),
+    }
+}
+
+/// Cash of the agents, the cash put aside in the buy offers and the fees kept by the exchange
+fn total_money(agents: &Agents, market: &mut Market) -> Money {
+    let offers = market.house.get_mut_trade_offers(InstrumentId::Equity(0));
+    let escrow = offers
+        .buyer_offers
+        .iter()
+        .map(|offer| offer.strike_price.times(offer.data.number_of_shares))
+        .sum::<Money>();
+    agents.balances.home.iter().copied().sum::<Money>() + escrow + market.fees_collected
+}
+
+/// Cash of the agents and the companies, the cash put aside in the offers of every exchange
+/// and in the bets on the lots, and the fees kept by the exchanges
+fn total_money_everywhere(
+    agents: &Agents,
+    companies: &Companies,
+    exchanges: &Exchanges,
+    currencies: &Currencies,
+) -> Money {
+    agents.balances.home.iter().copied().sum::<Money>()
+        + companies.balances.iter().copied().sum::<Money>()
+        + exchanges
+            .escrowed_cash(currencies)
+            .into_values()
+            .sum::<Money>()
+        + companies.escrowed_cash().into_values().sum::<Money>()
+        + exchanges.fees_collected().into_iter().sum::<Money>()
+}
+
+fn total_shares(agents: &Agents, market: &mut Market) -> u64 {
+    let offers = market.house.get_mut_trade_offers(InstrumentId::Equity(0));
+    let offered = offers
+        .seller_offers
+        .iter()
+        .map(|offer| offer.data.number_of_shares)
+        .sum::<u64>();
+    agents.holdings.iter().map(|(_, _, n)| n).sum::<u64>() + offered
+}
+
+#[test]
+fn amounts_are_exact_minor_units() {
+    assert_eq!(Money::from(0.1) + Money::from(0.2), Money::from(0.3));
+    assert_eq!(Money::from(1.23456), Money(12_346));
+    assert_eq!(Money::from(-1.23456), Money(-12_346));
+    assert_eq!(Money::from(10.5).times(3), Money::from(31.5));
+    assert_eq!(Money::from(10.5).times(u64::MAX), Money(i64::MAX));
+    assert_eq!(Money::from(-10.5).times(1 << 62), Money(i64::MIN));
+    assert_eq!(Money::from(100.0).scale(0.002), Money::from(0.2));
+    assert_eq!(Money::from(10.0) - Money::from(10.0001), -Money::MINOR_UNIT);
+    assert_eq!(Money::from(2.5).to_f64(), 2.5);
+    assert_eq!(Money::from(-2.05).to_string(), "-2.0500");
+    assert_eq!(Money::from_units(9_007_199_254_740_993), Money(i64::MAX));
+    assert_eq!(Money::from_units(123_456_789), Money(1_234_567_890_000));
+    assert_eq!(Money(1_234_567_899_999).whole_units(), 123_456_789);
+    assert_eq!(Money::from(-0.5).whole_units(), 0);
+    assert_eq!(Money::from(2.0001).ceil_to(Money::UNIT).whole_units(), 3);
+    assert_eq!(
+        (0..1_000_000).map(|_| Money::from(0.0001)).sum::<Money>(),
+        Money::from(100.0)
+    );
+}
+
+#[test]
+fn prices_are_snapped_to_the_tick() {
+    assert_eq!(Money::from(10.037).floor_to(TICK_SIZE), Money::from(10.03));
+    assert_eq!(Money::from(10.031).ceil_to(TICK_SIZE), Money::from(10.04));
+    assert_eq!(Money::from(10.03).ceil_to(TICK_SIZE), Money::from(10.03));
+    assert_eq!(Money::from(-0.015).floor_to(TICK_SIZE), Money::from(-0.02));
+    assert!(tick_size(50.0) < tick_size(500.0) && tick_size(500.0) < tick_size(5_000.0));
+
+    let companies = Companies::load(&[Company::new(0, 0.0, 0.0, 0.0, (0.0, 0, 0))]);
+    assert_eq!(companies.tick_size(InstrumentId::Equity(0)), TICK_SIZE);
+    assert_eq!(companies.tick_size(InstrumentId::Fx(1)), Money::MINOR_UNIT);
+    // buyers round down and sellers up, never below a tick
+    let buy = Market::snap_to_tick(&order(0, 10.037, TradeAction::Buy, 1), &companies);
+    assert_eq!(buy.strike_price, Money::from(10.03));
+    let sell = Market::snap_to_tick(&order(0, 10.031, TradeAction::Sell, 1), &companies);
+    assert_eq!(sell.strike_price, Money::from(10.04));
+    let dust = Market::snap_to_tick(&order(0, 0.001, TradeAction::Buy, 1), &companies);
+    assert_eq!(dust.strike_price, Money::from(0.01));
+}
+
+#[test]
+fn buyers_get_back_what_they_put_aside_over_the_price() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 0.0, &[(0, 10)], &[]),
+        Agent::new(1, 100.0, &[], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 0.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    for todo_transaction in [
+        order(0, 4.0, TradeAction::Sell, 10),
+        order(1, 5.009, TradeAction::Buy, 10),
+    ] {
+        market
+            .trade(false, &todo_transaction, &mut agents, &mut companies, 0.0)
+            .unwrap();
+    }
+    assert_eq!(agents.holdings.get(1, InstrumentId::Equity(0)), 10);
+    assert_eq!(agents.balances.get(0).unwrap(), Money::from(40.0));
+    assert_eq!(agents.balances.get(1).unwrap(), Money::from(60.0));
+}
+
+#[test]
+fn money_is_conserved_across_trades() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut agents = Agents::load(
+        &(0..20)
+            .map(|agent_id| Agent::new(agent_id, 10_000.0 / 3.0, &[(0, 1_000)], &[]))
+            .collect::<Vec<_>>(),
+    );
+    let mut companies = Companies::load(&[Company::new(0, 0.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::with_fees(FeeSchedule {
+        maker: -0.0013,
+        taker: 0.0027,
+    });
+    let money = total_money(&agents, &mut market);
+    let shares = total_shares(&agents, &mut market);
+
+    for _ in 0..500 {
+        let mut orders = (0..10)
+            .map(|_| {
+                let action = if rng.gen_bool(0.5) {
+                    TradeAction::Buy
+                } else {
+                    TradeAction::Sell
+                };
+                order(
+                    rng.gen_range(0..20),
+                    rng.gen_range(7.0..13.0),
+                    action,
+                    rng.gen_range(1..50),
+                )
+            })
+            .collect::<Vec<_>>();
+        market
+            .rand_do_trade(&mut rng, &mut agents, &mut companies, &mut orders)
+            .unwrap();
+        let mut expired = HashMap::new();
+        market.tick_failures(&mut expired);
+        agents.refund_failed_offers(&expired).unwrap();
+
+        assert_eq!(total_money(&agents, &mut market), money);
+        assert_eq!(total_shares(&agents, &mut market), shares);
+    }
+    assert!(market.fees_collected > Money::ZERO);
+}
+
+#[test]
+fn money_is_conserved_across_lots_bonds_and_exchanges() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut agents = Agents::load(
+        &(0..20)
+            .map(|agent_id| Agent::new(agent_id, 100_000.0 / 3.0, &[(0, 1_000)], &[]))
+            .collect::<Vec<_>>(),
+    );
+    let mut companies = Companies::load(&[
+        Company::new(0, 100_000.0 / 3.0, 1_000.0, 0.0, (0.0, 0, 0)),
+        Company::new(1, 0.0, 0.0, 0.0, (10.0, 4, 10)),
+    ]);
+    let currencies = Currencies::new(&[("USD", 1.0)]);
+    let mut exchanges = Exchanges::new(&[
+        (
+            FeeSchedule {
+                maker: -0.0013,
+                taker: 0.0027,
+            },
+            0,
+            0,
+        ),
+        (
+            FeeSchedule {
+                maker: 0.001,
+                taker: 0.002,
+            },
+            1,
+            0,
+        ),
+    ]);
+    exchanges.list(1, 0);
+    let mut bonds = Bonds::new();
+    let money = total_money_everywhere(&agents, &companies, &exchanges, &currencies);
+
+    let buyer_ids = agents.iter().collect::<Vec<_>>();
+    let bond_id = bonds.issue(
+        &mut agents,
+        &mut companies,
+        &Economy::new(),
+        0,
+        10_000.0,
+        &buyer_ids,
+    );
+    assert_eq!(bond_id, Some(0));
+    assert_eq!(
+        total_money_everywhere(&agents, &companies, &exchanges, &currencies),
+        money
+    );
+
+    for tick in 0..420 {
+        agents.current_tick = tick;
+        for _ in 0..10 {
+            let action = if rng.gen_bool(0.5) {
+                TradeAction::Buy
+            } else {
+                TradeAction::Sell
+            };
+            let order = order(
+                rng.gen_range(0..20),
+                rng.gen_range(7.0..13.0),
+                action,
+                rng.gen_range(1..50),
+            );
+            // both exchanges get their share, whatever the router would pick
+            exchanges.send(rng.gen_range(0..2), order, tick);
+        }
+        exchanges
+            .rand_do_trade(&mut rng, &mut agents, &mut companies, &currencies, tick)
+            .unwrap();
+        let mut expired = HashMap::new();
+        exchanges
+            .tick_failures(&mut agents, &currencies, &mut expired)
+            .unwrap();
+        agents.refund_failed_offers(&expired).unwrap();
+
+        if tick == 100 {
+            for agent_id in 0..6 {
+                // the ones spent out of cash just miss the offering
+                let _ = companies.lots[1].add_bet_and_update_agent(&mut agents, agent_id, 1);
+            }
+        }
+        if tick == 200 {
+            companies.finalize_lots(1, &mut agents, &mut rng).unwrap();
+        }
+        if tick % TICKS_PER_COUPON == 0 {
+            let defaulted = exchanges
+                .primary_mut()
+                .pay_bonds(&mut agents, &mut companies, &mut bonds)
+                .unwrap();
+            assert!(defaulted.is_empty());
+        }
+
+        assert_eq!(
+            total_money_everywhere(&agents, &companies, &exchanges, &currencies),
+            money
+        );
+    }
+    assert!(!bonds.bonds[0].is_active());
+    assert!(companies.balances[1] > Money::ZERO);
+    assert!(exchanges
+        .fees_collected()
+        .iter()
+        .all(|&fees| fees != Money::ZERO));
+}
+
+#[test]
+fn failed_offers_are_retried_at_an_exact_price() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut agents = Agents::load(&[
+        Agent::new(0, 1_000.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 10)], &[]),
+    ]);
+    agents.add_failed_offer(
+        InstrumentId::Equity(0),
+        0,
+        Money::from(10.03),
+        &TradeAction::Buy,
+    );
+    agents.add_failed_offer(
+        InstrumentId::Equity(0),
+        1,
+        Money::from(10.03),
+        &TradeAction::Sell,
+    );
+
+    let mut retried = Vec::new();
+    while retried.len() < 2 {
+        retried.clear();
+        agents
+            .try_failed_offers(&mut rng, &mut retried, &Trade::new(1))
+            .unwrap();
+    }
+    retried.sort_by_key(|order| order.agent_id);
+    // the buyer bids a quarter more, the seller asks a quarter less
+    assert_eq!(retried[0].strike_price, Money::from(12.5375));
+    assert_eq!(retried[1].strike_price, Money::from(7.5225));
+}
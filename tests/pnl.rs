@@ -0,0 +1,106 @@
+use stocks::{
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    etf::Etfs,
+    instrument::InstrumentId,
+    market::Market,
+    money::Money,
+    trade_house::{Trade, TradeAction},
+    transaction::{TodoTransaction, Transaction},
+};
+
+fn order(agent_id: u64, strike_price: f64, action: TradeAction, number: u64) -> TodoTransaction {
+    TodoTransaction {
+        agent_id,
+        instrument_id: InstrumentId::Equity(0),
+        strike_price: Money::from(strike_price),
+        action,
+        trade: Trade::new(number),
+    }
+}
+
+#[test]
+fn realized_and_unrealized_pnl() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 1_000.0, &[], &[]),
+        Agent::new(1, 1_000.0, &[], &[]),
+        Agent::new(2, 1_000.0, &[], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 10_000.0, 1_000.0, 0.0, (0.0, 0, 0))]);
+    agents.receive_shares(1, InstrumentId::Equity(0), 100, 2.0);
+    agents.receive_shares(2, InstrumentId::Equity(0), 100, 4.0);
+
+    // agent 1 sells half of its shares to agent 0 at a profit
+    agents.holdings.pop(1, InstrumentId::Equity(0), 50).unwrap();
+    agents
+        .exchange_assets_from_transaction(&Transaction::new(0, 1, InstrumentId::Equity(0), 50, 3.0))
+        .unwrap();
+    assert_eq!(agents.realized_pnl[1], Money::from(50.0));
+    assert_eq!(
+        agents
+            .positions
+            .get(1, InstrumentId::Equity(0))
+            .unwrap()
+            .cost_basis,
+        Money::from(2.0)
+    );
+
+    companies.market_values[0].current_price = 5.0;
+    assert_eq!(agents.unrealized_pnl(0, &companies), Money::from(100.0));
+    assert_eq!(agents.unrealized_pnl(1, &companies), Money::from(150.0));
+    assert_eq!(agents.unrealized_pnl(2, &companies), Money::from(100.0));
+
+    // agent 0: 100 / 150, agent 1: 200 / 200, agent 2: 100 / 400
+    let ranking = agents.rank_by_returns(&companies);
+    assert_eq!(
+        ranking.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+        vec![1, 0, 2]
+    );
+    assert_eq!(ranking[0].1, 1.0);
+}
+
+#[test]
+fn delisting_realizes_loss() {
+    let mut agents = Agents::load(&[Agent::new(0, 0.0, &[], &[])]);
+    let mut companies = Companies::load(&[Company::new(0, 0.0, 1_000.0, 0.0, (0.0, 0, 0))]);
+    agents.receive_shares(0, InstrumentId::Equity(0), 100, 2.0);
+    companies.delist(0, &mut agents, &mut Etfs::new()).unwrap();
+    assert_eq!(agents.realized_pnl[0], Money::from(-200.0));
+    assert_eq!(agents.returns(0, &companies), -1.0);
+}
+
+#[test]
+fn offer_filled_in_parts_realizes_every_share() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 1_000.0, &[], &[]),
+        Agent::new(1, 0.0, &[], &[]),
+        Agent::new(2, 1_000.0, &[], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 0.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    agents.receive_shares(1, InstrumentId::Equity(0), 10, 2.0);
+
+    for todo_transaction in [
+        order(1, 3.0, TradeAction::Sell, 10),
+        order(0, 3.0, TradeAction::Buy, 4),
+    ] {
+        market
+            .trade(false, &todo_transaction, &mut agents, &mut companies, 0.0)
+            .unwrap();
+    }
+    assert_eq!(agents.realized_pnl[1], Money::from(4.0));
+    market
+        .trade(
+            false,
+            &order(2, 3.0, TradeAction::Buy, 6),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+    assert_eq!(agents.realized_pnl[1], Money::from(10.0));
+    assert!(agents.positions.get(1, InstrumentId::Equity(0)).is_none());
+    assert_eq!(agents.closed_holding_periods.len(), 1);
+}
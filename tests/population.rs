@@ -0,0 +1,128 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::collections::HashMap;
+use stocks::{
+    entities::{
+        agents::{
+            Agent, Agents, BROKE_BALANCE_THRESHOLD, CONSUMPTION_RATE, LIQUIDATION_DISCOUNT,
+            SUBSISTENCE_COST,
+        },
+        companies::{Companies, Company},
+    },
+    instrument::InstrumentId,
+    market::Market,
+    money::Money,
+    social::{GraphKind, SocialNetwork},
+    trade_house::{Trade, TradeAction},
+    transaction::TodoTransaction,
+};
+
+#[test]
+fn agents_join_mid_run() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut agents = Agents::load(&[Agent::new(0, 1_000.0, &[], &[])]);
+    agents
+        .introduce_new_agents(|_, _| 2, &mut vec![10.0, 20.0], 2, 3)
+        .unwrap();
+    assert_eq!(agents.num_of_agents, 3);
+    assert_eq!(agents.preferences.0[2].data.len(), 6);
+    assert!(agents.is_active(2));
+    // every per-agent field has grown with the population
+    let saved = agents.save().unwrap();
+    assert_eq!(saved.len(), 3);
+    assert_eq!(saved[2].balance, Money::from(20.0));
+
+    let mut network =
+        SocialNetwork::rand_new(&mut rng, GraphKind::ScaleFree { edges_per_agent: 1 }, 1);
+    network.rand_add_agents(
+        &mut rng,
+        GraphKind::ScaleFree { edges_per_agent: 1 },
+        &[1, 2],
+    );
+    assert_eq!(network.neighbors.len(), 3);
+    assert!(network.degree(2) >= 1);
+}
+
+#[test]
+fn broke_agents_leave_and_liquidate() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 10_000.0, &[], &[]),
+        Agent::new(
+            1,
+            SUBSISTENCE_COST,
+            &[(0, 100)],
+            &[(0, (0, TradeAction::Buy))],
+        ),
+    ]);
+    agents.assign_incomes(|_| Money::from(1_000.0), &[0]);
+    // the shares of agent 1 are worthless
+    let mut companies = Companies::load(&[Company::new(0, 10_000.0, 1_000.0, 0.0, (0.0, 0, 0))]);
+    let broke_agents = agents.earn_and_consume(&companies, &HashMap::new());
+    let balance = Money::from(10_000.0 + 1_000.0);
+    assert_eq!(
+        agents.balances.get(0).unwrap(),
+        balance - SUBSISTENCE_COST - balance.scale(CONSUMPTION_RATE)
+    );
+    assert_eq!(broke_agents, vec![1]);
+    assert!(agents.balances.get(1).unwrap() < BROKE_BALANCE_THRESHOLD);
+
+    agents.leave(1);
+    assert!(!agents.is_active(1));
+    assert!(agents.preferences.0[1].data.is_empty());
+
+    companies.market_values[0].current_price = 100.0;
+    let orders = agents.liquidation_orders(&companies);
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0].agent_id, 1);
+    assert_eq!(orders[0].action, TradeAction::Sell);
+    assert_eq!(orders[0].trade.number_of_shares, 100);
+    assert_eq!(
+        orders[0].strike_price,
+        Money::from(100.0 * (1.0 - LIQUIDATION_DISCOUNT))
+    );
+}
+
+#[test]
+fn invested_agents_are_not_broke() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 0.0, &[(0, 100)], &[]),
+        Agent::new(1, 1_000.0, &[], &[]),
+        Agent::new(2, 0.0, &[(0, 100)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 10_000.0, 1_000.0, 0.0, (0.0, 0, 0))]);
+    companies.market_values[0].current_price = 10.0;
+    let mut market = Market::new();
+    // agent 1 puts its cash aside to buy, agent 2 offers its shares
+    for (agent_id, action, number_of_shares) in
+        [(1, TradeAction::Buy, 100), (2, TradeAction::Sell, 100)]
+    {
+        let todo_transaction = TodoTransaction {
+            agent_id,
+            instrument_id: InstrumentId::Equity(0),
+            strike_price: if action == TradeAction::Buy {
+                Money::from(10.0)
+            } else {
+                Money::from(20.0)
+            },
+            action,
+            trade: Trade::new(number_of_shares),
+        };
+        market
+            .trade(false, &todo_transaction, &mut agents, &mut companies, 0.0)
+            .unwrap();
+    }
+    assert_eq!(agents.balances.get(1).unwrap(), Money::ZERO);
+    assert_eq!(agents.holdings.get(2, InstrumentId::Equity(0)), 0);
+
+    let escrowed_cash = market.house.escrowed_cash();
+    assert_eq!(escrowed_cash[&1], Money::from(1_000.0));
+    assert!(agents
+        .earn_and_consume(&companies, &escrowed_cash)
+        .is_empty());
+    // without a price the shares are worth nothing
+    companies.market_values[0].current_price = 0.0;
+    assert_eq!(
+        agents.earn_and_consume(&companies, &escrowed_cash),
+        vec![0, 2]
+    );
+}
@@ -0,0 +1,129 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use stocks::{
+    economy::Economy,
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    instrument::InstrumentId,
+    market::Market,
+    money::Money,
+    scenario::{Scenario, ScenarioEvent, Target},
+    trade_house::{Trade, TradeAction},
+    transaction::TodoTransaction,
+    ScenarioError,
+};
+
+#[test]
+fn parse_scenario() {
+    let scenario = Scenario::load("tests/scenarios/panic.scenario").unwrap();
+    assert_eq!(scenario.events().len(), 7);
+    assert_eq!(
+        scenario.events()[0].event,
+        ScenarioEvent::News {
+            company: Target::One(0),
+            profit_percent: -90.0
+        }
+    );
+    assert!(matches!(
+        Scenario::parse("10 news 1\n"),
+        Err(ScenarioError::InvalidLine(1))
+    ));
+    assert!(matches!(
+        Scenario::parse("1 list\n10 halt 0 0\n"),
+        Err(ScenarioError::InvalidLine(2))
+    ));
+}
+
+#[test]
+fn empty_halt_is_ignored() {
+    let mut market = Market::new();
+    market.halt(Some(0), 0);
+    market.halt(None, 0);
+    assert!(!market.is_halted(0));
+    market.tick_halts();
+    assert!(!market.is_halted(0));
+
+    market.halt(Some(0), 1);
+    assert!(market.is_halted(0));
+    market.tick_halts();
+    assert!(!market.is_halted(0));
+}
+
+#[test]
+fn failed_event_does_not_stop_the_tick() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut scenario = Scenario::parse("1 cash 5 100\n1 cash 0 100\n1 rate 0.08\n").unwrap();
+    let mut companies = Companies::load(&[]);
+    let mut agents = Agents::load(&[Agent::new(0, 0.0, &[], &[])]);
+    let mut market = Market::new();
+    let mut economy = Economy::new();
+    scenario
+        .apply(
+            1,
+            &mut companies,
+            &mut agents,
+            &mut market,
+            &mut economy,
+            &mut rng,
+        )
+        .unwrap();
+    assert_eq!(agents.balances.get(0).unwrap(), Money::from(100.0));
+    assert_eq!(economy.interest_rate, 0.08);
+    assert!(scenario.is_finished());
+}
+
+#[test]
+fn apply_scenario() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut scenario = Scenario::load("tests/scenarios/panic.scenario").unwrap();
+    let mut companies = Companies::load(&[
+        Company::new(0, 10_000.0, 1_000.0, 0.0, (0.0, 0, 0)),
+        Company::new(1, 10_000.0, 1_000.0, 0.0, (0.0, 0, 0)),
+    ]);
+    let mut agents = Agents::load(&[Agent::new(0, 100.0, &[], &[]), Agent::new(1, 0.0, &[], &[])]);
+    let mut market = Market::new();
+    let mut economy = Economy::new();
+    let mut apply = |tick: u64,
+                     companies: &mut Companies,
+                     agents: &mut Agents,
+                     market: &mut Market,
+                     economy: &mut Economy| {
+        market.tick_halts();
+        scenario
+            .apply(tick, companies, agents, market, economy, &mut rng)
+            .unwrap();
+    };
+
+    apply(1, &mut companies, &mut agents, &mut market, &mut economy);
+    assert_eq!(companies.news[0], -90.0);
+    assert_eq!(companies.balances[0], Money::from(10_000.0 - 900.0));
+    assert_eq!(agents.balances.get(1).unwrap(), Money::from(500.0));
+
+    apply(2, &mut companies, &mut agents, &mut market, &mut economy);
+    assert_eq!(companies.company_hype(1), 80.0);
+
+    apply(3, &mut companies, &mut agents, &mut market, &mut economy);
+    assert_eq!(companies.news[1], -50.0);
+    let buy = TodoTransaction {
+        agent_id: 0,
+        instrument_id: InstrumentId::Equity(0),
+        strike_price: Money::from(1.0),
+        action: TradeAction::Buy,
+        trade: Trade::new(10),
+    };
+    assert!(market
+        .trade(false, &buy, &mut agents, &mut companies, 0.0)
+        .is_err());
+    apply(4, &mut companies, &mut agents, &mut market, &mut economy);
+    apply(5, &mut companies, &mut agents, &mut market, &mut economy);
+    assert!(market
+        .trade(false, &buy, &mut agents, &mut companies, 0.0)
+        .is_ok());
+    assert_eq!(economy.interest_rate, 0.08);
+
+    apply(6, &mut companies, &mut agents, &mut market, &mut economy);
+    assert_eq!(companies.num_of_companies, 3);
+    assert!(scenario.is_finished());
+}
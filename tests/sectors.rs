@@ -0,0 +1,108 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use stocks::{
+    economy::Economy,
+    entities::{
+        agents::Agents,
+        companies::{Companies, Company, HYPE_DECAY_RATE, HYPE_LIFETIME, HYPE_PREFERENCE_WEIGHT},
+    },
+    trade_house::TradeAction,
+};
+
+fn company_in_sector(id: u64, sector: u64) -> Company {
+    let mut company = Company::new(id, 100_000.0, 100.0, 0.0, (0.0, 0, 0));
+    company.sector = sector;
+    company
+}
+
+fn correlation(a: &[f64], b: &[f64]) -> f64 {
+    let mean = |x: &[f64]| x.iter().sum::<f64>() / x.len() as f64;
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let covariance = a
+        .iter()
+        .zip(b)
+        .map(|(a, b)| (a - mean_a) * (b - mean_b))
+        .sum::<f64>();
+    let deviation = |x: &[f64], mean: f64| x.iter().map(|x| (x - mean).powi(2)).sum::<f64>().sqrt();
+    covariance / (deviation(a, mean_a) * deviation(b, mean_b))
+}
+
+#[test]
+fn hype_is_tracked_per_sector() {
+    let mut companies = Companies::load(&[
+        company_in_sector(0, 0),
+        company_in_sector(1, 0),
+        company_in_sector(2, 1),
+    ]);
+    companies.send_hype(&mut vec![(0, 80.0), (1, 90.0), (2, -50.0)]);
+
+    assert_eq!(companies.company_hype(0), 80.0);
+    assert_eq!(companies.company_hype(1), 90.0);
+    assert_eq!(companies.company_hype(2), -50.0);
+    assert_eq!(companies.sector_hype(0), 85.0);
+    assert_eq!(companies.sector_hype(1), -50.0);
+    assert_eq!(companies.sector_hype(2), 0.0);
+}
+
+#[test]
+fn hype_decays_and_draws_agents() {
+    let mut companies = Companies::load(&[company_in_sector(0, 0), company_in_sector(1, 0)]);
+    companies.send_hype(&mut vec![(0, 100.0)]);
+    let preferences = companies.generate_preferences_from_news(&mut rand::thread_rng());
+    assert_eq!(
+        preferences
+            .iter()
+            .filter(|(company_id, action)| *company_id == 0 && *action == TradeAction::Buy)
+            .count(),
+        (100.0 * HYPE_PREFERENCE_WEIGHT) as usize
+    );
+
+    companies.tick_hype();
+    assert_eq!(companies.company_hype(0), 100.0 * HYPE_DECAY_RATE);
+    for _ in 1..HYPE_LIFETIME {
+        companies.tick_hype();
+    }
+    assert_eq!(companies.company_hype(0), 0.0);
+    assert!(companies
+        .generate_preferences_from_news(&mut rand::thread_rng())
+        .is_empty());
+}
+
+#[test]
+fn sector_news_moves_its_companies_together() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut companies = Companies::load(&[
+        company_in_sector(0, 0),
+        company_in_sector(1, 0),
+        company_in_sector(2, 1),
+    ]);
+    for id in companies.iter() {
+        companies.market_values[id as usize].current_price = 100.0;
+    }
+    let mut agents = Agents::new();
+    let economy = Economy::new();
+
+    let mut price_moves = vec![Vec::new(); 3];
+    for _ in 0..500 {
+        let prices = companies
+            .iter()
+            .map(|id| companies.get_current_price(id).unwrap())
+            .collect::<Vec<_>>();
+        companies.rand_release_news(&mut agents, &economy, &mut rng);
+        for (id, price) in prices.into_iter().enumerate() {
+            price_moves[id].push((companies.get_current_price(id as u64).unwrap() / price).ln());
+        }
+    }
+    let same_sector = correlation(&price_moves[0], &price_moves[1]);
+    let cross_sector = correlation(&price_moves[0], &price_moves[2]);
+    assert!(
+        same_sector > 0.3,
+        "same sector correlation: {}",
+        same_sector
+    );
+    assert!(
+        cross_sector.abs() < 0.15,
+        "cross sector correlation: {}",
+        cross_sector
+    );
+}
@@ -0,0 +1,61 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use stocks::{
+    entities::agents::{Agent, Agents},
+    social::{GraphKind, SocialNetwork},
+    trade_house::TradeAction,
+};
+
+#[test]
+fn graph_shapes() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let ring = SocialNetwork::rand_new(
+        &mut rng,
+        GraphKind::SmallWorld {
+            neighbors: 4,
+            rewiring_probability: 0.0,
+        },
+        100,
+    );
+    assert!((0..100).all(|agent_id| ring.degree(agent_id) == 4));
+
+    let random = SocialNetwork::rand_new(&mut rng, GraphKind::Random { average_degree: 6 }, 1_000);
+    let total_degree = (0..1_000)
+        .map(|agent_id| random.degree(agent_id))
+        .sum::<usize>();
+    assert!((5_000..=6_000).contains(&total_degree));
+
+    let scale_free =
+        SocialNetwork::rand_new(&mut rng, GraphKind::ScaleFree { edges_per_agent: 2 }, 1_000);
+    let hub = (0..1_000)
+        .max_by_key(|agent_id| scale_free.degree(*agent_id))
+        .unwrap();
+    assert!(scale_free.degree(hub) > 20);
+    assert!(scale_free.is_influencer(hub));
+    assert_eq!(scale_free.influencers.iter().filter(|x| **x).count(), 10);
+}
+
+#[test]
+fn rumors_spread_through_neighbors() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut agents = Agents::load(&[
+        Agent::new(0, 0.0, &[], &[(0, (7, TradeAction::Sell))]),
+        Agent::new(1, 0.0, &[], &[]),
+        Agent::new(2, 0.0, &[], &[]),
+    ]);
+    let mut network = SocialNetwork::new(3);
+    network.connect(0, 1);
+    network.connect(1, 2);
+
+    let mut ticks = 0;
+    while agents.preferences.0[2].data.is_empty() {
+        network.rand_spread(&mut rng, &mut agents);
+        ticks += 1;
+    }
+    // the rumor needs to go through agent 1 first
+    assert!(ticks >= 2);
+    assert!(agents.preferences.0[2]
+        .data
+        .iter()
+        .all(|entry| *entry == (7, TradeAction::Sell)));
+}
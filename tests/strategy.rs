@@ -0,0 +1,107 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use stocks::{
+    economy::Economy,
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    instrument::InstrumentId,
+    market::Market,
+    money::Money,
+    strategy::{
+        intrinsic_value, value_investor_action, value_investor_decision, AgentStrategy,
+        MARGIN_OF_SAFETY,
+    },
+    trade_house::{Trade, TradeAction},
+    transaction::TodoTransaction,
+};
+
+#[test]
+fn discounted_cash_flow_value() {
+    let economy = Economy::new();
+    let mut companies = Companies::load(&[Company::new(0, 10_000.0, 1_000.0, 0.0, (0.0, 0, 0))]);
+    assert!(intrinsic_value(&companies, 0, &economy).is_none());
+
+    companies.shares_outstanding[0] = 100;
+    let value = intrinsic_value(&companies, 0, &economy).unwrap();
+    // at least the cash, at most the undiscounted profits
+    assert!(value > 100.0);
+    assert!(value < 100.0 + 400.0);
+
+    // higher rates make the future profits worth less
+    let mut tight_economy = Economy::new();
+    tight_economy.interest_rate = 0.2;
+    assert!(intrinsic_value(&companies, 0, &tight_economy).unwrap() < value);
+}
+
+#[test]
+fn margin_of_safety() {
+    let value = 100.0;
+    let cheap = value * (1.0 - MARGIN_OF_SAFETY) - 1.0;
+    let expensive = value * (1.0 + MARGIN_OF_SAFETY) + 1.0;
+    assert_eq!(
+        value_investor_action(cheap, value, 0),
+        Some(TradeAction::Buy)
+    );
+    assert_eq!(value_investor_action(value, value, 10), None);
+    assert_eq!(value_investor_action(expensive, value, 0), None);
+    assert_eq!(
+        value_investor_action(expensive, value, 10),
+        Some(TradeAction::Sell)
+    );
+}
+
+#[test]
+fn value_investors_trade_a_fresh_market() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut companies = Companies::rand(10, 0, &mut rng);
+    let economy = Economy::new();
+    let mut agents = Agents::load(&[Agent::new(0, 1_000_000.0, &[], &[])]);
+    assert!(companies
+        .iter()
+        .all(|company_id| intrinsic_value(&companies, company_id, &economy).is_some()));
+
+    let company_id = companies
+        .iter()
+        .find(|&company_id| {
+            value_investor_decision(&companies, company_id, &economy, 0) == Some(TradeAction::Buy)
+        })
+        .expect("a value investor buys some company of a fresh market");
+    let order = TodoTransaction {
+        agent_id: 0,
+        instrument_id: InstrumentId::Equity(company_id),
+        strike_price: Money::from(companies.get_current_price(company_id).unwrap()),
+        action: TradeAction::Buy,
+        trade: Trade::new(10),
+    };
+    let mut market = Market::new();
+    market
+        .trade(false, &order, &mut agents, &mut companies, 0.0)
+        .unwrap();
+    let offers = market
+        .house
+        .get_mut_trade_offers(InstrumentId::Equity(company_id));
+    assert_eq!(offers.buyer_offers.len(), 1);
+    assert_eq!(offers.buyer_offers[0].offerer_id, 0);
+}
+
+#[test]
+fn strategies_survive_saving() {
+    let mut agents = Agents::load(&[Agent::new(0, 0.0, &[], &[]), Agent::new(1, 0.0, &[], &[])]);
+    agents.assign_strategies(
+        |agent_id| {
+            if agent_id == 1 {
+                AgentStrategy::Value
+            } else {
+                AgentStrategy::Sentiment
+            }
+        },
+        &[0, 1],
+    );
+    let agents = Agents::load(&agents.save().unwrap());
+    assert_eq!(
+        agents.strategies,
+        vec![AgentStrategy::Sentiment, AgentStrategy::Value]
+    );
+}
@@ -3,7 +3,9 @@ use stocks::{
         agents::{Agent, Agents},
         companies::{Companies, Company},
     },
+    instrument::InstrumentId,
     market::Market,
+    money::Money,
     trade_house::{Trade, TradeAction},
     transaction::TodoTransaction,
     OFFER_LIFETIME,
@@ -19,8 +21,8 @@ fn getting_added_to_offers_list() {
             false,
             &TodoTransaction {
                 agent_id: 0,
-                company_id: 0,
-                strike_price: 1.0,
+                instrument_id: InstrumentId::Equity(0),
+                strike_price: Money::from(1.0),
                 action: TradeAction::Buy,
                 trade: Trade::new(10),
             },
@@ -29,7 +31,14 @@ fn getting_added_to_offers_list() {
             0.0,
         )
         .unwrap();
-    assert_eq!(market.house.get_mut_trade_offers(0).buyer_offers.len(), 1);
+    assert_eq!(
+        market
+            .house
+            .get_mut_trade_offers(InstrumentId::Equity(0))
+            .buyer_offers
+            .len(),
+        1
+    );
 }
 
 #[test]
@@ -45,8 +54,8 @@ fn offer_resolving() {
             false,
             &TodoTransaction {
                 agent_id: 0,
-                company_id: 0,
-                strike_price: 1.0,
+                instrument_id: InstrumentId::Equity(0),
+                strike_price: Money::from(1.0),
                 action: TradeAction::Buy,
                 trade: Trade::new(100),
             },
@@ -60,8 +69,8 @@ fn offer_resolving() {
             false,
             &TodoTransaction {
                 agent_id: 1,
-                company_id: 0,
-                strike_price: 1.0,
+                instrument_id: InstrumentId::Equity(0),
+                strike_price: Money::from(1.0),
                 action: TradeAction::Sell,
                 trade: Trade::new(50),
             },
@@ -72,15 +81,29 @@ fn offer_resolving() {
         .unwrap();
 
     // 50 remain
-    assert_eq!(market.house.get_mut_trade_offers(0).buyer_offers.len(), 1);
-    assert_eq!(market.house.get_mut_trade_offers(0).seller_offers.len(), 0);
+    assert_eq!(
+        market
+            .house
+            .get_mut_trade_offers(InstrumentId::Equity(0))
+            .buyer_offers
+            .len(),
+        1
+    );
+    assert_eq!(
+        market
+            .house
+            .get_mut_trade_offers(InstrumentId::Equity(0))
+            .seller_offers
+            .len(),
+        0
+    );
 
     // to put the trade up, the agent needs to give that money
-    assert_eq!(agents.balances.get(0).unwrap(), 0.0);
-    assert_eq!(agents.balances.get(1).unwrap(), 50.0);
+    assert_eq!(agents.balances.get(0).unwrap(), Money::ZERO);
+    assert_eq!(agents.balances.get(1).unwrap(), Money::from(50.0));
 
-    assert_eq!(agents.holdings.get(0, 0), 50);
-    assert_eq!(agents.holdings.get(1, 0), 50);
+    assert_eq!(agents.holdings.get(0, InstrumentId::Equity(0)), 50);
+    assert_eq!(agents.holdings.get(1, InstrumentId::Equity(0)), 50);
 }
 
 #[test]
@@ -93,8 +116,8 @@ fn offer_refund() {
             false,
             &TodoTransaction {
                 agent_id: 0,
-                company_id: 0,
-                strike_price: 1.0,
+                instrument_id: InstrumentId::Equity(0),
+                strike_price: Money::from(1.0),
                 action: TradeAction::Buy,
                 trade: Trade::new(100),
             },
@@ -108,12 +131,15 @@ fn offer_refund() {
         market.house.tick();
     }
     let mut tick_data = market.house.tick();
-    let failed_offers = tick_data.0.get_mut(&0).unwrap();
+    let failed_offers = tick_data
+        .failed_trade_offer
+        .get_mut(&InstrumentId::Equity(0))
+        .unwrap();
     let failed_offer_data = failed_offers.pop();
     let failed_offer = failed_offer_data.unwrap();
 
     assert_eq!(failed_offer.0.data.number_of_shares, 100);
     assert_eq!(failed_offer.0.offerer_id, 0);
-    assert_eq!(failed_offer.0.strike_price, 1.0);
+    assert_eq!(failed_offer.0.strike_price, Money::from(1.0));
     assert_eq!(failed_offer.1, TradeAction::Buy);
 }
@@ -0,0 +1,87 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use stocks::{
+    currency::Currencies,
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    instrument::InstrumentId,
+    metrics::{gini, wealth_percentiles},
+    money::Money,
+    wealth::WealthDistribution,
+};
+
+fn home_only() -> Currencies {
+    Currencies::new(&[("USD", 1.0)])
+}
+
+#[test]
+fn distributions() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let empirical = WealthDistribution::load_empirical("tests/data/wealth.csv").unwrap();
+    assert_eq!(
+        empirical,
+        WealthDistribution::Empirical(vec![100.0, 200.0, 300.0])
+    );
+    assert!((0..100).all(|_| [100.0, 200.0, 300.0].contains(&empirical.sample(&mut rng))));
+    assert!(WealthDistribution::load_empirical("tests/data/missing.csv").is_err());
+
+    let pareto = WealthDistribution::Pareto {
+        scale: 1_000.0,
+        shape: 1.5,
+    };
+    let samples = (0..10_000)
+        .map(|_| pareto.sample(&mut rng))
+        .collect::<Vec<_>>();
+    assert!(samples.iter().all(|sample| *sample >= 1_000.0));
+    // heavier tailed than the uniform draws
+    let uniform = WealthDistribution::Uniform {
+        min: 1_000.0,
+        max: 10_000.0,
+    };
+    let uniform_samples = (0..10_000)
+        .map(|_| uniform.sample(&mut rng))
+        .collect::<Vec<_>>();
+    assert!(gini(&samples) > gini(&uniform_samples));
+}
+
+#[test]
+fn inequality_reporting() {
+    assert_eq!(gini(&[5.0, 5.0, 5.0, 5.0]), 0.0);
+    assert_eq!(gini(&[0.0, 0.0, 0.0, 10.0]), 0.75);
+    assert_eq!(
+        wealth_percentiles(&(1..=100).map(|x| x as f64).collect::<Vec<_>>()),
+        vec![11.0, 26.0, 51.0, 90.0, 99.0]
+    );
+}
+
+#[test]
+fn initial_holdings_are_marked_to_market() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut agents = Agents::load(&[Agent::new(0, 100.0, &[], &[])]);
+    let mut companies = Companies::load(&[Company::new(0, 10_000.0, 1_000.0, 0.0, (0.0, 0, 0))]);
+    companies.market_values[0].current_price = 10.0;
+    agents.rand_give_initial_holdings(
+        &mut rng,
+        &mut companies,
+        &WealthDistribution::Uniform {
+            min: 1_000.0,
+            max: 1_000.0,
+        },
+        &[0],
+    );
+    assert_eq!(agents.holdings.get(0, InstrumentId::Equity(0)), 100);
+    assert_eq!(companies.shares_outstanding[0], 100);
+    assert_eq!(agents.wealth(&companies, &home_only()), vec![1_100.0]);
+}
+
+#[test]
+fn foreign_cash_counts_at_the_rate() {
+    let mut agents = Agents::load(&[Agent::new(0, 100.0, &[], &[]), Agent::new(1, 0.0, &[], &[])]);
+    let companies = Companies::load(&[]);
+    let currencies = Currencies::new(&[("USD", 1.0), ("EUR", 2.0), ("GBP", 1.5)]);
+    agents.balances.add_in(0, 1, Money::from(50.0)).unwrap();
+    agents.balances.add_in(1, 2, Money::from(10.0)).unwrap();
+    assert_eq!(agents.wealth(&companies, &currencies), vec![200.0, 15.0]);
+}